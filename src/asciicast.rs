@@ -1,10 +1,12 @@
-use serde::Deserialize;
+use flate2::bufread::GzDecoder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Display;
-use std::io::BufRead;
+use std::io::{BufRead, BufReader, Write};
 
 use crate::theme::Theme;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct V2Theme {
     fg: String,
     bg: String,
@@ -13,16 +15,45 @@ pub struct V2Theme {
 
 #[derive(Deserialize)]
 pub struct V2Header {
+    pub version: Option<u8>,
     pub width: usize,
     pub height: usize,
+    pub timestamp: Option<f64>,
     pub idle_time_limit: Option<f64>,
     pub theme: Option<V2Theme>,
+    pub exit_code: Option<i32>,
+    pub title: Option<String>,
+    pub env: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[derive(Serialize)]
+struct V2HeaderOut<'a> {
+    version: u8,
+    width: usize,
+    height: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idle_time_limit: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    theme: Option<&'a V2Theme>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env: Option<&'a HashMap<String, serde_json::Value>>,
 }
 
 pub struct Header {
+    pub version: Option<u8>,
     pub terminal_size: (usize, usize),
+    pub timestamp: Option<u64>,
     pub idle_time_limit: Option<f64>,
     pub theme: Option<Theme>,
+    pub exit_code: Option<i32>,
+    pub title: Option<String>,
+    pub env: Option<HashMap<String, serde_json::Value>>,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -38,6 +69,8 @@ pub struct Event {
     pub data: String,
 }
 
+// the details are only shown through Debug
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum Error {
     Io(std::io::Error),
@@ -74,14 +107,46 @@ impl TryInto<Header> for V2Header {
             None => None,
         };
 
+        // not part of the v2 spec, added by wrapper scripts which record the
+        // exit status of the command either at the top level or in env
+        let exit_code = self.exit_code.or_else(|| {
+            self.env
+                .as_ref()
+                .and_then(|env| env.get("EXIT_CODE"))
+                .and_then(|code| code.as_str())
+                .and_then(|code| code.parse().ok())
+        });
+
         Ok(Header {
+            version: self.version,
             terminal_size: (self.width, self.height),
+            timestamp: self.timestamp.map(|t| t as u64),
             idle_time_limit: self.idle_time_limit,
             theme,
+            exit_code,
+            title: self.title,
+            env: self.env,
         })
     }
 }
 
+impl From<&Theme> for V2Theme {
+    fn from(theme: &Theme) -> Self {
+        let hex = |c: rgb::RGB8| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b);
+
+        let palette = (0..16)
+            .map(|i| hex(theme.color(i)))
+            .collect::<Vec<_>>()
+            .join(":");
+
+        V2Theme {
+            fg: hex(theme.foreground),
+            bg: hex(theme.background),
+            palette,
+        }
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         std::fmt::Debug::fmt(&self, f)
@@ -102,12 +167,22 @@ impl From<serde_json::Error> for Error {
     }
 }
 
-pub fn open<R: BufRead>(
-    reader: R,
-) -> Result<(Header, impl Iterator<Item = Result<Event, Error>>), Error> {
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+pub fn open<'a, R: BufRead + 'a>(
+    mut reader: R,
+) -> Result<(Header, impl Iterator<Item = Result<Event, Error>> + 'a), Error> {
+    let reader: Box<dyn BufRead + 'a> = if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Box::new(BufReader::new(GzDecoder::new(reader)))
+    } else {
+        Box::new(reader)
+    };
+
     let mut lines = reader.lines();
     let first_line = lines.next().ok_or(Error::EmptyFile)??;
-    let v2_header: V2Header = serde_json::from_str(&first_line)?;
+    // UTF-8 BOM added by some Windows editors
+    let first_line = first_line.strip_prefix('\u{feff}').unwrap_or(&first_line);
+    let v2_header: V2Header = serde_json::from_str(first_line)?;
     let header: Header = v2_header.try_into()?;
 
     let events = lines
@@ -141,6 +216,105 @@ fn parse_event(line: String) -> Result<Event, Error> {
     })
 }
 
+pub fn write<W: Write>(
+    mut writer: W,
+    header: &Header,
+    events: impl Iterator<Item = Event>,
+) -> Result<(), Error> {
+    let theme = header.theme.as_ref().map(V2Theme::from);
+
+    let header = V2HeaderOut {
+        version: 2,
+        width: header.terminal_size.0,
+        height: header.terminal_size.1,
+        timestamp: header.timestamp,
+        idle_time_limit: header.idle_time_limit,
+        theme: theme.as_ref(),
+        exit_code: header.exit_code,
+        title: header.title.as_deref(),
+        env: header.env.as_ref(),
+    };
+
+    serde_json::to_writer(&mut writer, &header)?;
+    writeln!(writer)?;
+
+    for Event { time, type_, data } in events {
+        let type_ = match type_ {
+            EventType::Output => 'o',
+            EventType::Input => 'i',
+            EventType::Other(c) => c,
+        };
+
+        serde_json::to_writer(&mut writer, &(time, type_.to_string(), data))?;
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+/// Prefix of the OSC sequence standing in for a marker in the output stream,
+/// so markers go through the same timing adjustments as the output.
+pub const MARKER_OSC: &str = "\x1b]agg-marker;";
+
+/// Turns marker events into output events carrying the label in a private
+/// OSC sequence (ignored by the terminal emulator), when enabled.
+pub fn inline_markers(
+    events: impl Iterator<Item = Result<Event, Error>>,
+    enabled: bool,
+) -> impl Iterator<Item = Result<Event, Error>> {
+    events.map(move |e| match e {
+        Ok(Event {
+            type_: EventType::Other('m'),
+            time,
+            data,
+        }) if enabled => {
+            let label = data.replace(char::is_control, " ");
+
+            Ok(Event {
+                time,
+                type_: EventType::Output,
+                data: format!("{MARKER_OSC}{}\x07", label.trim()),
+            })
+        }
+
+        e => e,
+    })
+}
+
+/// Turns marker OSC sequences inlined by `inline_markers` back into marker
+/// events, splitting the output around them.
+pub fn outline_markers(stdout: impl Iterator<Item = (f64, String)>) -> impl Iterator<Item = Event> {
+    stdout.flat_map(|(time, data)| {
+        let mut events = Vec::new();
+        let mut rest = data.as_str();
+
+        let mut push = |type_, data: &str| {
+            if !data.is_empty() || type_ != EventType::Output {
+                events.push(Event {
+                    time,
+                    type_,
+                    data: data.to_owned(),
+                });
+            }
+        };
+
+        while let Some(start) = rest.find(MARKER_OSC) {
+            let label = &rest[start + MARKER_OSC.len()..];
+            let Some(len) = label.find('\x07') else {
+                break;
+            };
+
+            push(EventType::Output, &rest[..start]);
+            push(EventType::Other('m'), &label[..len]);
+            rest = &label[len + 1..];
+        }
+
+        push(EventType::Output, rest);
+
+        events
+    })
+}
+
 pub fn stdout(
     events: impl Iterator<Item = Result<Event, Error>>,
 ) -> impl Iterator<Item = (f64, String)> {
@@ -156,7 +330,61 @@ pub fn stdout(
 
 #[cfg(test)]
 mod tests {
-    use std::{fs::File, io::BufReader};
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+    use std::{fs, fs::File, io::BufReader};
+
+    #[test]
+    fn inline_markers() {
+        let events = [
+            (1.0, super::EventType::Output, "foo"),
+            (2.0, super::EventType::Other('m'), "Step 1: Install"),
+            (3.0, super::EventType::Other('m'), ""),
+        ]
+        .map(|(time, type_, data)| {
+            Ok(super::Event {
+                time,
+                type_,
+                data: data.to_owned(),
+            })
+        });
+
+        let stdout =
+            super::stdout(super::inline_markers(events.into_iter(), true)).collect::<Vec<_>>();
+
+        assert_eq!(
+            stdout,
+            vec![
+                (1.0, "foo".to_owned()),
+                (2.0, "\x1b]agg-marker;Step 1: Install\x07".to_owned()),
+                (3.0, "\x1b]agg-marker;\x07".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn outline_markers() {
+        let stdout = [
+            (1.0, "foo".to_owned()),
+            (2.0, "bar\x1b]agg-marker;Step 1\x07baz".to_owned()),
+            (3.0, "\x1b]agg-marker;\x07".to_owned()),
+        ];
+
+        let events = super::outline_markers(stdout.into_iter())
+            .map(|e| (e.time, e.type_, e.data))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            vec![
+                (1.0, super::EventType::Output, "foo".to_owned()),
+                (2.0, super::EventType::Output, "bar".to_owned()),
+                (2.0, super::EventType::Other('m'), "Step 1".to_owned()),
+                (2.0, super::EventType::Output, "baz".to_owned()),
+                (3.0, super::EventType::Other('m'), "".to_owned()),
+            ]
+        );
+    }
 
     #[test]
     fn open() {
@@ -181,4 +409,106 @@ mod tests {
         assert_eq!(events[2].type_, super::EventType::Output);
         assert_eq!(events[2].data, "r\r\u{1b}[17C");
     }
+
+    #[test]
+    fn open_gzipped() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&fs::read("demo.cast").unwrap()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let (header, events) = super::open(&gzipped[..]).unwrap();
+
+        let events = events
+            .take(3)
+            .collect::<Result<Vec<super::Event>, super::Error>>()
+            .unwrap();
+
+        assert_eq!(header.terminal_size, (89, 22));
+        assert_eq!(events[0].data, "\u{1b}[?2004h");
+        assert_eq!(events[2].data, "r\r\u{1b}[17C");
+    }
+
+    #[test]
+    fn open_with_bom() {
+        let cast = [b"\xef\xbb\xbf".as_slice(), &fs::read("demo.cast").unwrap()].concat();
+        let (header, events) = super::open(&cast[..]).unwrap();
+
+        assert_eq!(header.terminal_size, (89, 22));
+        assert!(events.take(3).all(|e| e.is_ok()));
+    }
+
+    #[test]
+    fn open_exit_code() {
+        let header = |line: &str| super::open(line.as_bytes()).unwrap().0.exit_code;
+
+        assert_eq!(header(r#"{"version":2,"width":80,"height":24}"#), None);
+        assert_eq!(
+            header(r#"{"version":2,"width":80,"height":24,"exit_code":1}"#),
+            Some(1)
+        );
+        assert_eq!(
+            header(
+                r#"{"version":2,"width":80,"height":24,"env":{"SHELL":"/bin/sh","EXIT_CODE":"0"}}"#
+            ),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn write() {
+        let theme = "000000,ffffff,000000,111111,222222,333333,444444,555555,666666,777777"
+            .parse::<crate::theme::Theme>()
+            .unwrap();
+
+        let header = super::Header {
+            version: Some(2),
+            terminal_size: (80, 24),
+            timestamp: Some(1_700_000_000),
+            idle_time_limit: None,
+            theme: Some(theme.clone()),
+            exit_code: Some(1),
+            title: Some("demo".to_owned()),
+            env: Some([("TERM".to_owned(), "xterm".into())].into()),
+        };
+
+        let events = [
+            (0.5, super::EventType::Output, "foo"),
+            (1.0, super::EventType::Other('m'), "Step 1"),
+            (1.25, super::EventType::Output, "\u{1b}[1mbar"),
+        ]
+        .map(|(time, type_, data)| super::Event {
+            time,
+            type_,
+            data: data.to_owned(),
+        });
+
+        let mut buf = Vec::new();
+
+        super::write(&mut buf, &header, events.into_iter()).unwrap();
+
+        let (header, events) = super::open(&buf[..]).unwrap();
+
+        let events = events
+            .collect::<Result<Vec<super::Event>, super::Error>>()
+            .unwrap();
+
+        assert_eq!(header.terminal_size, (80, 24));
+        assert_eq!(header.theme, Some(theme));
+        assert_eq!(header.idle_time_limit, None);
+        assert_eq!(header.timestamp, Some(1_700_000_000));
+        assert_eq!(header.exit_code, Some(1));
+        assert_eq!(header.title.as_deref(), Some("demo"));
+        assert_eq!(
+            header.env,
+            Some([("TERM".to_owned(), "xterm".into())].into())
+        );
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].time, 0.5);
+        assert_eq!(events[0].data, "foo");
+        assert_eq!(events[1].type_, super::EventType::Other('m'));
+        assert_eq!(events[1].data, "Step 1");
+        assert_eq!(events[2].time, 1.25);
+        assert_eq!(events[2].type_, super::EventType::Output);
+        assert_eq!(events[2].data, "\u{1b}[1mbar");
+    }
 }
@@ -0,0 +1,112 @@
+use anyhow::{anyhow, Result};
+use imgref::ImgVec;
+use rgb::{FromSlice, RGB8, RGBA8};
+
+pub fn load(path: &str, (width, height): (usize, usize), fill: RGB8) -> Result<ImgVec<RGBA8>> {
+    let path = shellexpand::tilde(path).to_string();
+    let image = tiny_skia::Pixmap::load_png(&path).map_err(|e| anyhow!("{path}: {e}"))?;
+
+    let mut pixmap = tiny_skia::Pixmap::new(width as u32, height as u32)
+        .ok_or_else(|| anyhow!("invalid backdrop size {width}x{height}"))?;
+
+    pixmap.fill(tiny_skia::Color::from_rgba8(fill.r, fill.g, fill.b, 255));
+
+    // scale to cover the whole frame, cropping the overflow evenly on both sides
+    let scale = (width as f32 / image.width() as f32).max(height as f32 / image.height() as f32);
+    let x = (width as f32 - image.width() as f32 * scale) / 2.0;
+    let y = (height as f32 - image.height() as f32 * scale) / 2.0;
+
+    let paint = tiny_skia::PixmapPaint {
+        quality: tiny_skia::FilterQuality::Bicubic,
+        ..Default::default()
+    };
+
+    pixmap.draw_pixmap(
+        0,
+        0,
+        image.as_ref(),
+        &paint,
+        tiny_skia::Transform::from_row(scale, 0.0, 0.0, scale, x, y),
+        None,
+    );
+
+    let buf = pixmap.take().as_rgba().to_vec();
+
+    Ok(ImgVec::new(buf, width, height))
+}
+
+pub fn composite(frame: &mut ImgVec<RGBA8>, backdrop: &ImgVec<RGBA8>, opacity: f64) {
+    for (px, bd) in frame.buf_mut().iter_mut().zip(backdrop.buf().iter()) {
+        let ratio = opacity * px.a as f64 / 255.0;
+
+        *px = RGBA8::new(
+            mix(px.r, bd.r, ratio),
+            mix(px.g, bd.g, ratio),
+            mix(px.b, bd.b, ratio),
+            255,
+        );
+    }
+}
+
+/// Composites the frame over a solid color, leaving no transparency.
+pub fn flatten(frame: &mut ImgVec<RGBA8>, color: RGB8) {
+    for px in frame.buf_mut().iter_mut() {
+        let ratio = px.a as f64 / 255.0;
+
+        *px = RGBA8::new(
+            mix(px.r, color.r, ratio),
+            mix(px.g, color.g, ratio),
+            mix(px.b, color.b, ratio),
+            255,
+        );
+    }
+}
+
+fn mix(fg: u8, bg: u8, ratio: f64) -> u8 {
+    (fg as f64 * ratio + bg as f64 * (1.0 - ratio)).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use imgref::ImgVec;
+    use rgb::{RGB8, RGBA8};
+
+    #[test]
+    fn composite() {
+        let mut frame = ImgVec::new(
+            vec![RGBA8::new(200, 100, 0, 255), RGBA8::new(0, 0, 0, 0)],
+            2,
+            1,
+        );
+
+        let backdrop = ImgVec::new(
+            vec![RGBA8::new(0, 100, 200, 255), RGBA8::new(10, 20, 30, 255)],
+            2,
+            1,
+        );
+
+        super::composite(&mut frame, &backdrop, 0.5);
+
+        assert_eq!(frame.buf()[0], RGBA8::new(100, 100, 100, 255));
+        assert_eq!(frame.buf()[1], RGBA8::new(10, 20, 30, 255));
+    }
+
+    #[test]
+    fn flatten() {
+        let mut frame = ImgVec::new(
+            vec![
+                RGBA8::new(200, 100, 0, 255),
+                RGBA8::new(200, 100, 0, 0),
+                RGBA8::new(200, 100, 0, 51),
+            ],
+            3,
+            1,
+        );
+
+        super::flatten(&mut frame, RGB8::new(0, 0, 250));
+
+        assert_eq!(frame.buf()[0], RGBA8::new(200, 100, 0, 255));
+        assert_eq!(frame.buf()[1], RGBA8::new(0, 0, 250, 255));
+        assert_eq!(frame.buf()[2], RGBA8::new(40, 20, 200, 255));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::graphemes::Clusters;
+use unicode_bidi::{bidi_class, BidiClass, BidiInfo, Level};
+
+/// Reorders the cells of a line from logical to visual order, using the
+/// Unicode bidirectional algorithm with left-to-right base direction (like
+/// terminals supporting bidi do). Brackets inside right-to-left runs are
+/// mirrored. There's no shaping, so Arabic letters keep their isolated forms.
+pub fn reorder(line: Vec<(char, avt::Pen)>, clusters: &Clusters) -> Vec<(char, avt::Pen)> {
+    match visual_order(&line, clusters) {
+        Some(order) => order
+            .into_iter()
+            .map(|(i, rtl)| {
+                let (ch, pen) = line[i];
+
+                (if rtl { mirror(ch) } else { ch }, pen)
+            })
+            .collect(),
+
+        None => line,
+    }
+}
+
+/// Returns the column at which the cell at logical column `col` of a line is
+/// displayed after `reorder`, e.g. for placing the cursor.
+pub fn visual_col(line: &[(char, avt::Pen)], col: usize, clusters: &Clusters) -> usize {
+    visual_order(line, clusters)
+        .and_then(|order| order.iter().position(|(i, _)| *i == col))
+        .unwrap_or(col)
+}
+
+/// Logical indices of the cells of a line in visual order, each with whether
+/// it's part of a right-to-left run, or `None` for lines without RTL text.
+fn visual_order(line: &[(char, avt::Pen)], clusters: &Clusters) -> Option<Vec<(usize, bool)>> {
+    // placeholders are classified by the first char they stand for
+    let text = line
+        .iter()
+        .map(|(ch, _)| {
+            clusters
+                .resolve(*ch)
+                .and_then(|text| text.chars().next())
+                .unwrap_or(*ch)
+        })
+        .collect::<String>();
+
+    if !text.chars().any(is_rtl) {
+        return None;
+    }
+
+    let info = BidiInfo::new(&text, Some(Level::ltr()));
+
+    // byte offset in text => cell index
+    let mut cell_at = vec![0; text.len() + 1];
+
+    for (i, (offset, ch)) in text.char_indices().enumerate() {
+        cell_at[offset..offset + ch.len_utf8()].fill(i);
+    }
+
+    let mut order = Vec::with_capacity(line.len());
+
+    for para in &info.paragraphs {
+        let (levels, runs) = info.visual_runs(para, para.range.clone());
+
+        for run in runs {
+            let range = cell_at[run.start]..=cell_at[run.end - 1];
+
+            if levels[run.start].is_rtl() {
+                order.extend(range.rev().map(|i| (i, true)));
+            } else {
+                order.extend(range.map(|i| (i, false)));
+            }
+        }
+    }
+
+    Some(order)
+}
+
+fn is_rtl(ch: char) -> bool {
+    !ch.is_ascii() && matches!(bidi_class(ch), BidiClass::R | BidiClass::AL)
+}
+
+fn mirror(ch: char) -> char {
+    match ch {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        ch => ch,
+    }
+}
@@ -0,0 +1,123 @@
+use crate::ColorTransform;
+use imgref::ImgVec;
+use rgb::RGBA8;
+
+pub type Matrix = [[f64; 3]; 3];
+
+// Machado, Oliveira, Fernandes (2009), severity 1.0
+const PROTANOPIA: Matrix = [
+    [0.152286, 1.052583, -0.204868],
+    [0.114503, 0.786281, 0.099216],
+    [-0.003882, -0.048116, 1.051998],
+];
+
+const DEUTERANOPIA: Matrix = [
+    [0.367322, 0.860646, -0.227968],
+    [0.280085, 0.672501, 0.047413],
+    [-0.011820, 0.042940, 0.968881],
+];
+
+const TRITANOPIA: Matrix = [
+    [1.255528, -0.076749, -0.178779],
+    [-0.078411, 0.930809, 0.147602],
+    [0.004733, 0.691367, 0.303900],
+];
+
+// Rec. 709 luma
+const GRAYSCALE: Matrix = [
+    [0.2126, 0.7152, 0.0722],
+    [0.2126, 0.7152, 0.0722],
+    [0.2126, 0.7152, 0.0722],
+];
+
+const LINEAR_STEPS: usize = 4096;
+
+pub fn matrix(transform: ColorTransform) -> &'static Matrix {
+    match transform {
+        ColorTransform::Protanopia => &PROTANOPIA,
+        ColorTransform::Deuteranopia => &DEUTERANOPIA,
+        ColorTransform::Tritanopia => &TRITANOPIA,
+        ColorTransform::Grayscale => &GRAYSCALE,
+    }
+}
+
+fn to_linear(v: f64) -> f64 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn to_srgb(v: f64) -> f64 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Applies the transform in linear RGB space, in place.
+pub fn apply(image: &mut ImgVec<RGBA8>, transform: ColorTransform) {
+    let m = matrix(transform);
+
+    let decode = (0..256)
+        .map(|v| to_linear(v as f64 / 255.0))
+        .collect::<Vec<_>>();
+
+    let encode = (0..=LINEAR_STEPS)
+        .map(|v| (to_srgb(v as f64 / LINEAR_STEPS as f64) * 255.0).round() as u8)
+        .collect::<Vec<_>>();
+
+    let encode = |v: f64| encode[(v.clamp(0.0, 1.0) * LINEAR_STEPS as f64).round() as usize];
+
+    for px in image.buf_mut().iter_mut() {
+        let (r, g, b) = (
+            decode[px.r as usize],
+            decode[px.g as usize],
+            decode[px.b as usize],
+        );
+
+        px.r = encode(m[0][0] * r + m[0][1] * g + m[0][2] * b);
+        px.g = encode(m[1][0] * r + m[1][1] * g + m[1][2] * b);
+        px.b = encode(m[2][0] * r + m[2][1] * g + m[2][2] * b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ColorTransform;
+    use imgref::ImgVec;
+    use rgb::RGBA8;
+
+    #[test]
+    fn apply() {
+        let pixels = vec![
+            RGBA8::new(255, 255, 255, 255),
+            RGBA8::new(0, 0, 0, 255),
+            RGBA8::new(255, 0, 0, 255),
+        ];
+
+        for transform in [
+            ColorTransform::Protanopia,
+            ColorTransform::Deuteranopia,
+            ColorTransform::Tritanopia,
+            ColorTransform::Grayscale,
+        ] {
+            let mut image = ImgVec::new(pixels.clone(), 3, 1);
+            super::apply(&mut image, transform);
+            let buf = image.buf();
+
+            assert_eq!(buf[0], RGBA8::new(255, 255, 255, 255));
+            assert_eq!(buf[1], RGBA8::new(0, 0, 0, 255));
+        }
+
+        let mut image = ImgVec::new(pixels, 3, 1);
+        super::apply(&mut image, ColorTransform::Grayscale);
+        let red = image.buf()[2];
+
+        assert_eq!(red.r, red.g);
+        assert_eq!(red.g, red.b);
+        assert!(red.r > 100 && red.r < 140);
+    }
+}
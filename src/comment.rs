@@ -0,0 +1,113 @@
+use std::io::{self, Write};
+
+/// Wraps a GIF writer, inserting a comment extension block right after the
+/// logical screen descriptor (and global color table, if any).
+pub struct GifComment<W: Write> {
+    inner: W,
+    comment: Option<Vec<u8>>,
+    head: Vec<u8>,
+}
+
+impl<W: Write> GifComment<W> {
+    pub fn new(inner: W, comment: &str) -> Self {
+        Self {
+            inner,
+            comment: Some(block(comment.as_bytes())),
+            head: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for GifComment<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(comment) = &self.comment else {
+            return self.inner.write(buf);
+        };
+
+        self.head.extend_from_slice(buf);
+
+        if let Some(len) = header_len(&self.head) {
+            self.inner.write_all(&self.head[..len])?;
+            self.inner.write_all(comment)?;
+            self.inner.write_all(&self.head[len..])?;
+            self.head = Vec::new();
+            self.comment = None;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn header_len(head: &[u8]) -> Option<usize> {
+    // signature + version (6 bytes) and logical screen descriptor (7 bytes)
+    let packed = *head.get(10)?;
+
+    let color_table = if packed & 0x80 != 0 {
+        3 << ((packed & 0x07) + 1)
+    } else {
+        0
+    };
+
+    let len = 13 + color_table;
+
+    (head.len() >= len).then_some(len)
+}
+
+fn block(comment: &[u8]) -> Vec<u8> {
+    let mut block = vec![0x21, 0xfe];
+
+    for chunk in comment.chunks(255) {
+        block.push(chunk.len() as u8);
+        block.extend_from_slice(chunk);
+    }
+
+    block.push(0);
+
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GifComment;
+    use std::io::Write;
+
+    #[test]
+    fn gif_comment() {
+        // header with a 2 color global color table, followed by a trailer
+        let gif = [
+            b"GIF89a".as_slice(),
+            &[2, 0, 1, 0, 0x80, 0, 0],
+            &[0, 0, 0, 255, 255, 255],
+            &[0x3b],
+        ]
+        .concat();
+
+        let mut out = Vec::new();
+        let mut writer = GifComment::new(&mut out, "hi");
+
+        for chunk in gif.chunks(5) {
+            writer.write_all(chunk).unwrap();
+        }
+
+        writer.flush().unwrap();
+
+        assert_eq!(&out[..19], &gif[..19]);
+        assert_eq!(&out[19..25], &[0x21, 0xfe, 2, b'h', b'i', 0]);
+        assert_eq!(&out[25..], &gif[19..]);
+    }
+
+    #[test]
+    fn block() {
+        let comment = "x".repeat(300);
+        let block = super::block(comment.as_bytes());
+
+        assert_eq!(block.len(), 2 + 1 + 255 + 1 + 45 + 1);
+        assert_eq!(block[2], 255);
+        assert_eq!(block[258], 45);
+        assert_eq!(block[block.len() - 1], 0);
+    }
+}
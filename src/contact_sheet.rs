@@ -0,0 +1,106 @@
+use imgref::ImgVec;
+use rgb::RGBA8;
+
+use crate::indexed::Image;
+
+pub const GAP: usize = 8;
+
+/// Picks up to `n` indices spread evenly over `0..count`, including the first
+/// and the last one.
+pub fn sample(count: usize, n: usize) -> Vec<usize> {
+    if count <= n {
+        return (0..count).collect();
+    }
+
+    if n == 1 {
+        return vec![count - 1];
+    }
+
+    (0..n)
+        .map(|k| ((k * (count - 1)) as f64 / (n - 1) as f64).round() as usize)
+        .collect()
+}
+
+/// Tiles same-sized images into a grid with `cols` columns, separated (and
+/// surrounded) by gaps filled with `fill`.
+pub fn compose(images: &[Image], cols: usize, fill: RGBA8) -> ImgVec<RGBA8> {
+    let (w, h) = images
+        .first()
+        .map_or((0, 0), |image| (image.width(), image.height()));
+
+    let rows = images.len().div_ceil(cols);
+    let width = cols * w + (cols + 1) * GAP;
+    let height = rows * h + (rows + 1) * GAP;
+    let mut buf = vec![fill; width * height];
+
+    for (i, image) in images.iter().enumerate() {
+        let x = GAP + (i % cols) * (w + GAP);
+        let y = GAP + (i / cols) * (h + GAP);
+
+        for row in 0..h {
+            let start = (y + row) * width + x;
+            image.copy_row(row, &mut buf[start..start + w]);
+        }
+    }
+
+    ImgVec::new(buf, width, height)
+}
+
+/// File name for the `n`th chapter thumbnail: the marker label turned into a
+/// lowercase slug, or its time when the label has nothing usable.
+pub fn chapter_name(n: usize, time: f64, label: &str) -> String {
+    let slug = label
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.is_empty() {
+        format!("{n:02}-{time:.1}s.png")
+    } else {
+        format!("{n:02}-{slug}.png")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::indexed::Image;
+    use imgref::ImgVec;
+    use rgb::RGBA8;
+
+    #[test]
+    fn sample() {
+        assert_eq!(super::sample(3, 6), vec![0, 1, 2]);
+        assert_eq!(super::sample(100, 4), vec![0, 33, 66, 99]);
+        assert_eq!(super::sample(10, 1), vec![9]);
+    }
+
+    #[test]
+    fn chapter_name() {
+        assert_eq!(
+            super::chapter_name(1, 2.0, "Step 1: Install"),
+            "01-step-1-install.png"
+        );
+        assert_eq!(super::chapter_name(12, 3.5, "--"), "12-3.5s.png");
+    }
+
+    #[test]
+    fn compose() {
+        let red = RGBA8::new(255, 0, 0, 255);
+        let fill = RGBA8::new(0, 0, 0, 255);
+        let images = (0..3)
+            .map(|i| Image::new(ImgVec::new(vec![red; 4 * 2], 4, 2), i > 0))
+            .collect::<Vec<_>>();
+
+        let sheet = super::compose(&images, 2, fill);
+        let gap = super::GAP;
+
+        assert_eq!(sheet.width(), 2 * 4 + 3 * gap);
+        assert_eq!(sheet.height(), 2 * 2 + 3 * gap);
+        assert_eq!(sheet[(gap, gap)], red);
+        assert_eq!(sheet[(gap + 4 + gap, gap)], red);
+        assert_eq!(sheet[(gap + 4, gap)], fill);
+        assert_eq!(sheet[(gap + 4 + gap, gap + 2 + gap)], fill);
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::{bail, Result};
+use imgref::ImgVec;
+use rgb::RGBA8;
+
+/// A rectangle of terminal cells as (column, row, width, height).
+pub type Region = (usize, usize, usize, usize);
+
+type Lines = Vec<Vec<(char, avt::Pen)>>;
+
+/// Shrinks the region to fit the terminal, failing when it starts outside.
+pub fn clamp((x, y, w, h): Region, (cols, rows): (usize, usize)) -> Result<Region> {
+    if x >= cols || y >= rows {
+        bail!("emphasis region starts at {x},{y}, outside of the {cols}x{rows} terminal");
+    }
+
+    Ok((x, y, w.min(cols - x), h.min(rows - y)))
+}
+
+/// Cuts the region out of the terminal lines, along with the cursor position
+/// relative to it (none when the cursor is outside).
+pub fn cut(
+    lines: &Lines,
+    cursor: Option<(usize, usize)>,
+    (x, y, w, h): Region,
+) -> (Lines, Option<(usize, usize)>) {
+    let region = lines
+        .iter()
+        .skip(y)
+        .take(h)
+        .map(|line| line.iter().skip(x).take(w).cloned().collect())
+        .collect();
+
+    let cursor = cursor
+        .filter(|&(cx, cy)| cx >= x && cx < x + w && cy >= y && cy < y + h)
+        .map(|(cx, cy)| (cx - x, cy - y));
+
+    (region, cursor)
+}
+
+/// Draws the magnified region over the frame, centered on the `center` pixel
+/// but shifted to stay within the frame (and clipped if it's larger).
+pub fn composite(frame: &mut ImgVec<RGBA8>, zoomed: &ImgVec<RGBA8>, (cx, cy): (f32, f32)) {
+    let place = |center: f32, size: usize, limit: usize| {
+        let pos = (center - size as f32 / 2.0).round() as isize;
+
+        pos.min(limit as isize - size as isize).max(0) as usize
+    };
+
+    let x = place(cx, zoomed.width(), frame.width());
+    let y = place(cy, zoomed.height(), frame.height());
+    let (width, height, stride) = (frame.width(), frame.height(), frame.stride());
+    let buf = frame.buf_mut();
+
+    for (row, pixels) in zoomed.rows().enumerate().take(height - y) {
+        for (col, px) in pixels.iter().enumerate().take(width - x) {
+            let dst = &mut buf[(y + row) * stride + x + col];
+            *dst = over(*px, *dst);
+        }
+    }
+}
+
+/// Straight alpha "over" operator.
+fn over(src: RGBA8, dst: RGBA8) -> RGBA8 {
+    let sa = src.a as f64 / 255.0;
+    let da = dst.a as f64 / 255.0 * (1.0 - sa);
+    let a = sa + da;
+
+    if a == 0.0 {
+        return RGBA8::default();
+    }
+
+    let mix = |s: u8, d: u8| ((s as f64 * sa + d as f64 * da) / a).round() as u8;
+
+    RGBA8::new(
+        mix(src.r, dst.r),
+        mix(src.g, dst.g),
+        mix(src.b, dst.b),
+        (a * 255.0).round() as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use imgref::ImgVec;
+    use rgb::RGBA8;
+
+    #[test]
+    fn clamp() {
+        assert_eq!(super::clamp((2, 1, 4, 2), (10, 5)).unwrap(), (2, 1, 4, 2));
+        assert_eq!(super::clamp((8, 3, 4, 4), (10, 5)).unwrap(), (8, 3, 2, 2));
+        assert!(super::clamp((10, 0, 1, 1), (10, 5)).is_err());
+    }
+
+    #[test]
+    fn cut() {
+        let pen = avt::Pen::default();
+
+        let lines = ["abcd", "efgh", "ijkl"]
+            .iter()
+            .map(|s| s.chars().map(|ch| (ch, pen)).collect())
+            .collect();
+
+        let (region, cursor) = super::cut(&lines, Some((2, 2)), (1, 1, 2, 2));
+        let text = region
+            .iter()
+            .map(|line| line.iter().map(|(ch, _)| ch).collect::<String>())
+            .collect::<Vec<_>>();
+
+        assert_eq!(text, ["fg", "jk"]);
+        assert_eq!(cursor, Some((1, 1)));
+        assert_eq!(super::cut(&lines, Some((0, 0)), (1, 1, 2, 2)).1, None);
+    }
+
+    #[test]
+    fn composite() {
+        let (black, white) = (RGBA8::new(0, 0, 0, 255), RGBA8::new(255, 255, 255, 255));
+        let mut frame = ImgVec::new(vec![black; 16], 4, 4);
+        let zoomed = ImgVec::new(vec![white; 4], 2, 2);
+
+        // centered on the bottom-right pixel, shifted to stay inside
+        super::composite(&mut frame, &zoomed, (3.5, 3.5));
+
+        let white_px = frame
+            .buf()
+            .iter()
+            .enumerate()
+            .filter(|(_, px)| **px == white)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        assert_eq!(white_px, [10, 11, 14, 15]);
+    }
+
+    #[test]
+    fn over() {
+        let dst = RGBA8::new(0, 0, 0, 255);
+
+        assert_eq!(super::over(RGBA8::new(255, 0, 0, 0), dst), dst);
+        assert_eq!(
+            super::over(RGBA8::new(255, 255, 255, 128), dst),
+            RGBA8::new(128, 128, 128, 255)
+        );
+    }
+}
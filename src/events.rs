@@ -1,3 +1,6 @@
+use log::debug;
+use std::iter;
+
 type Event = (f64, String);
 
 struct Batch<I>
@@ -57,10 +60,106 @@ pub fn batch(iter: impl Iterator<Item = Event>, fps_cap: u8) -> impl Iterator<It
     }
 }
 
+/// One frame per burst of output, shown when the burst starts. A burst spans
+/// at most one frame interval from its first event, so a steady stream of
+/// closely spaced events still yields frames at the FPS cap.
+pub fn coalesce(iter: impl Iterator<Item = Event>, fps_cap: u8) -> impl Iterator<Item = Event> {
+    let max_frame_time = 1.0 / (fps_cap as f64);
+    let mut iter = iter.peekable();
+
+    iter::from_fn(move || {
+        let (time, mut data) = iter.next()?;
+
+        while let Some((_, next_data)) =
+            iter.next_if(|(next_time, _)| next_time - time < max_frame_time)
+        {
+            data.push_str(&next_data);
+        }
+
+        Some((time, data))
+    })
+}
+
+/// One frame per event (no FPS cap), except that events sharing a timestamp
+/// are merged, as they can't be shown separately.
+pub fn exact(iter: impl Iterator<Item = Event>) -> impl Iterator<Item = Event> {
+    let mut iter = iter.peekable();
+
+    iter::from_fn(move || {
+        let (time, mut data) = iter.next()?;
+
+        while let Some((_, next_data)) = iter.next_if(|(next_time, _)| *next_time == time) {
+            data.push_str(&next_data);
+        }
+
+        Some((time, data))
+    })
+}
+
+pub fn sample(iter: impl Iterator<Item = Event>, interval: f64) -> impl Iterator<Item = Event> {
+    let mut iter = iter.peekable();
+    let mut n: u64 = 0;
+
+    iter::from_fn(move || {
+        iter.peek()?;
+
+        let time = n as f64 * interval;
+        let mut data = "".to_owned();
+
+        while let Some((_, next_data)) = iter.next_if(|(next_time, _)| *next_time <= time) {
+            data.push_str(&next_data);
+        }
+
+        n += 1;
+
+        Some((time, data))
+    })
+}
+
+/// Merges frames shown for less than `min` seconds into the following frame,
+/// which then starts at the merged frame's time and lasts for both. Merged
+/// frames get `None`, to be dropped. The last frame is never merged.
+pub fn min_duration(timeline: &[(f64, f64)], min: f64) -> Vec<Option<(f64, f64)>> {
+    let mut start = None;
+
+    timeline
+        .iter()
+        .enumerate()
+        .map(|(i, &(time, duration))| {
+            let start_time = start.take().unwrap_or(time);
+            let duration = time + duration - start_time;
+
+            if duration < min && i + 1 < timeline.len() {
+                start = Some(start_time);
+
+                None
+            } else {
+                Some((start_time, duration))
+            }
+        })
+        .collect()
+}
+
 pub fn accelerate(events: impl Iterator<Item = Event>, speed: f64) -> impl Iterator<Item = Event> {
     events.map(move |(time, data)| (time / speed, data))
 }
 
+/// Clamps times jumping backward (clock adjustments during recording) to the
+/// previous time, so times never decrease.
+pub fn monotonic(events: impl Iterator<Item = Event>) -> impl Iterator<Item = Event> {
+    let mut prev_time = 0.0;
+
+    events.map(move |(time, data)| {
+        if time < prev_time {
+            debug!("clamping backward timestamp {time} to {prev_time}");
+        } else {
+            prev_time = time;
+        }
+
+        (prev_time, data)
+    })
+}
+
 pub fn limit_idle_time(
     events: impl Iterator<Item = Event>,
     limit: f64,
@@ -82,6 +181,66 @@ pub fn limit_idle_time(
     })
 }
 
+/// Gaps longer than this are idle time, not part of an update burst.
+const AUTO_FPS_IDLE_GAP: f64 = 0.5;
+
+/// Picks an FPS cap matching the typical update rate of the recording: the
+/// median interval between events during activity (idle gaps excluded),
+/// clamped to 10 fps at least and the default cap at most, which a fixed
+/// `--fps-cap` has to be raised explicitly to exceed.
+pub fn auto_fps(times: &[f64]) -> u8 {
+    let mut intervals = times
+        .windows(2)
+        .map(|w| w[1] - w[0])
+        .filter(|&d| d > 0.0 && d < AUTO_FPS_IDLE_GAP)
+        .collect::<Vec<_>>();
+
+    if intervals.is_empty() {
+        return crate::DEFAULT_FPS_CAP;
+    }
+
+    intervals.sort_by(|a, b| a.total_cmp(b));
+    let median = intervals[intervals.len() / 2];
+
+    (1.0 / median)
+        .round()
+        .clamp(10.0, crate::DEFAULT_FPS_CAP as f64) as u8
+}
+
+/// Pairs each frame time with how long the frame stays on screen: until the
+/// next frame, or `last` seconds for the final one.
+pub fn durations(times: &[f64], last: f64) -> Vec<(f64, f64)> {
+    times
+        .iter()
+        .enumerate()
+        .map(|(i, &time)| {
+            let duration = times.get(i + 1).map_or(last, |next| (next - time).max(0.0));
+
+            (time, duration)
+        })
+        .collect()
+}
+
+/// Snaps frame times to centiseconds (GIF delay unit). Rounding absolute
+/// times rather than individual durations carries the rounding error forward,
+/// so the total duration doesn't drift on long, high frame rate recordings.
+/// Frames falling on the same centisecond can't be shown apart, so all but the
+/// last of them (with the newest content) get `None`, to be dropped.
+pub fn centiseconds(times: &[f64]) -> Vec<Option<f64>> {
+    let cs = times
+        .iter()
+        .map(|time| (time * 100.0).round().max(0.0) as u64)
+        .collect::<Vec<_>>();
+
+    cs.iter()
+        .enumerate()
+        .map(|(i, &t)| match cs.get(i + 1) {
+            Some(&next) if next <= t => None,
+            _ => Some(t as f64 / 100.0),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -99,6 +258,27 @@ mod tests {
         assert_eq!(&stdout[2], &(1.0, "baz".to_owned()));
     }
 
+    #[test]
+    fn exact() {
+        let stdout = [
+            (0.0, "foo".to_owned()),
+            (0.001, "bar".to_owned()),
+            (0.001, "baz".to_owned()),
+            (0.002, "qux".to_owned()),
+        ];
+
+        let stdout = super::exact(stdout.into_iter()).collect::<Vec<_>>();
+
+        assert_eq!(
+            stdout,
+            [
+                (0.0, "foo".to_owned()),
+                (0.001, "barbaz".to_owned()),
+                (0.002, "qux".to_owned())
+            ]
+        );
+    }
+
     #[test]
     fn batch() {
         let stdout = [
@@ -139,6 +319,89 @@ mod tests {
         assert_eq!(&stdout[2], &(2.0, "bar".to_owned()));
     }
 
+    #[test]
+    fn coalesce() {
+        let stdout = [
+            (0.0, "foo".to_owned()),
+            (1.0, "bar".to_owned()),
+            (1.02, "baz".to_owned()),
+            (1.04, "qux".to_owned()),
+            (2.0, "quux".to_owned()),
+        ];
+
+        let stdout = super::coalesce(stdout.into_iter(), 30).collect::<Vec<_>>();
+
+        assert_eq!(stdout.len(), 4);
+        assert_eq!(&stdout[0], &(0.0, "foo".to_owned()));
+        assert_eq!(&stdout[1], &(1.0, "barbaz".to_owned()));
+        assert_eq!(&stdout[2], &(1.04, "qux".to_owned()));
+        assert_eq!(&stdout[3], &(2.0, "quux".to_owned()));
+    }
+
+    #[test]
+    fn coalesce_steady_stream() {
+        let stdout = (0..1024).map(|i| (i as f64 / 64.0, "x".to_owned()));
+        let stdout = super::coalesce(stdout, 8).collect::<Vec<_>>();
+
+        assert_eq!(stdout.len(), 128);
+
+        for (i, (time, data)) in stdout.iter().enumerate() {
+            assert_eq!(*time, (i * 8) as f64 / 64.0);
+            assert_eq!(data, "xxxxxxxx");
+        }
+    }
+
+    #[test]
+    fn sample() {
+        let stdout = [
+            (0.0, "foo".to_owned()),
+            (0.2, "bar".to_owned()),
+            (0.7, "baz".to_owned()),
+            (2.1, "qux".to_owned()),
+        ];
+
+        let stdout = super::sample(stdout.into_iter(), 1.0).collect::<Vec<_>>();
+
+        assert_eq!(stdout.len(), 4);
+        assert_eq!(&stdout[0], &(0.0, "foo".to_owned()));
+        assert_eq!(&stdout[1], &(1.0, "barbaz".to_owned()));
+        assert_eq!(&stdout[2], &(2.0, "".to_owned()));
+        assert_eq!(&stdout[3], &(3.0, "qux".to_owned()));
+    }
+
+    #[test]
+    fn min_duration() {
+        let timeline = super::durations(&[0.0, 1.0, 1.04, 1.08, 1.12, 2.0, 2.01], 0.05);
+        let timeline = super::min_duration(&timeline, 0.1);
+
+        let expected = [
+            Some((0.0, 1.0)),
+            None,
+            None,
+            // accumulated 1.0..1.08 isn't long enough yet, 1.0..1.12 is
+            Some((1.0, 0.12)),
+            Some((1.12, 0.88)),
+            None,
+            // the last frame stays, even if short
+            Some((2.0, 0.06)),
+        ];
+
+        assert_eq!(timeline.len(), expected.len());
+
+        for (actual, expected) in timeline.iter().zip(expected) {
+            match (actual, expected) {
+                (Some((t1, d1)), Some((t2, d2))) => {
+                    assert!(
+                        (t1 - t2).abs() < 1e-9 && (d1 - d2).abs() < 1e-9,
+                        "{actual:?}"
+                    );
+                }
+
+                _ => assert_eq!(*actual, expected),
+            }
+        }
+    }
+
     #[test]
     fn limit_idle_time() {
         let stdout = [
@@ -157,4 +420,124 @@ mod tests {
         assert_eq!(&stdout[3], &(3.5, "qux".to_owned()));
         assert_eq!(&stdout[4], &(5.5, "quux".to_owned()));
     }
+
+    #[test]
+    fn monotonic() {
+        let stdout = [
+            (0.0, "foo".to_owned()),
+            (2.0, "bar".to_owned()),
+            (1.5, "baz".to_owned()),
+            (1.8, "qux".to_owned()),
+            (3.0, "quux".to_owned()),
+        ];
+
+        let times = super::monotonic(stdout.into_iter())
+            .map(|(time, _)| time)
+            .collect::<Vec<_>>();
+
+        assert_eq!(times, vec![0.0, 2.0, 2.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn monotonic_cast() {
+        let cast = concat!(
+            "{\"version\": 2, \"width\": 10, \"height\": 2}\n",
+            "[0.5, \"o\", \"a\"]\n",
+            "[3.0, \"o\", \"b\"]\n",
+            "[1.0, \"o\", \"c\"]\n",
+            "[4.0, \"o\", \"d\"]\n",
+        );
+
+        let (_, events) = crate::asciicast::open(std::io::BufReader::new(cast.as_bytes())).unwrap();
+        let stdout = super::monotonic(crate::asciicast::stdout(events));
+        let stdout = super::limit_idle_time(stdout, 5.0);
+        let stdout = super::batch(stdout, 30);
+        let frames = crate::vt::frames(stdout, (10, 2), Default::default()).collect::<Vec<_>>();
+
+        let times = frames.iter().map(|f| f.time).collect::<Vec<_>>();
+        assert!(times.windows(2).all(|w| w[0] <= w[1]));
+
+        let times = super::centiseconds(&times)
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        let durations = super::durations(&times, 3.0);
+        assert!(durations.iter().all(|(_, d)| *d > 0.0));
+        assert_eq!(frames.last().unwrap().lines[0][3].0, 'd');
+    }
+
+    #[test]
+    fn auto_fps() {
+        assert_eq!(super::auto_fps(&[]), crate::DEFAULT_FPS_CAP);
+        assert_eq!(super::auto_fps(&[0.0, 10.0]), crate::DEFAULT_FPS_CAP);
+
+        // typing at ~5 chars/s with long pauses in between
+        let typing = (0..20)
+            .map(|i| (i / 5) as f64 * 10.0 + (i % 5) as f64 * 0.2)
+            .collect::<Vec<_>>();
+
+        assert_eq!(super::auto_fps(&typing), 10);
+
+        // 24 fps animation
+        let animation = (0..100).map(|i| i as f64 / 24.0).collect::<Vec<_>>();
+        assert_eq!(super::auto_fps(&animation), 24);
+
+        // progress bar flooding output
+        let flood = (0..1000).map(|i| i as f64 * 0.001).collect::<Vec<_>>();
+        assert_eq!(super::auto_fps(&flood), crate::DEFAULT_FPS_CAP);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(super::durations(&[], 3.0), vec![]);
+        assert_eq!(super::durations(&[0.0], 3.0), vec![(0.0, 3.0)]);
+
+        assert_eq!(
+            super::durations(&[0.0, 0.5, 2.0], 3.0),
+            vec![(0.0, 0.5), (0.5, 1.5), (2.0, 3.0)]
+        );
+
+        // out of order times never produce negative durations
+        assert_eq!(
+            super::durations(&[1.0, 0.5], 3.0),
+            vec![(1.0, 0.0), (0.5, 3.0)]
+        );
+    }
+
+    #[test]
+    fn centiseconds() {
+        assert_eq!(
+            super::centiseconds(&[0.0, 0.014, 0.016, 0.0161, 1.0]),
+            vec![Some(0.0), Some(0.01), None, Some(0.02), Some(1.0)]
+        );
+
+        // an hour at 60 fps
+        let times = (0..216_000).map(|i| i as f64 / 60.0).collect::<Vec<_>>();
+        let times = super::centiseconds(&times);
+        assert!(times.iter().all(Option::is_some));
+        let times = times.into_iter().flatten().collect::<Vec<_>>();
+        let durations = super::durations(&times, 0.0);
+        let total = durations.iter().map(|(_, d)| d).sum::<f64>();
+
+        assert!((total - 3599.98).abs() < 1e-6, "total duration: {total}");
+
+        for (_, d) in durations.iter().take(times.len() - 1) {
+            let cs = d * 100.0;
+            assert!((cs - cs.round()).abs() < 1e-6 && cs.round() >= 1.0);
+        }
+    }
+
+    #[test]
+    fn centiseconds_dense() {
+        // 10 minutes at 200 fps, two frames per centisecond
+        let times = (0..=120_000).map(|i| i as f64 / 200.0).collect::<Vec<_>>();
+        let times = super::centiseconds(&times)
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        assert_eq!(times.len(), 60_001);
+        assert_eq!(times.last(), Some(&600.0));
+        assert!(times.windows(2).all(|w| w[1] - w[0] > 0.005));
+    }
 }
@@ -1,26 +1,90 @@
-pub fn init(font_dirs: &[String], font_family: &str) -> Option<(fontdb::Database, Vec<String>)> {
+use log::{debug, warn};
+use std::path::Path;
+
+pub fn init(
+    font_dirs: &[String],
+    font_files: &[String],
+    font_family: &str,
+) -> Option<(fontdb::Database, Vec<String>)> {
     let mut font_db = fontdb::Database::new();
     font_db.load_system_fonts();
 
     for dir in font_dirs {
-        font_db.load_fonts_dir(shellexpand::tilde(dir).to_string());
+        let path = shellexpand::tilde(dir).to_string();
+
+        if !Path::new(&path).is_dir() {
+            warn!("font dir {} doesn't exist, skipping", dir);
+            continue;
+        }
+
+        let count = font_db.faces().len();
+        font_db.load_fonts_dir(&path);
+        debug!(
+            "loaded {} faces from font dir {}",
+            font_db.faces().len() - count,
+            path
+        );
     }
 
-    let mut families = font_family
-        .split(',')
-        .map(|name| name.trim())
-        .filter_map(|name| find_font_family(&font_db, name))
+    let mut families = Vec::new();
+    let mut file_faces = Vec::new();
+
+    for file in font_files {
+        let count = font_db.faces().len();
+
+        if let Err(e) = font_db.load_font_file(shellexpand::tilde(file).to_string()) {
+            warn!("can't load font file {}: {}", file, e);
+            continue;
+        }
+
+        for face in &font_db.faces()[count..] {
+            debug!("font file {} provides {}", file, describe(face));
+            file_faces.push(face.id);
+
+            if !families.contains(&face.family) {
+                families.push(face.family.clone());
+            }
+        }
+    }
+
+    // the renderers look fonts up by family name, so drop installed faces of
+    // the same families to make sure the given files are the ones used
+    let shadowed = font_db
+        .faces()
+        .iter()
+        .filter(|face| families.contains(&face.family) && !file_faces.contains(&face.id))
+        .map(|face| face.id)
         .collect::<Vec<_>>();
 
+    for id in shadowed {
+        if let Some(face) = font_db.face(id) {
+            debug!("font file overrides {}", describe(face));
+        }
+
+        font_db.remove_face(id);
+    }
+
+    for name in font_family.split(',').map(|name| name.trim()) {
+        if let Some(name) = find_font_family(&font_db, name) {
+            if families.contains(&name) {
+                debug!("skipping font family {:?}, already selected", name);
+            } else {
+                families.push(name);
+            }
+        }
+    }
+
     if families.is_empty() {
         None
     } else {
-        for name in ["DejaVu Sans", "Noto Emoji"] {
+        for name in ["DejaVu Sans", "Noto Emoji", "Noto Color Emoji"] {
             if let Some(name) = find_font_family(&font_db, name) {
                 families.push(name);
             }
         }
 
+        debug!("selected font families: {:?}", families);
+
         Some((font_db, families))
     }
 }
@@ -35,7 +99,70 @@ fn find_font_family(font_db: &fontdb::Database, name: &str) -> Option<String> {
         style: fontdb::Style::Normal,
     };
 
-    font_db
+    let face = font_db
         .query(&query)
-        .map(|face_id| font_db.face(face_id).unwrap().family.clone())
+        .and_then(|face_id| font_db.face(face_id));
+
+    match face {
+        Some(face) => {
+            debug!("font family {:?} matched {}", name, describe(face));
+
+            Some(face.family.clone())
+        }
+
+        None => {
+            debug!("font family {:?} not found, skipping", name);
+
+            None
+        }
+    }
+}
+
+fn describe(face: &fontdb::FaceInfo) -> String {
+    let source = match &face.source {
+        fontdb::Source::File(path) => path.display().to_string(),
+        _ => "<memory>".to_owned(),
+    };
+
+    format!(
+        "{} ({}, weight={}, style={:?}, monospaced={}) from {}",
+        face.family, face.post_script_name, face.weight.0, face.style, face.monospaced, source
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    const FONT_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/DejaVuSansMono.ttf");
+
+    #[test]
+    fn font_file() {
+        let (font_db, families) =
+            super::init(&[], &[FONT_FILE.to_owned()], "No Such Font Family").unwrap();
+
+        assert_eq!(families[0], "DejaVu Sans Mono");
+
+        let query = fontdb::Query {
+            families: &[fontdb::Family::Name(&families[0])],
+            ..Default::default()
+        };
+
+        let face = font_db.face(font_db.query(&query).unwrap()).unwrap();
+
+        match &face.source {
+            fontdb::Source::File(path) => assert_eq!(path, Path::new(FONT_FILE)),
+            _ => panic!("expected a face loaded from a file"),
+        }
+    }
+
+    #[test]
+    fn missing_font_file() {
+        assert!(super::init(
+            &[],
+            &["/no/such/font.ttf".to_owned()],
+            "No Such Font Family"
+        )
+        .is_none());
+    }
 }
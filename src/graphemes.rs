@@ -0,0 +1,231 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{Normalization, UnderlineStyle};
+
+// Supplementary Private Use Area-B, which (unlike area A) icon fonts don't use
+const FIRST_PLACEHOLDER: u32 = 0x100000;
+const LAST_PLACEHOLDER: u32 = 0x10FFFC;
+
+/// Fills the second cell of a double-width cluster, drawn as blank.
+pub const SPACER: char = '\u{10FFFD}';
+
+/// Multi-codepoint grapheme clusters (ZWJ emoji sequences, flags, skin tone
+/// modifiers) of a recording, each stood in for by a placeholder char so that
+/// the terminal emulator keeps it together. Shared by the emulation, which
+/// substitutes the clusters, and the renderers, which resolve them.
+#[derive(Clone, Default)]
+pub struct Clusters(Arc<RwLock<Table>>);
+
+#[derive(Default)]
+struct Table {
+    clusters: Vec<(String, Option<UnderlineStyle>)>,
+    placeholders: HashMap<(String, Option<UnderlineStyle>), char>,
+}
+
+impl Clusters {
+    /// Replaces clusters with placeholder chars, followed by a spacer for
+    /// double-width ones so that the rest of the line keeps its columns.
+    /// Clusters starting with an ASCII char are left alone, as those may be
+    /// parts of escape sequences.
+    pub fn substitute<'a>(&self, data: &'a str) -> Cow<'a, str> {
+        if data.is_ascii() {
+            return Cow::Borrowed(data);
+        }
+
+        let mut out = String::with_capacity(data.len());
+
+        for cluster in data.graphemes(true) {
+            let mut chars = cluster.chars();
+
+            let placeholder = match (chars.next(), chars.next()) {
+                (Some(first), Some(_)) if !first.is_ascii() => self.placeholder(cluster, None),
+                _ => None,
+            };
+
+            match placeholder {
+                Some(ch) => {
+                    out.push(ch);
+
+                    if cluster.width() > 1 {
+                        out.push(SPACER);
+                    }
+                }
+
+                None => out.push_str(cluster),
+            }
+        }
+
+        Cow::Owned(out)
+    }
+
+    /// Returns a placeholder char standing for a cell underlined in an
+    /// explicit style (SGR `4:2` etc.), which the pen can't express.
+    pub fn underlined(&self, ch: char, style: UnderlineStyle) -> char {
+        let text = self.resolve(ch).unwrap_or_else(|| ch.to_string());
+
+        self.placeholder(&text, Some(style)).unwrap_or(ch)
+    }
+
+    fn placeholder(&self, cluster: &str, style: Option<UnderlineStyle>) -> Option<char> {
+        let mut table = self.0.write().unwrap();
+        let key = (cluster.to_owned(), style);
+
+        if let Some(ch) = table.placeholders.get(&key) {
+            return Some(*ch);
+        }
+
+        let ch = char::from_u32(FIRST_PLACEHOLDER + table.clusters.len() as u32)
+            .filter(|ch| *ch as u32 <= LAST_PLACEHOLDER)?;
+
+        table.clusters.push(key.clone());
+        table.placeholders.insert(key, ch);
+
+        Some(ch)
+    }
+
+    /// Returns the grapheme cluster (or single char) represented by a
+    /// placeholder char.
+    pub fn resolve(&self, ch: char) -> Option<String> {
+        let i = (ch as u32).checked_sub(FIRST_PLACEHOLDER)?;
+
+        self.0
+            .read()
+            .unwrap()
+            .clusters
+            .get(i as usize)
+            .map(|(cluster, _)| cluster.clone())
+    }
+
+    /// Returns the underline style carried by a placeholder char, see
+    /// `underlined`.
+    pub fn underline_style(&self, ch: char) -> Option<UnderlineStyle> {
+        let i = (ch as u32).checked_sub(FIRST_PLACEHOLDER)?;
+
+        self.0.read().unwrap().clusters.get(i as usize)?.1
+    }
+}
+
+/// Converts text to the given Unicode normalization form, e.g. composing the
+/// decomposed accented letters of macOS file names (NFD) so they're drawn with
+/// precomposed glyphs. A mark split from its base char across events is left
+/// as it is.
+pub fn normalize(data: String, form: Normalization) -> String {
+    if data.is_ascii() {
+        return data;
+    }
+
+    match form {
+        Normalization::None => data,
+        Normalization::Nfc => data.nfc().collect(),
+        Normalization::Nfd => data.nfd().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clusters, SPACER};
+    use crate::{Normalization, UnderlineStyle};
+
+    #[test]
+    fn normalize() {
+        let decomposed = "\x1b[1mcafe\u{301} A\u{30a}\u{301}".to_owned();
+        let composed = "\x1b[1mcaf\u{e9} \u{1fa}".to_owned();
+
+        assert_eq!(
+            super::normalize(decomposed.clone(), Normalization::Nfc),
+            composed
+        );
+        assert_eq!(
+            super::normalize(composed.clone(), Normalization::Nfd),
+            decomposed
+        );
+        assert_eq!(
+            super::normalize(decomposed.clone(), Normalization::None),
+            decomposed
+        );
+    }
+
+    #[test]
+    fn substitute() {
+        let clusters = Clusters::default();
+        let data =
+            "a\u{1f1fa}\u{1f1f8}b\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}\x1b[31mc\u{301}";
+        let out = clusters.substitute(data);
+        let chars = out.chars().collect::<Vec<_>>();
+
+        assert_eq!(chars.len(), 13);
+        assert_eq!(chars[0], 'a');
+        assert_eq!(chars[2], SPACER);
+        assert_eq!(chars[3], 'b');
+        assert_eq!(chars[5], SPACER);
+        assert_eq!(
+            clusters.resolve(chars[1]).as_deref(),
+            Some("\u{1f1fa}\u{1f1f8}")
+        );
+        assert_eq!(
+            clusters.resolve(chars[4]).as_deref(),
+            Some("\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}")
+        );
+        assert!(out.ends_with("\x1b[31mc\u{301}"));
+        assert_eq!(clusters.resolve('a'), None);
+        assert_eq!(clusters.substitute("plain"), "plain");
+    }
+
+    #[test]
+    fn substitute_narrow_cluster() {
+        let clusters = Clusters::default();
+        let out = clusters.substitute("\u{e4}\u{301}x");
+        let chars = out.chars().collect::<Vec<_>>();
+
+        assert_eq!(chars.len(), 2);
+        assert_eq!(chars[1], 'x');
+        assert_eq!(clusters.resolve(chars[0]).as_deref(), Some("\u{e4}\u{301}"));
+    }
+
+    #[test]
+    fn separate_tables() {
+        let (a, b) = (Clusters::default(), Clusters::default());
+        let flag = a.substitute("\u{1f1fa}\u{1f1f8}").chars().next().unwrap();
+
+        // the same cluster gets the same placeholder, other tables don't know it
+        assert_eq!(
+            a.substitute("\u{1f1fa}\u{1f1f8}").chars().next(),
+            Some(flag)
+        );
+        assert_eq!(b.resolve(flag), None);
+        assert!(b.clone().substitute("\u{1f1e9}\u{1f1ea}").starts_with(flag));
+        assert_eq!(b.resolve(flag).as_deref(), Some("\u{1f1e9}\u{1f1ea}"));
+    }
+
+    #[test]
+    fn underlined() {
+        let clusters = Clusters::default();
+        let flag = clusters
+            .substitute("\u{1f1fa}\u{1f1f8}")
+            .chars()
+            .next()
+            .unwrap();
+        let a = clusters.underlined('a', UnderlineStyle::Curly);
+        let styled_flag = clusters.underlined(flag, UnderlineStyle::Double);
+
+        assert_ne!(a, 'a');
+        assert_eq!(clusters.resolve(a).as_deref(), Some("a"));
+        assert_eq!(clusters.underline_style(a), Some(UnderlineStyle::Curly));
+        assert_eq!(clusters.underlined('a', UnderlineStyle::Curly), a);
+        assert_eq!(
+            clusters.resolve(styled_flag).as_deref(),
+            Some("\u{1f1fa}\u{1f1f8}")
+        );
+        assert_eq!(
+            clusters.underline_style(styled_flag),
+            Some(UnderlineStyle::Double)
+        );
+        assert_eq!(clusters.underline_style(flag), None);
+        assert_eq!(clusters.underline_style('a'), None);
+    }
+}
@@ -0,0 +1,113 @@
+use std::borrow::Cow;
+
+#[derive(Default)]
+enum State {
+    #[default]
+    Text,
+    Esc,
+    Osc(String),
+    Skip,
+    SkipEsc,
+}
+
+const INLINE_IMAGE_OSC: &str = "1337;";
+
+/// Removes image payloads from terminal output: DCS strings (sixel), APC
+/// strings (Kitty graphics protocol) and OSC 1337 sequences (iTerm2 inline
+/// images). These can be megabytes long and split across many events, so
+/// the parsing state is kept between calls.
+#[derive(Default)]
+pub struct Filter {
+    state: State,
+}
+
+impl Filter {
+    pub fn strip<'a>(&mut self, data: &'a str) -> Cow<'a, str> {
+        if matches!(self.state, State::Text) && !data.contains(['\x1b', '\u{90}', '\u{9f}']) {
+            return Cow::Borrowed(data);
+        }
+
+        let mut out = String::with_capacity(data.len());
+
+        for ch in data.chars() {
+            self.state = match (std::mem::take(&mut self.state), ch) {
+                (State::Text, '\x1b') => State::Esc,
+                (State::Text, '\u{90}' | '\u{9f}') => State::Skip,
+
+                (State::Text, ch) => {
+                    out.push(ch);
+                    State::Text
+                }
+
+                (State::Esc, 'P' | '_') => State::Skip,
+                (State::Esc, ']') => State::Osc(String::new()),
+
+                (State::Esc, '\x1b') => {
+                    out.push('\x1b');
+                    State::Esc
+                }
+
+                (State::Esc, ch) => {
+                    out.push('\x1b');
+                    out.push(ch);
+                    State::Text
+                }
+
+                (State::Osc(mut prefix), ch) => {
+                    prefix.push(ch);
+
+                    if prefix == INLINE_IMAGE_OSC {
+                        State::Skip
+                    } else if INLINE_IMAGE_OSC.starts_with(&prefix) {
+                        State::Osc(prefix)
+                    } else {
+                        out.push_str("\x1b]");
+                        out.push_str(&prefix);
+                        State::Text
+                    }
+                }
+
+                (State::Skip, '\x1b') => State::SkipEsc,
+                (State::Skip, '\x07' | '\u{9c}') => State::Text,
+                (State::Skip, _) => State::Skip,
+
+                (State::SkipEsc, '\\') => State::Text,
+                (State::SkipEsc, '\x1b') => State::SkipEsc,
+                (State::SkipEsc, _) => State::Skip,
+            };
+        }
+
+        Cow::Owned(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+
+    #[test]
+    fn strip() {
+        let mut filter = Filter::default();
+
+        assert_eq!(filter.strip("plain \x1b[1mtext"), "plain \x1b[1mtext");
+        assert_eq!(filter.strip("a\x1bPq#0;2;0;0;0#0~~@@vv@@~~$-\x1b\\b"), "ab");
+        assert_eq!(filter.strip("a\x1b_Gf=100;iVBORw0KGgo=\x1b\\b"), "ab");
+        assert_eq!(filter.strip("a\x1b]1337;File=inline=1:R0lGODlh\x07b"), "ab");
+        assert_eq!(
+            filter.strip("\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\"),
+            "\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\"
+        );
+        assert_eq!(filter.strip("\x1b]133;A\x07"), "\x1b]133;A\x07");
+    }
+
+    #[test]
+    fn strip_split() {
+        let mut filter = Filter::default();
+
+        assert_eq!(filter.strip("a\x1b"), "a");
+        assert_eq!(filter.strip("Pq#0~~"), "");
+        assert_eq!(filter.strip("@@vv\x1b"), "");
+        assert_eq!(filter.strip("\\b\x1b"), "b");
+        assert_eq!(filter.strip("[31mc"), "\x1b[31mc");
+    }
+}
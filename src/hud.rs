@@ -0,0 +1,197 @@
+/// How long (in seconds) a marker caption stays on screen.
+pub const CAPTION_DURATION: f64 = 2.0;
+
+/// Writes frame index and time, in inverse video, over the top-right corner
+/// of the terminal. Debugging aid for correlating glitches with frames.
+pub fn overlay(lines: &mut [Vec<(char, avt::Pen)>], index: usize, time: f64) {
+    if let Some(line) = lines.first_mut() {
+        write_right(line, "7", &format!(" #{index} {time:.3}s "));
+    }
+}
+
+/// Writes the date of the recording (UTC), in inverse video, over the
+/// top-right corner of the terminal.
+pub fn date(lines: &mut [Vec<(char, avt::Pen)>], timestamp: u64) {
+    if let Some(line) = lines.first_mut() {
+        write_right(line, "7", &format!(" {} ", format_date(timestamp)));
+    }
+}
+
+/// Formats a unix timestamp as YYYY-MM-DD (proleptic Gregorian calendar).
+pub fn format_date(timestamp: u64) -> String {
+    // days since 0000-03-01, with years starting in March so leap days come last
+    let days = (timestamp / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Writes a success (green check) or failure (red cross) badge with the exit
+/// code over the bottom-right corner of the terminal.
+pub fn badge(lines: &mut [Vec<(char, avt::Pen)>], exit_code: i32) {
+    let (sgr, mark) = if exit_code == 0 {
+        ("1;97;42", '✔')
+    } else {
+        ("1;97;41", '✘')
+    };
+
+    if let Some(line) = lines.last_mut() {
+        write_right(line, sgr, &format!(" {mark} {exit_code} "));
+    }
+}
+
+/// Writes the window title, in inverse video, centered in a bar over the top
+/// row of the terminal.
+pub fn title(lines: &mut [Vec<(char, avt::Pen)>], title: &str) {
+    if let Some(line) = lines.first_mut() {
+        let text = format!(" {title} ");
+        let len = text.chars().count().min(line.len());
+        let offset = (line.len() - len) / 2;
+        let bar = format!("{}{text}{}", " ".repeat(offset), " ".repeat(line.len()));
+
+        write_at(line, 0, "7", &bar);
+    }
+}
+
+/// Shows the label of the most recent marker, in bold inverse video, over the
+/// bottom-left corner of the terminal for a couple of seconds.
+#[derive(Default)]
+pub struct Captions {
+    current: Option<(String, f64)>,
+}
+
+impl Captions {
+    pub fn overlay(
+        &mut self,
+        lines: &mut [Vec<(char, avt::Pen)>],
+        markers: Vec<String>,
+        time: f64,
+    ) {
+        if !markers.is_empty() {
+            self.current = Some((markers.join(" / "), time));
+        }
+
+        if let Some((label, start)) = &self.current {
+            if time - start < CAPTION_DURATION {
+                if let Some(line) = lines.last_mut() {
+                    write_at(line, 0, "1;7", &format!(" {label} "));
+                }
+            } else {
+                self.current = None;
+            }
+        }
+    }
+}
+
+fn write_right(line: &mut [(char, avt::Pen)], sgr: &str, text: &str) {
+    let offset = line.len() - text.chars().count().min(line.len());
+    write_at(line, offset, sgr, text);
+}
+
+fn write_at(line: &mut [(char, avt::Pen)], offset: usize, sgr: &str, text: &str) {
+    let len = text.chars().count().min(line.len() - offset);
+    let text = text.chars().take(len).collect::<String>();
+
+    // let the VT produce the styled cells so no pen internals are needed
+    let mut vt = avt::Vt::new(len.max(1), 1);
+    vt.feed_str(&format!("\x1b[{sgr}m{text}"));
+    let cells = vt.lines()[0].cells().collect::<Vec<_>>();
+
+    for (cell, hud_cell) in line[offset..].iter_mut().zip(cells) {
+        *cell = hud_cell;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn overlay() {
+        let stdout = [(0.0, "foo".to_owned())];
+        let mut fs =
+            crate::vt::frames(stdout.into_iter(), (20, 2), Default::default()).collect::<Vec<_>>();
+        let lines = &mut fs[0].lines;
+
+        super::overlay(lines, 12, 3.5);
+
+        let text = lines[0].iter().map(|(ch, _)| ch).collect::<String>();
+
+        assert_eq!(text, "foo      #12 3.500s ");
+        assert!(lines[0][10].1.is_inverse());
+        assert!(!lines[0][0].1.is_inverse());
+    }
+
+    #[test]
+    fn title() {
+        let stdout = [(0.0, "foo".to_owned())];
+        let mut fs =
+            crate::vt::frames(stdout.into_iter(), (12, 2), Default::default()).collect::<Vec<_>>();
+        let lines = &mut fs[0].lines;
+
+        super::title(lines, "vim");
+
+        let text = lines[0].iter().map(|(ch, _)| ch).collect::<String>();
+
+        assert_eq!(text, "    vim     ");
+        assert!(lines[0].iter().all(|(_, pen)| pen.is_inverse()));
+        assert!(!lines[1][0].1.is_inverse());
+    }
+
+    #[test]
+    fn captions() {
+        let stdout = [(0.0, "foo".to_owned())];
+        let fs =
+            crate::vt::frames(stdout.into_iter(), (12, 2), Default::default()).collect::<Vec<_>>();
+        let mut captions = super::Captions::default();
+        let text =
+            |lines: &[Vec<(char, avt::Pen)>]| lines[1].iter().map(|(ch, _)| ch).collect::<String>();
+
+        let mut lines = fs[0].lines.clone();
+        captions.overlay(&mut lines, vec!["Step 1".to_owned()], 1.0);
+        assert_eq!(text(&lines), " Step 1     ");
+        assert!(lines[1][0].1.is_inverse());
+        assert!(!lines[1][8].1.is_inverse());
+
+        let mut lines = fs[0].lines.clone();
+        captions.overlay(&mut lines, Vec::new(), 2.5);
+        assert_eq!(text(&lines), " Step 1     ");
+
+        let mut lines = fs[0].lines.clone();
+        captions.overlay(&mut lines, Vec::new(), 3.0);
+        assert_eq!(text(&lines), "            ");
+    }
+
+    #[test]
+    fn format_date() {
+        assert_eq!(super::format_date(0), "1970-01-01");
+        assert_eq!(super::format_date(951_782_400), "2000-02-29");
+        assert_eq!(super::format_date(1_700_000_000), "2023-11-14");
+        assert_eq!(super::format_date(1_709_251_199), "2024-02-29");
+    }
+
+    #[test]
+    fn badge() {
+        let stdout = [(0.0, "foo".to_owned())];
+        let mut fs =
+            crate::vt::frames(stdout.into_iter(), (10, 2), Default::default()).collect::<Vec<_>>();
+        let lines = &mut fs[0].lines;
+
+        super::badge(lines, 127);
+
+        let text = lines[1].iter().map(|(ch, _)| ch).collect::<String>();
+
+        assert_eq!(text, "    ✘ 127 ");
+        assert!(matches!(
+            lines[1][5].1.background(),
+            Some(avt::Color::Indexed(1))
+        ));
+        assert!(lines[1][0].1.background().is_none());
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use imgref::ImgVec;
+use log::debug;
+use rgb::RGBA8;
+
+/// A rendered frame kept in memory: as palette indices (a quarter of the
+/// size) when it has at most 256 distinct colors, as RGBA otherwise.
+#[derive(Clone)]
+pub enum Image {
+    Indexed {
+        palette: Vec<RGBA8>,
+        pixels: ImgVec<u8>,
+    },
+    Rgba(ImgVec<RGBA8>),
+}
+
+impl Image {
+    /// Converts to indexed representation when `indexed` is set and the image
+    /// has few enough colors (anti-aliased text in many colors can have more).
+    pub fn new(image: ImgVec<RGBA8>, indexed: bool) -> Self {
+        if !indexed {
+            return Self::Rgba(image);
+        }
+
+        let mut palette = Vec::new();
+        let mut index = HashMap::new();
+        let mut pixels = Vec::with_capacity(image.width() * image.height());
+
+        for px in image.pixels() {
+            let key = u32::from_ne_bytes([px.r, px.g, px.b, px.a]);
+
+            let i = *index.entry(key).or_insert_with(|| {
+                palette.push(px);
+                palette.len() - 1
+            });
+
+            if i > 255 {
+                break;
+            }
+
+            pixels.push(i as u8);
+        }
+
+        if pixels.len() < image.width() * image.height() {
+            debug!("frame has more than 256 colors, keeping it as RGBA");
+
+            return Self::Rgba(image);
+        }
+
+        Self::Indexed {
+            palette,
+            pixels: ImgVec::new(pixels, image.width(), image.height()),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        match self {
+            Self::Indexed { pixels, .. } => pixels.width(),
+            Self::Rgba(image) => image.width(),
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self {
+            Self::Indexed { pixels, .. } => pixels.height(),
+            Self::Rgba(image) => image.height(),
+        }
+    }
+
+    /// Copies row `y` into `out`, expanding palette indices.
+    pub fn copy_row(&self, y: usize, out: &mut [RGBA8]) {
+        match self {
+            Self::Indexed { palette, pixels } => {
+                let start = y * pixels.stride();
+                let row = &pixels.buf()[start..start + pixels.width()];
+
+                for (px, &i) in out.iter_mut().zip(row) {
+                    *px = palette[i as usize];
+                }
+            }
+
+            Self::Rgba(image) => {
+                let start = y * image.stride();
+                out.copy_from_slice(&image.buf()[start..start + image.width()]);
+            }
+        }
+    }
+
+    pub fn to_rgba(&self) -> ImgVec<RGBA8> {
+        let (width, height) = (self.width(), self.height());
+        let mut buf = vec![RGBA8::default(); width * height];
+
+        for (y, row) in buf.chunks_mut(width).enumerate() {
+            self.copy_row(y, row);
+        }
+
+        ImgVec::new(buf, width, height)
+    }
+
+    pub fn into_rgba(self) -> ImgVec<RGBA8> {
+        match self {
+            Self::Rgba(image) => image,
+            indexed => indexed.to_rgba(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Image;
+    use imgref::ImgVec;
+    use rgb::RGBA8;
+
+    #[test]
+    fn indexed() {
+        let (a, b) = (RGBA8::new(255, 0, 0, 255), RGBA8::new(0, 0, 0, 255));
+        let image = ImgVec::new(vec![a, b, b, a, a, b], 3, 2);
+        let indexed = Image::new(image.clone(), true);
+
+        assert!(matches!(&indexed, Image::Indexed { palette, .. } if palette.len() == 2));
+        assert_eq!(indexed.into_rgba().buf(), image.buf());
+    }
+
+    #[test]
+    fn too_many_colors() {
+        let buf = (0..300)
+            .map(|i| RGBA8::new((i % 256) as u8, (i / 256) as u8, 0, 255))
+            .collect::<Vec<_>>();
+
+        let image = ImgVec::new(buf, 30, 10);
+
+        assert!(matches!(Image::new(image.clone(), true), Image::Rgba(_)));
+        assert!(matches!(Image::new(image, false), Image::Rgba(_)));
+    }
+}
@@ -0,0 +1,151 @@
+use std::fmt::Write;
+use std::io::BufRead;
+
+use crate::asciicast::{self, EventType};
+use crate::hud;
+
+/// Reads a recording without rendering it and describes its header and
+/// events, for deciding render settings and triaging bad recordings. Invalid
+/// events are counted rather than failing the whole report.
+pub fn report<I: BufRead>(input: I) -> Result<String, asciicast::Error> {
+    let (header, events) = asciicast::open(input)?;
+
+    let (mut output, mut input, mut markers, mut resizes, mut other) = (0, 0, 0, 0, 0);
+    let (mut invalid, mut backward) = (0, 0);
+    let mut first_error = None;
+    let mut duration: f64 = 0.0;
+
+    for event in events {
+        let event = match event {
+            Ok(event) => event,
+
+            Err(e) => {
+                invalid += 1;
+                first_error.get_or_insert(e);
+                continue;
+            }
+        };
+
+        if event.time < duration {
+            backward += 1;
+        }
+
+        duration = duration.max(event.time);
+
+        match event.type_ {
+            EventType::Output => output += 1,
+            EventType::Input => input += 1,
+            EventType::Other('m') => markers += 1,
+            EventType::Other('r') => resizes += 1,
+            EventType::Other(_) => other += 1,
+        }
+    }
+
+    let total = output + input + markers + resizes + other;
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    let mut report = String::new();
+
+    let version = header
+        .version
+        .map_or_else(|| "unknown".to_owned(), |v| v.to_string());
+
+    let _ = writeln!(report, "version:             {version}");
+    let (cols, rows) = header.terminal_size;
+    let _ = writeln!(report, "terminal size:       {cols}x{rows}");
+    let _ = writeln!(report, "duration:            {duration:.2}s");
+
+    let _ = writeln!(
+        report,
+        "events:              {total} (output {output}, input {input}, markers {markers}, resizes {resizes}, other {other})"
+    );
+
+    if let Some(e) = first_error {
+        let _ = writeln!(report, "invalid events:      {invalid} (first: {e})");
+    }
+
+    if backward > 0 {
+        let _ = writeln!(
+            report,
+            "backward timestamps: {backward} (see --normalize-timestamps)"
+        );
+    }
+
+    let _ = writeln!(report, "resized:             {}", yes_no(resizes > 0));
+    let _ = writeln!(
+        report,
+        "theme:               {}",
+        yes_no(header.theme.is_some())
+    );
+
+    if let Some(title) = &header.title {
+        let _ = writeln!(report, "title:               {title}");
+    }
+
+    if let Some(timestamp) = header.timestamp {
+        let _ = writeln!(
+            report,
+            "recorded on:         {}",
+            hud::format_date(timestamp)
+        );
+    }
+
+    if let Some(limit) = header.idle_time_limit {
+        let _ = writeln!(report, "idle time limit:     {limit}s");
+    }
+
+    if let Some(code) = header.exit_code {
+        let _ = writeln!(report, "exit code:           {code}");
+    }
+
+    let mut env = header
+        .env
+        .iter()
+        .flatten()
+        .map(|(key, value)| match value.as_str() {
+            Some(value) => format!("{key}={value}"),
+            None => format!("{key}={value}"),
+        })
+        .collect::<Vec<_>>();
+
+    env.sort();
+
+    if !env.is_empty() {
+        let _ = writeln!(report, "env:                 {}", env.join(", "));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn report() {
+        let cast = concat!(
+            "{\"version\": 2, \"width\": 80, \"height\": 24, \"title\": \"demo\", ",
+            "\"env\": {\"TERM\": \"xterm-256color\", \"SHELL\": \"/bin/bash\"}}\n",
+            "[0.5, \"o\", \"foo\"]\n",
+            "[1.0, \"i\", \"x\"]\n",
+            "[1.5, \"m\", \"step 1\"]\n",
+            "[2.0, \"r\", \"100x30\"]\n",
+            "[1.8, \"o\", \"bar\"]\n",
+            "[2.5, \"o\"]\n",
+            "[3.25, \"o\", \"baz\"]\n",
+        );
+
+        let report = super::report(cast.as_bytes()).unwrap();
+
+        assert!(report.contains("version:             2\n"));
+        assert!(report.contains("terminal size:       80x24\n"));
+        assert!(report.contains("duration:            3.25s\n"));
+        assert!(report.contains(
+            "events:              6 (output 3, input 1, markers 1, resizes 1, other 0)\n"
+        ));
+        assert!(report.contains("invalid events:      1 (first: InvalidEventData)\n"));
+        assert!(report.contains("backward timestamps: 1"));
+        assert!(report.contains("resized:             yes\n"));
+        assert!(report.contains("theme:               no\n"));
+        assert!(report.contains("title:               demo\n"));
+        assert!(report.contains("env:                 SHELL=/bin/bash, TERM=xterm-256color\n"));
+        assert!(!report.contains("exit code"));
+    }
+}
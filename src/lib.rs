@@ -1,16 +1,46 @@
 use anyhow::{anyhow, Result};
 use clap::ArgEnum;
-use log::info;
+use imgref::ImgVec;
+use log::{debug, info, warn};
+use rgb::RGBA8;
+use std::collections::HashMap;
 use std::fmt::{Debug, Display};
-use std::io::{BufRead, Write};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
 use std::{iter, thread, time::Instant};
 mod asciicast;
+mod backdrop;
+mod bidi;
+mod color_transform;
+mod comment;
+mod contact_sheet;
+mod emphasis;
 mod events;
 mod fonts;
+mod graphemes;
+mod graphics;
+mod hud;
+mod indexed;
+mod inspect;
+mod pad;
+mod palette;
+mod png;
+mod profile;
 mod renderer;
+mod retime;
+mod scale;
+mod selftest;
+mod svg;
 mod theme;
+mod theme_image;
 mod vt;
 
+pub use renderer::Capabilities;
+
 pub const DEFAULT_FONT_FAMILY: &str =
     "JetBrains Mono,Fira Code,SF Mono,Menlo,Consolas,DejaVu Sans Mono,Liberation Mono";
 pub const DEFAULT_FONT_SIZE: usize = 14;
@@ -20,52 +50,293 @@ pub const DEFAULT_LINE_HEIGHT: f64 = 1.4;
 pub const DEFAULT_NO_LOOP: bool = false;
 pub const DEFAULT_SPEED: f64 = 1.0;
 pub const DEFAULT_IDLE_TIME_LIMIT: f64 = 5.0;
+pub const DEFAULT_TERMINAL_OPACITY: f64 = 0.85;
+pub const DEFAULT_OVERSIZE_WARNING: f64 = 20.0;
+pub const DEFAULT_EMPHASIS_ZOOM: f64 = 2.0;
+
+/// How many rendered frames may wait for the encoder with `--indexed`, where
+/// they're kept as palette indices until gifski takes them.
+const INDEXED_QUEUE_DEPTH: usize = 8;
 
+/// Called with the frame index, total number of frames and the frame's
+/// presentation time (in seconds) as each frame is handed over to the encoder,
+/// for progress reporting by embedders. It runs on the thread that called
+/// `run`, which renders the frames, not on the encoder thread. Fewer frames
+/// than the total are reported when the render is cancelled.
+pub type FrameCallback = Arc<dyn Fn(usize, usize, f64) + Send + Sync>;
+
+#[derive(Clone)]
 pub struct Config {
+    pub allow_clip: bool,
+    pub alpha_mode: AlphaMode,
+    pub aspect: Option<(usize, usize)>,
+    pub auto_fps: bool,
+    pub background_image: Option<String>,
+    pub baseline_offset: f64,
+    pub bidi: bool,
+    pub cancelled: Option<Arc<AtomicBool>>,
+    pub canvas: Option<(usize, usize)>,
+    pub color_transform: Option<ColorTransform>,
+    pub colors: u16,
     pub cols: Option<usize>,
+    pub comment: Option<String>,
+    pub cursor: CursorShape,
+    pub cursor_color: Option<String>,
+    pub cursor_text_color: Option<String>,
+    pub dirty_rects: bool,
+    pub emphasis_region: Option<(usize, usize, usize, usize)>,
+    pub emphasis_zoom: f64,
     pub font_dirs: Vec<String>,
     pub font_family: String,
+    pub font_files: Vec<String>,
     pub font_size: usize,
+    pub fit_to_content: bool,
+    pub flatten: Option<String>,
     pub fps_cap: u8,
+    pub frame_callback: Option<FrameCallback>,
+    pub frame_interval: Option<f64>,
+    pub frame_on_event: bool,
+    pub frame_range: Option<(usize, usize)>,
+    pub hinting: Hinting,
+    pub hud: bool,
     pub idle_time_limit: Option<f64>,
+    pub indexed: bool,
     pub last_frame_duration: f64,
     pub line_height: f64,
+    pub low_memory: bool,
+    pub marker_label: bool,
+    pub max_height: Option<usize>,
+    pub max_idle_frames: Option<usize>,
+    pub max_width: Option<usize>,
+    pub metadata: bool,
+    pub min_frame_duration: Option<f64>,
     pub no_loop: bool,
+    pub normalize: Normalization,
+    pub normalize_timestamps: bool,
+    pub output_last_frame: Option<String>,
+    pub oversize_warning: f64,
+    pub pad_color: Option<String>,
+    pub palette_size: Option<u16>,
+    pub preamble: Option<String>,
+    pub profile: bool,
     pub renderer: Renderer,
+    pub retime: Option<String>,
+    pub reverse: bool,
     pub rows: Option<usize>,
+    pub scale: f64,
+    pub scale_mode: ScaleMode,
+    pub show_date: bool,
+    pub show_exit: bool,
+    pub show_window_title: bool,
+    pub snap_to_grid: bool,
+    pub source: Option<String>,
     pub speed: f64,
+    pub strict: bool,
+    pub supersample: usize,
+    pub terminal_opacity: f64,
     pub theme: Option<Theme>,
+    pub total_duration: Option<f64>,
+    pub underline_style: UnderlineStyle,
+    pub window_title: Option<String>,
     pub show_progress_bar: bool,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            allow_clip: false,
+            alpha_mode: Default::default(),
+            aspect: None,
+            auto_fps: false,
+            background_image: None,
+            baseline_offset: 0.0,
+            bidi: false,
+            cancelled: None,
+            canvas: None,
+            color_transform: None,
+            colors: 256,
             cols: None,
+            comment: None,
+            cursor: Default::default(),
+            cursor_color: None,
+            cursor_text_color: None,
+            dirty_rects: false,
+            emphasis_region: None,
+            emphasis_zoom: DEFAULT_EMPHASIS_ZOOM,
             font_dirs: vec![],
             font_family: String::from(DEFAULT_FONT_FAMILY),
+            font_files: vec![],
             font_size: DEFAULT_FONT_SIZE,
+            fit_to_content: false,
+            flatten: None,
             fps_cap: DEFAULT_FPS_CAP,
+            frame_callback: None,
+            frame_interval: None,
+            frame_on_event: false,
+            frame_range: None,
+            hinting: Default::default(),
+            hud: false,
             idle_time_limit: None,
+            indexed: false,
             last_frame_duration: DEFAULT_LAST_FRAME_DURATION,
             line_height: DEFAULT_LINE_HEIGHT,
+            low_memory: false,
+            marker_label: false,
+            max_height: None,
+            max_idle_frames: None,
+            max_width: None,
+            metadata: true,
+            min_frame_duration: None,
             no_loop: DEFAULT_NO_LOOP,
+            normalize: Default::default(),
+            normalize_timestamps: false,
+            output_last_frame: None,
+            oversize_warning: DEFAULT_OVERSIZE_WARNING,
+            pad_color: None,
+            palette_size: None,
+            preamble: None,
+            profile: false,
             renderer: Default::default(),
+            retime: None,
+            reverse: false,
             rows: None,
+            scale: 1.0,
+            scale_mode: Default::default(),
+            show_date: false,
+            show_exit: false,
+            show_window_title: false,
+            snap_to_grid: false,
+            source: None,
             speed: DEFAULT_SPEED,
+            strict: false,
+            supersample: 1,
+            terminal_opacity: DEFAULT_TERMINAL_OPACITY,
             theme: Default::default(),
+            total_duration: None,
+            underline_style: Default::default(),
+            window_title: None,
             show_progress_bar: true,
         }
     }
 }
 
-#[derive(Clone, ArgEnum, Default)]
+#[derive(Clone, Debug, ArgEnum, Default)]
 pub enum Renderer {
     #[default]
     Fontdue,
     Resvg,
 }
 
+impl Display for Renderer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ArgEnum, Default)]
+pub enum CursorShape {
+    #[default]
+    Block,
+    Underline,
+    Bar,
+}
+
+impl Display for CursorShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ArgEnum, Default)]
+pub enum UnderlineStyle {
+    #[default]
+    Single,
+    Double,
+    Curly,
+    Dotted,
+}
+
+impl Display for UnderlineStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+/// How semi-transparent pixels of PNG output store their color: straight
+/// (standard PNG) or multiplied by alpha.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ArgEnum, Default)]
+pub enum AlphaMode {
+    #[default]
+    Straight,
+    Premultiplied,
+}
+
+impl Display for AlphaMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+/// Unicode normalization form the recorded text is converted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ArgEnum, Default)]
+pub enum Normalization {
+    #[default]
+    None,
+    Nfc,
+    Nfd,
+}
+
+impl Display for Normalization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+/// How output is scaled by `scale` and to fit `max_width`/`max_height`: by
+/// rendering with a proportionally larger or smaller font size, or by
+/// rendering at the unscaled size and resampling the frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ArgEnum, Default)]
+pub enum ScaleMode {
+    #[default]
+    Render,
+    Post,
+}
+
+impl Display for ScaleMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ArgEnum, Default)]
+pub enum Hinting {
+    On,
+    #[default]
+    Off,
+}
+
+impl Display for Hinting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+/// A point in a recording for single-frame rendering: a frame index, or a
+/// time in seconds (after speed and idle time adjustments).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Seek {
+    Frame(usize),
+    Time(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ArgEnum)]
+pub enum ColorTransform {
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+    Grayscale,
+}
+
 #[derive(Clone, Debug, ArgEnum, Default)]
 pub enum Theme {
     Asciinema,
@@ -79,6 +350,52 @@ pub enum Theme {
     Custom(String),
     #[clap(skip)]
     Embedded(theme::Theme),
+    #[clap(skip)]
+    FromImage(String),
+    #[clap(skip)]
+    File(String),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Input(anyhow::Error),
+    Font(String),
+    Render(anyhow::Error),
+    Encode(anyhow::Error),
+    Cancelled,
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Input(_) => 2,
+            Error::Font(_) => 3,
+            Error::Render(_) => 4,
+            Error::Encode(_) => 5,
+            Error::Cancelled => 130,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Input(_) => f.write_str("invalid input"),
+            Error::Font(families) => write!(f, "no faces matching font families {families}"),
+            Error::Render(_) => f.write_str("frame rendering failed"),
+            Error::Encode(_) => f.write_str("GIF encoding failed"),
+            Error::Cancelled => f.write_str("rendering cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Input(e) | Error::Render(e) | Error::Encode(e) => Some(&**e),
+            Error::Font(_) | Error::Cancelled => None,
+        }
+    }
 }
 
 impl TryFrom<Theme> for theme::Theme {
@@ -95,6 +412,8 @@ impl TryFrom<Theme> for theme::Theme {
             SolarizedLight => "fdf6e3,657b83,073642,dc322f,859900,b58900,268bd2,d33682,2aa198,eee8d5,002b36,cb4b16,586e75,657c83,839496,6c71c4,93a1a1,fdf6e3".parse(),
             Custom(t) => t.parse(),
             Embedded(t) => Ok(t),
+            FromImage(path) => theme_image::extract(&path),
+            File(path) => theme::load(&path),
         }
     }
 }
@@ -106,13 +425,36 @@ impl Display for Theme {
         match self {
             Custom(_) => f.write_str("custom"),
             Embedded(_) => f.write_str("embedded"),
+            FromImage(_) => f.write_str("image"),
+            File(_) => f.write_str("file"),
             t => write!(f, "{}", format!("{t:?}").to_lowercase()),
         }
     }
 }
 
-pub fn run<I: BufRead, O: Write + Send>(input: I, output: O, config: Config) -> Result<()> {
-    let (header, events) = asciicast::open(input)?;
+struct Recording {
+    terminal_size: (usize, usize),
+    theme: Option<theme::Theme>,
+    stdout: Vec<(f64, String)>,
+    timestamp: Option<u64>,
+    exit_code: Option<i32>,
+    title: Option<String>,
+    env: Option<HashMap<String, serde_json::Value>>,
+    anomalies: usize,
+}
+
+struct Plan {
+    frames: Frames,
+    settings: renderer::Settings,
+    theme_name: String,
+    timestamp: Option<u64>,
+    exit_code: Option<i32>,
+    anomalies: usize,
+    profile: profile::Profile,
+}
+
+fn load<I: BufRead>(input: I, config: &Config) -> Result<Recording> {
+    let (header, events) = asciicast::open(input).map_err(|e| Error::Input(e.into()))?;
 
     let terminal_size = (
         config.cols.unwrap_or(header.terminal_size.0),
@@ -124,46 +466,370 @@ pub fn run<I: BufRead, O: Write + Send>(input: I, output: O, config: Config) ->
         .or(header.idle_time_limit)
         .unwrap_or(DEFAULT_IDLE_TIME_LIMIT);
 
+    let events = asciicast::inline_markers(events, config.marker_label);
     let stdout = asciicast::stdout(events);
-    let stdout = iter::once((0.0, "".to_owned())).chain(stdout);
+
+    let stdout: Box<dyn Iterator<Item = (f64, String)>> = if config.normalize_timestamps {
+        Box::new(events::monotonic(stdout))
+    } else {
+        Box::new(stdout)
+    };
+
+    // the preamble becomes part of the initial (blank otherwise) screen
+    let preamble = match &config.preamble {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| Error::Input(anyhow!("{path}: {e}")))?
+            .replace("\r\n", "\n")
+            .replace('\n', "\r\n"),
+        None => "".to_owned(),
+    };
+
+    let form = config.normalize;
+
+    let stdout = iter::once((0.0, preamble))
+        .chain(stdout)
+        .map(move |(time, data)| (time, graphemes::normalize(data, form)));
+
+    let segments = match &config.retime {
+        Some(path) => retime::load(path).map_err(Error::Input)?,
+        None => vec![],
+    };
+
+    let stdout = retime::apply(stdout, segments);
+
     let stdout = events::limit_idle_time(stdout, itl);
-    let stdout = events::accelerate(stdout, config.speed);
-    let stdout = events::batch(stdout, config.fps_cap);
-    let stdout = stdout.collect::<Vec<_>>();
-    let count = stdout.len() as u64;
-    let frames = vt::frames(stdout.into_iter(), terminal_size);
+    let stdout = events::accelerate(stdout, config.speed).collect::<Vec<_>>();
+
+    let mut anomalies = 0;
+
+    if let Some(cols) = vt::addressed_cols(stdout.iter().cloned()) {
+        if cols > terminal_size.0 {
+            warn!(
+                "terminal is {} columns wide but content addresses column {}, try --cols {}",
+                terminal_size.0, cols, cols
+            );
+
+            anomalies += 1;
+        }
+    }
+
+    Ok(Recording {
+        terminal_size,
+        theme: header.theme,
+        stdout,
+        timestamp: header.timestamp,
+        exit_code: header.exit_code,
+        title: header.title,
+        env: header.env,
+        anomalies,
+    })
+}
+
+/// Font database and selected families, loaded once and shared by `batch`.
+type Fonts = (fontdb::Database, Vec<String>);
+
+/// Frames of a recording, emulated on demand. Replaying the emulation is
+/// cheap compared to rendering, so the frames can be generated again rather
+/// than all held in memory.
+struct Frames {
+    stdout: Vec<(f64, String)>,
+    terminal_size: (usize, usize),
+    clusters: graphemes::Clusters,
+    bidi: bool,
+    frame_range: Option<(usize, usize)>,
+    content_box: Option<(usize, usize, usize, usize)>,
+    max_idle_frames: Option<usize>,
+    marker_label: bool,
+    reverse: bool,
+}
+
+impl Frames {
+    fn iter(&self) -> Box<dyn Iterator<Item = vt::Frame> + '_> {
+        if self.reverse {
+            return Box::new(self.collect().into_iter());
+        }
+
+        self.emulate()
+    }
+
+    fn collect(&self) -> Vec<vt::Frame> {
+        let frames = self.emulate().collect::<Vec<_>>();
+
+        if self.reverse {
+            vt::reverse(frames)
+        } else {
+            frames
+        }
+    }
+
+    fn emulate(&self) -> Box<dyn Iterator<Item = vt::Frame> + '_> {
+        let stdout = self.stdout.iter().cloned();
+        let frames = vt::frames(stdout, self.terminal_size, self.clusters.clone());
+
+        let frames: Box<dyn Iterator<Item = vt::Frame>> = if self.bidi {
+            Box::new(vt::bidi(frames, self.clusters.clone()))
+        } else {
+            Box::new(frames)
+        };
+
+        let frames: Box<dyn Iterator<Item = vt::Frame>> = match self.frame_range {
+            Some(range) => Box::new(vt::select(frames, range)),
+            None => frames,
+        };
+
+        let frames: Box<dyn Iterator<Item = vt::Frame>> = match self.content_box {
+            Some(rect) => Box::new(vt::crop(frames, rect)),
+            None => frames,
+        };
+
+        let frames: Box<dyn Iterator<Item = vt::Frame>> = match self.max_idle_frames {
+            Some(max) => Box::new(vt::max_idle_frames(frames, max)),
+            None => frames,
+        };
+
+        if self.marker_label {
+            Box::new(vt::expire_markers(frames, hud::CAPTION_DURATION))
+        } else {
+            frames
+        }
+    }
+}
+
+fn plan<I: BufRead>(input: I, config: &Config, fonts: Option<&Fonts>) -> Result<Plan> {
+    let mut profile = profile::Profile::default();
+
+    let Recording {
+        terminal_size,
+        theme,
+        stdout,
+        timestamp,
+        exit_code,
+        anomalies,
+        ..
+    } = profile::measure(&mut profile.parsing, || load(input, config))?;
+
+    if config.show_exit && exit_code.is_none() {
+        warn!("recording has no exit code, not showing exit badge");
+    }
+
+    if config.show_date && timestamp.is_none() {
+        warn!("recording has no timestamp, not showing date");
+    }
+
+    let timing_start = Instant::now();
+
+    let fps_cap = if config.auto_fps {
+        let times = stdout.iter().map(|(time, _)| *time).collect::<Vec<_>>();
+        let fps = events::auto_fps(&times);
+        info!("auto FPS cap: {fps}");
+
+        fps
+    } else {
+        config.fps_cap
+    };
+
+    let stdout = if let Some(interval) = config.frame_interval {
+        events::sample(stdout.into_iter(), interval).collect::<Vec<_>>()
+    } else if fps_cap == 0 {
+        events::exact(stdout.into_iter()).collect::<Vec<_>>()
+    } else if config.frame_on_event {
+        events::coalesce(stdout.into_iter(), fps_cap).collect::<Vec<_>>()
+    } else {
+        events::batch(stdout.into_iter(), fps_cap).collect::<Vec<_>>()
+    };
+
+    let content_box = if config.fit_to_content {
+        vt::content_box(stdout.iter().cloned(), terminal_size)
+    } else {
+        None
+    };
 
-    info!("terminal size: {}x{}", terminal_size.0, terminal_size.1);
+    if let Some(rect) = content_box {
+        info!("cropping to content: {:?}", rect);
+    }
+
+    let clusters = graphemes::Clusters::default();
+
+    let frames = Frames {
+        stdout,
+        terminal_size,
+        clusters: clusters.clone(),
+        bidi: config.bidi,
+        frame_range: config.frame_range,
+        content_box,
+        max_idle_frames: config.max_idle_frames,
+        marker_label: config.marker_label,
+        reverse: config.reverse,
+    };
 
-    let (font_db, font_families) = fonts::init(&config.font_dirs, &config.font_family)
-        .ok_or_else(|| anyhow!("no faces matching font families {}", config.font_family))?;
+    let terminal_size = content_box.map_or(terminal_size, |rect| (rect.2, rect.3));
+    profile.frame_timing = timing_start.elapsed();
 
-    info!("selected font families: {:?}", font_families);
+    let (font_db, font_families) = match fonts {
+        Some(fonts) => fonts.clone(),
+        None => load_fonts(config)?,
+    };
 
     let theme_opt = config
         .theme
-        .or_else(|| header.theme.map(Theme::Embedded))
+        .clone()
+        .or_else(|| theme.map(Theme::Embedded))
         .unwrap_or(Theme::Dracula);
 
-    info!("selected theme: {}", theme_opt);
+    let theme_name = theme_opt.to_string();
+    let mut theme = theme::Theme::try_from(theme_opt).map_err(Error::Input)?;
+    theme.colors = config.colors;
+
+    if let Some(color) = &config.cursor_color {
+        theme.cursor = Some(
+            theme::parse_hex_triplet(color.trim_start_matches('#'))
+                .map_err(|e| Error::Input(anyhow!("invalid cursor color: {e}")))?,
+        );
+    }
+
+    if let Some(color) = &config.cursor_text_color {
+        theme.cursor_text = Some(
+            theme::parse_hex_triplet(color.trim_start_matches('#'))
+                .map_err(|e| Error::Input(anyhow!("invalid cursor text color: {e}")))?,
+        );
+    }
 
     let settings = renderer::Settings {
         terminal_size,
         font_db,
         font_families,
         font_size: config.font_size,
+        hinting: config.hinting == Hinting::On,
+        dirty_rects: config.dirty_rects,
+        snap_to_grid: config.snap_to_grid,
         line_height: config.line_height,
-        theme: theme_opt.try_into()?,
+        baseline_offset: config.baseline_offset,
+        underline_style: config.underline_style,
+        theme,
+        clusters,
     };
 
-    let mut renderer: Box<dyn renderer::Renderer> = match config.renderer {
-        Renderer::Fontdue => Box::new(renderer::fontdue(settings)),
-        Renderer::Resvg => Box::new(renderer::resvg(settings)),
-    };
+    Ok(Plan {
+        frames,
+        settings,
+        theme_name,
+        timestamp,
+        exit_code,
+        anomalies,
+        profile,
+    })
+}
+
+/// Returns the features supported by a renderer backend.
+pub fn capabilities(renderer: &Renderer) -> Capabilities {
+    renderer::capabilities(renderer)
+}
+
+/// Describes the recording's header and events without rendering it.
+pub fn inspect<I: BufRead>(input: I) -> Result<String> {
+    Ok(inspect::report(input).map_err(|e| Error::Input(e.into()))?)
+}
+
+/// Returns the time the recording was made (unix timestamp), if its header
+/// includes it.
+pub fn recorded_at<I: BufRead>(input: I) -> Result<Option<u64>> {
+    let (header, _) = asciicast::open(input).map_err(|e| Error::Input(e.into()))?;
+
+    Ok(header.timestamp)
+}
+
+fn load_fonts(config: &Config) -> Result<Fonts> {
+    fonts::init(&config.font_dirs, &config.font_files, &config.font_family)
+        .ok_or_else(|| Error::Font(config.font_family.clone()).into())
+}
+
+pub fn run<I: BufRead, O: Write + Send>(input: I, output: O, config: Config) -> Result<()> {
+    render_gif(input, output, config, None)
+}
+
+fn render_gif<I: BufRead, O: Write + Send>(
+    input: I,
+    output: O,
+    config: Config,
+    fonts: Option<&Fonts>,
+) -> Result<()> {
+    let Plan {
+        frames,
+        settings,
+        theme_name,
+        timestamp,
+        exit_code,
+        anomalies,
+        mut profile,
+    } = plan(input, &config, fonts)?;
+
+    // in low memory mode the terminal is emulated twice, first only to get
+    // frame times and changes, instead of keeping all frames around until
+    // rendered
+    let (times, changes, emulated): (Vec<f64>, Vec<usize>, Box<dyn Iterator<Item = vt::Frame>>) =
+        if config.low_memory {
+            let (times, changes) = profile::measure(&mut profile.terminal, || {
+                let mut prev = None;
+
+                frames
+                    .iter()
+                    .map(|frame| {
+                        let changed = vt::changed_cells(prev.as_ref(), &frame);
+                        let time = frame.time;
+                        prev = Some(frame);
+
+                        (time, changed)
+                    })
+                    .unzip()
+            });
+
+            (times, changes, frames.iter())
+        } else {
+            let frames = profile::measure(&mut profile.terminal, || frames.collect());
+
+            let (times, changes) = frames
+                .iter()
+                .enumerate()
+                .map(|(i, frame)| {
+                    let prev = i.checked_sub(1).map(|i| &frames[i]);
 
-    let (width, height) = renderer.pixel_size();
+                    (frame.time, vt::changed_cells(prev, frame))
+                })
+                .unzip();
 
-    info!("gif dimensions: {}x{}", width, height);
+            (times, changes, Box::new(frames.into_iter()))
+        };
+
+    let timeline = timeline(&times, &config);
+    let count = timeline.iter().flatten().count();
+    let terminal_size = settings.terminal_size;
+    let font_families = settings.font_families.clone();
+    let mut pipeline = Pipeline::new(&config, settings)?;
+    let canvas_size = pipeline.canvas_size;
+
+    info!(
+        "rendering plan: renderer={}, font_families={:?}, font_size={}px, line_height={}, theme={}, terminal={}x{}, gif={}x{}, frames={}",
+        config.renderer,
+        font_families,
+        config.font_size,
+        config.line_height,
+        theme_name,
+        terminal_size.0,
+        terminal_size.1,
+        canvas_size.0,
+        canvas_size.1,
+        count
+    );
+
+    let estimate = estimate_gif_size(canvas_size, terminal_size, &timeline, &changes) / 1_000_000.0;
+    debug!("estimated GIF size: {estimate:.2}MB");
+
+    if config.oversize_warning > 0.0 && estimate > config.oversize_warning {
+        warn!(
+            "the GIF will likely be around {:.0}MB, to make it smaller try lower --fps-cap, --max-width or --palette-size",
+            estimate
+        );
+    }
 
     let repeat = if config.no_loop {
         gifski::Repeat::Finite(0)
@@ -172,39 +838,132 @@ pub fn run<I: BufRead, O: Write + Send>(input: I, output: O, config: Config) ->
     };
 
     let settings = gifski::Settings {
-        width: Some(width as u32),
-        height: Some(height as u32),
+        width: Some(canvas_size.0 as u32),
+        height: Some(canvas_size.1 as u32),
         fast: true,
         repeat,
         ..Default::default()
     };
 
-    let (collector, writer) = gifski::new(settings)?;
+    let (collector, writer) = gifski::new(settings).map_err(|e| Error::Encode(e.into()))?;
+    let gif_comment = comment_text(&config, &theme_name);
     let start_time = Instant::now();
+    let mut cancelled = false;
 
     thread::scope(|s| {
         let writer_handle = s.spawn(move || {
-            if config.show_progress_bar {
-                let mut pr = gifski::progress::ProgressBar::new(count);
+            let start = Instant::now();
+
+            let output: Box<dyn Write + '_> = match &gif_comment {
+                Some(text) => Box::new(comment::GifComment::new(output, text)),
+                None => Box::new(output),
+            };
+
+            let result = if config.show_progress_bar {
+                let mut pr = gifski::progress::ProgressBar::new(count as u64);
                 let result = writer.write(output, &mut pr);
                 pr.finish();
                 result
             } else {
                 let mut pr = gifski::progress::NoProgress {};
                 writer.write(output, &mut pr)
+            };
+
+            (result, start.elapsed())
+        });
+        let (queue, queue_recv) =
+            mpsc::sync_channel::<(usize, indexed::Image, f64)>(if config.indexed {
+                INDEXED_QUEUE_DEPTH
+            } else {
+                0
+            });
+
+        let feeder_handle = s.spawn(move || {
+            for (i, image, pts) in queue_recv {
+                collector.add_frame_rgba(i, image.into_rgba(), pts)?;
             }
+
+            Ok::<_, gifski::Error>(())
         });
-        for (i, (time, lines, cursor)) in frames.enumerate() {
-            let image = renderer.render(lines, cursor);
-            let time = if i == 0 { 0.0 } else { time };
-            collector.add_frame_rgba(i, image, time + config.last_frame_duration)?;
+
+        let mut overlays = Overlays::new(&config, timestamp, exit_code, count);
+
+        // gifski takes a non-zero timestamp of the first frame as the
+        // duration of the last one, and shifts all the frames back by it
+        let mut pts = timeline
+            .iter()
+            .flatten()
+            .last()
+            .map_or(config.last_frame_duration, |(_, duration)| *duration);
+
+        for (i, (mut frame, (time, duration))) in keep(emulated, timeline).enumerate() {
+            if is_cancelled(&config) {
+                cancelled = true;
+                break;
+            }
+
+            let render_start = Instant::now();
+            let cursor_shape = frame.cursor_shape.unwrap_or(config.cursor);
+            overlays.apply(&mut frame, i, time);
+
+            let image = pipeline.render(frame.lines, frame.cursor, cursor_shape)?;
+            let image = indexed::Image::new(image, config.indexed);
+            profile.rendering += render_start.elapsed();
+            profile.frames += 1;
+
+            let sent = profile::measure(&mut profile.encoder_wait, || queue.send((i, image, pts)));
+
+            // the feeder only hangs up after an error, reported when it's joined
+            if sent.is_err() {
+                break;
+            }
+
+            pts += duration;
+
+            if let Some(callback) = &config.frame_callback {
+                callback(i, count, time);
+            }
+        }
+
+        drop(queue);
+        feeder_handle
+            .join()
+            .unwrap()
+            .map_err(|e| Error::Render(e.into()))?;
+
+        if let (Some(path), false, Some(last)) =
+            (&config.output_last_frame, cancelled, count.checked_sub(1))
+        {
+            let target = Seek::Frame(last);
+            let image = render_frame_at(
+                &frames,
+                &mut pipeline,
+                &config,
+                timestamp,
+                exit_code,
+                target,
+            )?;
+            let png = encode_png(image, &config, &theme_name)?;
+
+            std::fs::write(path, png).map_err(|e| Error::Encode(anyhow!("{path}: {e}")))?;
         }
 
-        drop(collector);
-        writer_handle.join().unwrap()?;
+        let (result, encoding) = writer_handle.join().unwrap();
+        profile.encoding = encoding;
+        result.map_err(|e| Error::Encode(e.into()))?;
         Result::<()>::Ok(())
     })?;
 
+    if config.profile {
+        info!(target: "agg::profile", "{profile}");
+    }
+
+    if cancelled {
+        return Err(Error::Cancelled.into());
+    }
+
+    check_strict(&config, anomalies + pipeline.anomalies())?;
+
     info!(
         "rendering finished in {}s",
         start_time.elapsed().as_secs_f32()
@@ -212,3 +971,1416 @@ pub fn run<I: BufRead, O: Write + Send>(input: I, output: O, config: Config) ->
 
     Ok(())
 }
+
+/// Renders a built-in recording exercising colors, text attributes, box
+/// drawing, wide characters and the cursor, then checks that the resulting
+/// GIF is well-formed and has the expected frame count. Returns a summary.
+pub fn selftest<O: Write>(mut output: O, config: Config) -> Result<String> {
+    let cast = selftest::cast();
+    let expected_frames = plan(cast.as_bytes(), &config, None)?.frames.iter().count();
+    let mut gif = Vec::new();
+    run(cast.as_bytes(), &mut gif, config)?;
+
+    let ((width, height), frames) = selftest::gif_info(&gif).map_err(Error::Encode)?;
+
+    if width == 0 || height == 0 {
+        return Err(Error::Encode(anyhow!("GIF has zero size ({width}x{height})")).into());
+    }
+
+    if frames != expected_frames {
+        return Err(Error::Encode(anyhow!(
+            "GIF has {frames} frames, expected {expected_frames}"
+        ))
+        .into());
+    }
+
+    output
+        .write_all(&gif)
+        .map_err(|e| Error::Encode(e.into()))?;
+
+    Ok(format!(
+        "{}x{} terminal rendered to {width}x{height} GIF with {frames} frames ({} bytes)",
+        selftest::TERMINAL_SIZE.0,
+        selftest::TERMINAL_SIZE.1,
+        gif.len()
+    ))
+}
+
+/// Outcome of `batch`: GIFs written, and recordings which failed to render.
+#[derive(Default)]
+pub struct BatchSummary {
+    pub rendered: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, anyhow::Error)>,
+}
+
+/// Renders every `.cast` file in `input_dir` to a GIF of the same name in
+/// `output_dir`, loading fonts once for all of them. A recording failing to
+/// render doesn't stop the batch, cancellation does.
+pub fn batch(input_dir: &Path, output_dir: &Path, config: Config) -> Result<BatchSummary> {
+    let mut inputs = fs::read_dir(input_dir)
+        .map_err(|e| Error::Input(anyhow!("{}: {e}", input_dir.display())))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "cast"))
+        .collect::<Vec<_>>();
+
+    inputs.sort();
+
+    if inputs.is_empty() {
+        warn!("no .cast files found in {}", input_dir.display());
+    }
+
+    fs::create_dir_all(output_dir).map_err(|e| Error::Encode(e.into()))?;
+    let fonts = load_fonts(&config)?;
+    let mut summary = BatchSummary::default();
+
+    for input in inputs {
+        if is_cancelled(&config) {
+            return Err(Error::Cancelled.into());
+        }
+
+        let mut name = input.file_stem().unwrap_or_default().to_os_string();
+        name.push(".gif");
+        let output = output_dir.join(name);
+
+        info!("rendering {} to {}", input.display(), output.display());
+
+        let config = Config {
+            source: Some(input.display().to_string()),
+            ..config.clone()
+        };
+
+        let result = fs::File::open(&input)
+            .map_err(|e| Error::Input(e.into()).into())
+            .and_then(|file| {
+                let gif = fs::File::create(&output).map_err(|e| Error::Encode(e.into()))?;
+                let result = render_gif(BufReader::new(file), gif, config, Some(&fonts));
+
+                if result.is_err() {
+                    let _ = fs::remove_file(&output);
+                }
+
+                result
+            });
+
+        match result {
+            Ok(()) => summary.rendered.push(output),
+
+            Err(err) if matches!(err.downcast_ref::<Error>(), Some(Error::Cancelled)) => {
+                return Err(err);
+            }
+
+            Err(err) => {
+                warn!("{}: {err:#}", input.display());
+                summary.failed.push((input, err));
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Combines the user comment with a description of how the file was rendered
+/// (unless metadata is disabled).
+fn comment_text(config: &Config, theme_name: &str) -> Option<String> {
+    let metadata = config.metadata.then(|| {
+        let source = config
+            .source
+            .as_ref()
+            .map_or(String::new(), |source| format!(" from {source}"));
+
+        format!(
+            "rendered by agg {}{}: renderer={}, font_size={}px, line_height={}, theme={}, speed={}",
+            env!("CARGO_PKG_VERSION"),
+            source,
+            config.renderer,
+            config.font_size,
+            config.line_height,
+            theme_name,
+            config.speed
+        )
+    });
+
+    match (config.comment.clone(), metadata) {
+        (Some(comment), Some(metadata)) => Some(format!("{comment}\n{metadata}")),
+        (comment, metadata) => comment.or(metadata),
+    }
+}
+
+/// Rough estimate of the GIF file size in bytes, from the number of cells
+/// the shown frames change (`changes` has `vt::changed_cells` for each frame
+/// of the timeline). GIF frames store only the changed areas, at about a
+/// third of a byte per pixel for text, and each frame adds its own palette
+/// and headers.
+fn estimate_gif_size(
+    (width, height): (usize, usize),
+    (cols, rows): (usize, usize),
+    timeline: &[Option<(f64, f64)>],
+    changes: &[usize],
+) -> f64 {
+    let frames = timeline.iter().flatten().count();
+
+    // frames merged into a shown frame change its cells too
+    let changed_cells = timeline
+        .iter()
+        .zip(changes)
+        .scan(0, |pending, (slot, changed)| {
+            *pending += changed;
+            Some(slot.map(|_| std::mem::take(pending)))
+        })
+        .flatten()
+        .sum::<usize>();
+
+    let changed_area = (width * height * changed_cells) as f64 / (cols * rows).max(1) as f64;
+
+    changed_area * 0.34 + frames as f64 * 800.0
+}
+
+/// Returns presentation time and display duration for each of the frames
+/// with the given times, `None` for frames not shown. The first frame is
+/// always shown at 0. Frames falling on the same centisecond are merged into
+/// the last of them, and so are frames shorter than
+/// `config.min_frame_duration` into the next one. Frames ending after
+/// `config.total_duration` aren't shown either, and the last frame shown
+/// lasts until then.
+fn timeline(times: &[f64], config: &Config) -> Vec<Option<(f64, f64)>> {
+    let times = times
+        .iter()
+        .enumerate()
+        .map(|(i, time)| if i == 0 { 0.0 } else { *time })
+        .collect::<Vec<_>>();
+
+    let centiseconds = events::centiseconds(&times);
+    let times = centiseconds.iter().flatten().copied().collect::<Vec<_>>();
+    let timeline = events::durations(&times, config.last_frame_duration);
+
+    let mut shown = match config.min_frame_duration {
+        Some(min) => events::min_duration(&timeline, min),
+        None => timeline.into_iter().map(Some).collect(),
+    }
+    .into_iter();
+
+    let mut timeline = centiseconds
+        .iter()
+        .map(|time| time.and_then(|_| shown.next().flatten()))
+        .collect::<Vec<_>>();
+
+    if let Some(total) = config.total_duration {
+        let mut truncated = false;
+
+        // the first frame is at 0, and is kept even if it ends later
+        for slot in timeline.iter_mut() {
+            if slot.is_some_and(|(time, _)| time > 0.0 && time + config.last_frame_duration > total)
+            {
+                *slot = None;
+                truncated = true;
+            }
+        }
+
+        if truncated {
+            warn!("recording is longer than total duration of {total}s, truncating");
+        }
+
+        // the last frame is held so that the animation ends exactly then
+        if let Some((time, duration)) = timeline.iter_mut().flatten().last() {
+            if total > *time {
+                *duration = total - *time;
+            }
+        }
+    }
+
+    timeline
+}
+
+/// Pairs frames with their timeline entries, dropping frames that aren't
+/// shown and passing their markers on to the next shown frame.
+fn keep<T>(
+    frames: impl Iterator<Item = vt::Frame>,
+    timeline: Vec<Option<T>>,
+) -> impl Iterator<Item = (vt::Frame, T)> {
+    let mut markers = Vec::new();
+
+    frames.zip(timeline).filter_map(move |(mut frame, slot)| {
+        markers.append(&mut frame.markers);
+        let slot = slot?;
+        frame.markers = std::mem::take(&mut markers);
+
+        Some((frame, slot))
+    })
+}
+
+/// Draws the configured overlays (window title, date, frame counter, marker
+/// captions, exit badge) over the shown frames, which have to be passed in
+/// order as captions carry over from frame to frame.
+struct Overlays<'a> {
+    config: &'a Config,
+    timestamp: Option<u64>,
+    exit_code: Option<i32>,
+    count: usize,
+    captions: hud::Captions,
+}
+
+impl<'a> Overlays<'a> {
+    fn new(
+        config: &'a Config,
+        timestamp: Option<u64>,
+        exit_code: Option<i32>,
+        count: usize,
+    ) -> Self {
+        Self {
+            config,
+            timestamp,
+            exit_code,
+            count,
+            captions: hud::Captions::default(),
+        }
+    }
+
+    /// Draws over the `index`th shown frame, presented at `time`.
+    fn apply(&mut self, frame: &mut vt::Frame, index: usize, time: f64) {
+        let config = self.config;
+        let lines = &mut frame.lines;
+
+        if config.show_window_title {
+            if let Some(title) = frame.title.as_ref().or(config.window_title.as_ref()) {
+                hud::title(lines, title);
+            }
+        }
+
+        if let (true, Some(timestamp)) = (config.show_date, self.timestamp) {
+            hud::date(lines, timestamp);
+        }
+
+        if config.hud {
+            hud::overlay(lines, index, time);
+        }
+
+        if config.marker_label {
+            self.captions.overlay(lines, frame.markers.clone(), time);
+        }
+
+        if config.show_exit && index + 1 == self.count {
+            if let Some(code) = self.exit_code {
+                hud::badge(lines, code);
+            }
+        }
+    }
+}
+
+/// Pairs the frames with the timeline like `keep`, returning the shown ones
+/// with their presentation times and the overlays drawn.
+fn shown_frames<'a>(
+    frames: impl Iterator<Item = vt::Frame> + 'a,
+    timeline: Vec<Option<(f64, f64)>>,
+    mut overlays: Overlays<'a>,
+) -> impl Iterator<Item = vt::Frame> + 'a {
+    keep(frames, timeline)
+        .enumerate()
+        .map(move |(i, (mut frame, (time, _)))| {
+            overlays.apply(&mut frame, i, time);
+
+            vt::Frame { time, ..frame }
+        })
+}
+
+/// Renders the shown frame at the target (an index into the frames of the
+/// GIF, or a time on its timeline) through the pipeline, emulating the
+/// terminal just up to it.
+fn render_frame_at(
+    frames: &Frames,
+    pipeline: &mut Pipeline,
+    config: &Config,
+    timestamp: Option<u64>,
+    exit_code: Option<i32>,
+    target: Seek,
+) -> Result<ImgVec<RGBA8>> {
+    let times = frames.iter().map(|frame| frame.time).collect::<Vec<_>>();
+    let timeline = timeline(&times, config);
+    let count = timeline.iter().flatten().count();
+    let overlays = Overlays::new(config, timestamp, exit_code, count);
+    let frame = seek(shown_frames(frames.iter(), timeline, overlays), target)?;
+    let cursor_shape = frame.cursor_shape.unwrap_or(config.cursor);
+
+    pipeline.render(frame.lines, frame.cursor, cursor_shape)
+}
+
+fn new_renderer(backend: &Renderer, settings: renderer::Settings) -> Box<dyn renderer::Renderer> {
+    match backend {
+        Renderer::Fontdue => Box::new(renderer::fontdue(settings)),
+        Renderer::Resvg => Box::new(renderer::resvg(settings)),
+    }
+}
+
+type Region = (usize, usize, usize, usize);
+
+/// Turns terminal screens into output images the same way for every raster
+/// output (GIF frames, stills, contact sheet tiles): rasterized with the
+/// magnified region composited in, downsampled from the supersampled size,
+/// scaled to fit, over the background image, centered on the canvas, color
+/// transformed, palette reduced and finally flattened. `place_svg` mirrors
+/// what of this can be expressed in SVG output.
+struct Pipeline {
+    renderer: Box<dyn renderer::Renderer>,
+    emphasis: Option<(Region, Box<dyn renderer::Renderer>)>,
+    supersample: usize,
+    /// The renderer's output size, divided by `supersample`.
+    pixel_size: (usize, usize),
+    size: (usize, usize),
+    canvas_size: (usize, usize),
+    pad_color: rgb::RGB8,
+    backdrop: Option<ImgVec<RGBA8>>,
+    terminal_opacity: f64,
+    color_transform: Option<ColorTransform>,
+    palette: Option<palette::Palette>,
+    flatten: Option<rgb::RGB8>,
+}
+
+impl Pipeline {
+    fn new(config: &Config, mut settings: renderer::Settings) -> Result<Self> {
+        // render at n times the font size, downsampled to the final size
+        let supersample = config.supersample.max(1);
+        settings.font_size *= supersample;
+
+        let background = settings.theme.background;
+        let terminal_size = settings.terminal_size;
+        let mut renderer = new_renderer(&config.renderer, settings.clone());
+
+        let (w, h) = renderer.pixel_size();
+        let pixel_size = ((w / supersample).max(1), (h / supersample).max(1));
+        let scaled = scale::by(pixel_size, config.scale);
+        let (width, _) = scale::fit(scaled, config.max_width, config.max_height);
+
+        // crisper than resampling, but the size only approximately matches
+        if config.scale_mode == ScaleMode::Render && width != pixel_size.0 {
+            let ratio = if width == scaled.0 {
+                config.scale
+            } else {
+                width as f64 / pixel_size.0 as f64
+            };
+
+            settings.font_size = ((settings.font_size as f64 * ratio).floor() as usize).max(1);
+            debug!("rendering at font size {} to scale", settings.font_size);
+            renderer = new_renderer(&config.renderer, settings.clone());
+        }
+
+        // the magnified region is rendered separately at a larger font size
+        let emphasis = match config.emphasis_region {
+            Some(region) => {
+                let region = emphasis::clamp(region, terminal_size).map_err(Error::Input)?;
+
+                let settings = renderer::Settings {
+                    terminal_size: (region.2, region.3),
+                    font_db: settings.font_db.clone(),
+                    font_families: settings.font_families.clone(),
+                    font_size: (settings.font_size as f64 * config.emphasis_zoom).round() as usize,
+                    theme: settings.theme.clone(),
+                    ..settings
+                };
+
+                Some((region, new_renderer(&config.renderer, settings)))
+            }
+
+            None => None,
+        };
+
+        debug!(
+            "cell size: {:?}, origin: {:?}",
+            renderer.cell_size(),
+            renderer.origin()
+        );
+
+        debug!(
+            "{} renderer capabilities: {:?}",
+            config.renderer,
+            capabilities(&config.renderer)
+        );
+
+        // whatever the font size couldn't do exactly is resampled
+        let (w, h) = renderer.pixel_size();
+        let pixel_size = ((w / supersample).max(1), (h / supersample).max(1));
+
+        let (width, height) = match config.scale_mode {
+            ScaleMode::Render => scale::fit(pixel_size, config.max_width, config.max_height),
+            ScaleMode::Post => scale::fit(scaled, config.max_width, config.max_height),
+        };
+
+        let canvas_size = match (config.canvas, config.aspect) {
+            (Some((w, h)), _) => {
+                if (width > w || height > h) && !config.allow_clip {
+                    return Err(Error::Input(anyhow!(
+                        "rendered terminal is {width}x{height}, larger than the {w}x{h} canvas, try smaller --font-size, --max-width/--max-height or --allow-clip"
+                    ))
+                    .into());
+                }
+
+                (w, h)
+            }
+
+            (None, Some(ratio)) => pad::aspect_size((width, height), ratio),
+            (None, None) => (width, height),
+        };
+
+        let pad_color = match &config.pad_color {
+            Some(color) => theme::parse_hex_triplet(color.trim_start_matches('#'))
+                .map_err(|e| Error::Input(anyhow!("invalid pad color: {e}")))?,
+            None => background,
+        };
+
+        let flatten = config
+            .flatten
+            .as_ref()
+            .map(|color| theme::parse_hex_triplet(color.trim_start_matches('#')))
+            .transpose()
+            .map_err(|e| Error::Input(anyhow!("invalid flatten color: {e}")))?;
+
+        let backdrop = config
+            .background_image
+            .as_ref()
+            .map(|path| backdrop::load(path, (width, height), background))
+            .transpose()
+            .map_err(Error::Input)?;
+
+        let palette = config
+            .palette_size
+            .map(palette::Palette::new)
+            .transpose()
+            .map_err(Error::Render)?;
+
+        Ok(Self {
+            renderer,
+            emphasis,
+            supersample,
+            pixel_size,
+            size: (width, height),
+            canvas_size,
+            pad_color,
+            backdrop,
+            terminal_opacity: config.terminal_opacity,
+            color_transform: config.color_transform,
+            palette,
+            flatten,
+        })
+    }
+
+    fn render(
+        &mut self,
+        lines: Vec<Vec<(char, avt::Pen)>>,
+        cursor: Option<(usize, usize)>,
+        cursor_shape: CursorShape,
+    ) -> Result<ImgVec<RGBA8>> {
+        let zoomed = self.emphasis.as_mut().map(|(region, renderer)| {
+            let (lines, cursor) = emphasis::cut(&lines, cursor, *region);
+
+            renderer.render(lines, cursor, cursor_shape)
+        });
+
+        let mut image = self.renderer.render(lines, cursor, cursor_shape);
+
+        if let (Some(zoomed), Some(((x, y, w, h), _))) = (&zoomed, &self.emphasis) {
+            let (col_width, row_height) = self.renderer.cell_size();
+            let (left, top) = self.renderer.origin();
+            let cx = left + col_width * (*x as f32 + *w as f32 / 2.0);
+            let cy = top + row_height * (*y as f32 + *h as f32 / 2.0);
+
+            emphasis::composite(&mut image, zoomed, (cx, cy));
+        }
+
+        if self.supersample > 1 {
+            image = scale::downsample(&image, self.supersample);
+        }
+
+        if self.size != self.pixel_size {
+            image = scale::resize(&image, self.size);
+        }
+
+        if let Some(backdrop) = &self.backdrop {
+            backdrop::composite(&mut image, backdrop, self.terminal_opacity);
+        }
+
+        if self.canvas_size != self.size {
+            image = pad::center(&image, self.canvas_size, self.pad_color.alpha(255));
+        }
+
+        if let Some(transform) = self.color_transform {
+            color_transform::apply(&mut image, transform);
+        }
+
+        if let Some(palette) = &mut self.palette {
+            image = palette.reduce(image).map_err(Error::Render)?;
+        }
+
+        // last, so that nothing after it can bring transparency back
+        if let Some(color) = self.flatten {
+            backdrop::flatten(&mut image, color);
+        }
+
+        Ok(image)
+    }
+
+    /// Places an SVG document made by the resvg renderer (which must have the
+    /// pipeline's pixel size) like `render` places rasterized frames. Palette
+    /// reduction has no SVG counterpart and the magnified region isn't drawn.
+    fn place_svg(&self, document: &str) -> Result<String> {
+        let backdrop = self
+            .backdrop
+            .as_ref()
+            .map(png::encode)
+            .transpose()
+            .map_err(Error::Render)?;
+
+        let placement = svg::Placement {
+            size: self.size,
+            document_size: self.pixel_size,
+            canvas_size: self.canvas_size,
+            pad_color: self.pad_color,
+            backdrop: backdrop.as_deref(),
+            terminal_opacity: self.terminal_opacity,
+            color_matrix: self.color_transform.map(color_transform::matrix),
+            flatten: self.flatten,
+        };
+
+        Ok(svg::place(document, &placement))
+    }
+
+    fn anomalies(&self) -> usize {
+        self.renderer.anomalies()
+    }
+}
+
+fn check_strict(config: &Config, anomalies: usize) -> Result<()> {
+    if config.strict && anomalies > 0 {
+        return Err(Error::Render(anyhow!(
+            "{anomalies} rendering anomalies found in strict mode, see warnings above"
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+fn is_cancelled(config: &Config) -> bool {
+    config
+        .cancelled
+        .as_ref()
+        .is_some_and(|c| c.load(Ordering::Relaxed))
+}
+
+pub fn run_svg<I: BufRead, O: Write>(input: I, mut output: O, config: Config) -> Result<()> {
+    use renderer::Renderer as _;
+
+    let Plan {
+        frames,
+        settings,
+        timestamp,
+        exit_code,
+        anomalies,
+        ..
+    } = plan(input, &config, None)?;
+
+    // only the placement on the canvas is used, the frames stay vector
+    let pipeline_config = Config {
+        renderer: Renderer::Resvg,
+        supersample: 1,
+        scale_mode: ScaleMode::Post,
+        emphasis_region: None,
+        ..config.clone()
+    };
+
+    let pipeline = Pipeline::new(&pipeline_config, settings.clone())?;
+    let mut renderer = renderer::resvg(settings);
+    let start_time = Instant::now();
+    let frames = frames.collect();
+    let times = frames.iter().map(|frame| frame.time).collect::<Vec<_>>();
+    let timeline = timeline(&times, &config);
+    let count = timeline.iter().flatten().count();
+
+    let duration = timeline
+        .iter()
+        .flatten()
+        .last()
+        .map_or(config.last_frame_duration, |(time, duration)| {
+            time + duration
+        });
+
+    let overlays = Overlays::new(&config, timestamp, exit_code, count);
+
+    let frames = shown_frames(frames.into_iter(), timeline, overlays)
+        .map(|frame| {
+            let cursor_shape = frame.cursor_shape.unwrap_or(config.cursor);
+            let lines = frame.lines;
+
+            // only rasterizing finds text the fonts can't render
+            if config.strict {
+                renderer.render(lines.clone(), frame.cursor, cursor_shape);
+            }
+
+            (
+                frame.time,
+                renderer.frame_svg(lines, frame.cursor, cursor_shape),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    check_strict(&config, anomalies + renderer.anomalies())?;
+
+    let body = svg::animate(&frames, duration, !config.no_loop);
+    let document = pipeline.place_svg(&renderer.document(&body))?;
+
+    output
+        .write_all(document.as_bytes())
+        .map_err(|e| Error::Encode(e.into()))?;
+
+    info!(
+        "rendering finished in {}s",
+        start_time.elapsed().as_secs_f32()
+    );
+
+    Ok(())
+}
+
+pub fn contact_sheet<I: BufRead, O: Write>(
+    input: I,
+    mut output: O,
+    config: Config,
+    (cols, rows): (usize, usize),
+) -> Result<()> {
+    let Plan {
+        frames,
+        settings,
+        theme_name,
+        ..
+    } = plan(input, &config, None)?;
+
+    let mut pipeline = Pipeline::new(&config, settings)?;
+    let frames = frames.collect();
+    let indices = contact_sheet::sample(frames.len(), cols * rows);
+
+    info!(
+        "rendering contact sheet of {} frames out of {}",
+        indices.len(),
+        frames.len()
+    );
+
+    let images = frames
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| indices.contains(i))
+        .map(|(_, frame)| {
+            let cursor_shape = frame.cursor_shape.unwrap_or(config.cursor);
+            let image = pipeline.render(frame.lines, frame.cursor, cursor_shape)?;
+
+            Ok(indexed::Image::new(image, config.indexed))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // transparent gaps, so that the sheet has the tiles' alpha throughout
+    let mut sheet = contact_sheet::compose(&images, cols, RGBA8::new(0, 0, 0, 0));
+
+    if let Some(color) = pipeline.flatten {
+        backdrop::flatten(&mut sheet, color);
+    }
+
+    let png = encode_png(sheet, &config, &theme_name)?;
+
+    output
+        .write_all(&png)
+        .map_err(|e| Error::Encode(e.into()))?;
+
+    Ok(())
+}
+
+/// Renders the screen at each labeled marker to a PNG file in `output_dir`,
+/// for previews of chapters. Returns paths of the written files.
+pub fn chapter_thumbnails<I: BufRead>(
+    input: I,
+    output_dir: &Path,
+    config: Config,
+) -> Result<Vec<PathBuf>> {
+    // markers are only parsed with labels on, while captions are drawn only
+    // if asked for
+    let marked_config = Config {
+        marker_label: true,
+        ..config.clone()
+    };
+
+    let Plan {
+        frames,
+        settings,
+        theme_name,
+        timestamp,
+        exit_code,
+        ..
+    } = plan(input, &marked_config, None)?;
+
+    // no frames clearing captions then either, so that the GIF's frames
+    // aren't followed by extra ones
+    let frames = Frames {
+        marker_label: config.marker_label,
+        ..frames
+    };
+
+    fs::create_dir_all(output_dir).map_err(|e| Error::Encode(e.into()))?;
+
+    let mut pipeline = Pipeline::new(&config, settings)?;
+    let times = frames.iter().map(|frame| frame.time).collect::<Vec<_>>();
+
+    let chapters = keep(frames.iter(), timeline(&times, &config))
+        .enumerate()
+        .filter(|(_, (frame, _))| !frame.markers.is_empty())
+        .map(|(i, (frame, (time, _)))| (i, time, frame.markers))
+        .collect::<Vec<_>>();
+
+    let mut paths = Vec::new();
+
+    for (index, time, markers) in chapters {
+        let target = Seek::Frame(index);
+        let image = render_frame_at(
+            &frames,
+            &mut pipeline,
+            &config,
+            timestamp,
+            exit_code,
+            target,
+        )?;
+        let png = encode_png(image, &config, &theme_name)?;
+
+        // markers hit at once share the screen
+        for label in &markers {
+            let path = output_dir.join(contact_sheet::chapter_name(paths.len() + 1, time, label));
+
+            info!(
+                "rendering marker \"{label}\" at {time:.2}s to {}",
+                path.display()
+            );
+
+            fs::write(&path, &png)
+                .map_err(|e| Error::Encode(anyhow!("{}: {e}", path.display())))?;
+            paths.push(path);
+        }
+    }
+
+    if paths.is_empty() {
+        warn!("recording has no labeled markers, no thumbnails written");
+    }
+
+    Ok(paths)
+}
+
+/// Encodes a still image as PNG in the configured alpha mode, with the
+/// metadata comment.
+fn encode_png(mut image: ImgVec<RGBA8>, config: &Config, theme_name: &str) -> Result<Vec<u8>> {
+    if config.alpha_mode == AlphaMode::Premultiplied {
+        image = png::premultiply(&image);
+    }
+
+    let mut png = png::encode(&image).map_err(Error::Render)?;
+
+    if let Some(text) = comment_text(config, theme_name) {
+        png::add_text(&mut png, "Comment", &text);
+    }
+
+    Ok(png)
+}
+
+pub fn dump_svg_frame<I: BufRead, O: Write>(
+    input: I,
+    mut output: O,
+    config: Config,
+    index: usize,
+) -> Result<()> {
+    let Plan {
+        frames, settings, ..
+    } = plan(input, &config, None)?;
+
+    let frame = seek(frames.iter(), Seek::Frame(index))?;
+    let renderer = renderer::resvg(settings);
+    let cursor_shape = frame.cursor_shape.unwrap_or(config.cursor);
+    let svg = renderer.document(&renderer.frame_svg(frame.lines, frame.cursor, cursor_shape));
+
+    output
+        .write_all(svg.as_bytes())
+        .map_err(|e| Error::Encode(e.into()))?;
+
+    Ok(())
+}
+
+/// Renders the frame of the GIF at the given index or time with the
+/// configured renderer, overlays and post-processing, replaying the recording
+/// up to that point.
+pub fn render_at<I: BufRead>(input: I, config: Config, target: Seek) -> Result<ImgVec<RGBA8>> {
+    let Plan {
+        frames,
+        settings,
+        timestamp,
+        exit_code,
+        ..
+    } = plan(input, &config, None)?;
+
+    let mut pipeline = Pipeline::new(&config, settings)?;
+
+    render_frame_at(
+        &frames,
+        &mut pipeline,
+        &config,
+        timestamp,
+        exit_code,
+        target,
+    )
+}
+
+fn seek(frames: impl Iterator<Item = vt::Frame>, target: Seek) -> Result<vt::Frame> {
+    vt::seek(frames, target).ok_or_else(|| {
+        let e = match target {
+            Seek::Frame(index) => anyhow!("recording has fewer than {} frames", index + 1),
+            Seek::Time(_) => anyhow!("recording has no frames"),
+        };
+
+        Error::Input(e).into()
+    })
+}
+
+/// Writes the recording as an asciicast with timing adjustments (idle time
+/// limit, speed, retiming) applied. Markers are inlined into the output while
+/// those are applied, and turned back into marker events here.
+pub fn write_cast<I: BufRead, O: Write>(input: I, output: O, config: &Config) -> Result<()> {
+    let config = Config {
+        marker_label: true,
+        ..config.clone()
+    };
+
+    let Recording {
+        terminal_size,
+        theme,
+        stdout,
+        timestamp,
+        exit_code,
+        title,
+        env,
+        ..
+    } = load(input, &config)?;
+
+    let theme = match config.theme.clone() {
+        Some(t) => Some(theme::Theme::try_from(t).map_err(Error::Input)?),
+        None => theme,
+    };
+
+    let header = asciicast::Header {
+        version: Some(2),
+        terminal_size,
+        timestamp,
+        // idle time limit has already been applied to the output
+        idle_time_limit: None,
+        theme,
+        exit_code,
+        title,
+        env,
+    };
+
+    let events = asciicast::outline_markers(stdout.into_iter());
+
+    asciicast::write(output, &header, events).map_err(|e| Error::Encode(e.into()))?;
+
+    Ok(())
+}
+
+/// Joins several recordings into a single asciicast, one after another,
+/// separated by `gap` seconds. With `reset` the terminal is reset before each
+/// subsequent recording, otherwise its output continues on the same screen.
+/// The header (theme, idle time limit) of the first recording is used, with
+/// the terminal size large enough for all of them and the exit code of the
+/// last one.
+pub fn concat<I: BufRead>(inputs: Vec<I>, gap: f64, reset: bool) -> Result<Vec<u8>> {
+    let mut header: Option<asciicast::Header> = None;
+    let mut stdout = Vec::new();
+    let mut offset = 0.0;
+
+    for input in inputs {
+        let (next, events) = asciicast::open(input).map_err(|e| Error::Input(e.into()))?;
+
+        header = Some(match header {
+            Some(first) => {
+                offset += gap;
+
+                if reset {
+                    stdout.push((offset, "\x1bc".to_owned()));
+                }
+
+                asciicast::Header {
+                    terminal_size: (
+                        first.terminal_size.0.max(next.terminal_size.0),
+                        first.terminal_size.1.max(next.terminal_size.1),
+                    ),
+                    exit_code: next.exit_code,
+                    ..first
+                }
+            }
+
+            None => next,
+        });
+
+        let mut end = 0.0;
+
+        for (time, data) in asciicast::stdout(events) {
+            end = time;
+            stdout.push((offset + time, data));
+        }
+
+        offset += end;
+    }
+
+    let header = header.ok_or_else(|| Error::Input(anyhow!("no recordings to concatenate")))?;
+    let mut output = Vec::new();
+
+    let events = stdout.into_iter().map(|(time, data)| asciicast::Event {
+        time,
+        type_: asciicast::EventType::Output,
+        data,
+    });
+
+    asciicast::write(&mut output, &header, events).map_err(|e| Error::Encode(e.into()))?;
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, Error};
+
+    /// Config with the font from the fixtures, so that results don't depend
+    /// on the fonts installed on the system.
+    fn config() -> Config {
+        Config {
+            font_dirs: vec![concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures").to_owned()],
+            font_family: "DejaVu Sans Mono".to_owned(),
+            show_progress_bar: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn content_wider_than_header() {
+        let cast = concat!(
+            r#"{"version": 2, "width": 4, "height": 2}"#,
+            "\n",
+            r#"[0.5, "o", "ab\u001b[1;12Hcd"]"#,
+            "\n"
+        );
+
+        let strict = |cols| Config {
+            cols,
+            strict: true,
+            ..config()
+        };
+
+        // rendered clipped, but counted as an anomaly
+        let mut gif = Vec::new();
+        crate::run(cast.as_bytes(), &mut gif, config()).unwrap();
+        assert!(!gif.is_empty());
+
+        let err = crate::run(cast.as_bytes(), Vec::new(), strict(None)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Render(_))
+        ));
+
+        // as suggested by the warning
+        crate::run(cast.as_bytes(), Vec::new(), strict(Some(12))).unwrap();
+    }
+
+    #[test]
+    fn render_at() {
+        use crate::Seek;
+
+        let cast = concat!(
+            r#"{"version": 2, "width": 4, "height": 2}"#,
+            "\n",
+            r#"[0.5, "o", "a"]"#,
+            "\n",
+            r#"[1.0, "o", "b"]"#,
+            "\n"
+        );
+
+        let render = |target| crate::render_at(cast.as_bytes(), config(), target).unwrap();
+
+        // the blank frame at 0 comes first
+        assert_eq!(render(Seek::Frame(1)), render(Seek::Time(0.7)));
+        assert_eq!(render(Seek::Frame(2)), render(Seek::Time(60.0)));
+        assert_ne!(render(Seek::Frame(1)), render(Seek::Frame(2)));
+        assert_ne!(render(Seek::Frame(0)), render(Seek::Frame(1)));
+
+        assert!(crate::render_at(cast.as_bytes(), config(), Seek::Frame(3)).is_err());
+    }
+
+    #[test]
+    fn chapter_thumbnails() {
+        use crate::Seek;
+
+        let cast = concat!(
+            r#"{"version": 2, "width": 8, "height": 2, "exit_code": 1}"#,
+            "\n",
+            r#"[0.5, "o", "a"]"#,
+            "\n",
+            r#"[1.0, "m", "Step 1"]"#,
+            "\n",
+            r#"[2.0, "o", "b"]"#,
+            "\n",
+            r#"[2.0, "m", ""]"#,
+            "\n",
+            r#"[3.0, "m", "Done"]"#,
+            "\n"
+        );
+
+        let config = Config {
+            show_exit: true,
+            ..config()
+        };
+
+        let dir = std::env::temp_dir().join(format!("agg-chapters-{}", std::process::id()));
+        let paths = crate::chapter_thumbnails(cast.as_bytes(), &dir, config.clone()).unwrap();
+        let names = paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["01-step-1.png", "02-done.png"]);
+
+        // the same frames of the GIF as render_at gives, overlays included
+        // (the exit badge on the last one) but without captions
+        for (path, time) in paths.iter().zip([1.0, 3.0]) {
+            let image = crate::render_at(cast.as_bytes(), config.clone(), Seek::Time(time));
+            let png = crate::encode_png(image.unwrap(), &config, "dracula").unwrap();
+
+            assert_eq!(std::fs::read(path).unwrap(), png);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn scale_modes() {
+        use crate::{ScaleMode, Seek};
+
+        let cast = concat!(
+            r#"{"version": 2, "width": 4, "height": 2}"#,
+            "\n",
+            r#"[0.5, "o", "ab"]"#,
+            "\n"
+        );
+
+        let render = |scale, scale_mode, max_width| {
+            let config = Config {
+                scale,
+                scale_mode,
+                max_width,
+                ..config()
+            };
+
+            crate::render_at(cast.as_bytes(), config, Seek::Frame(1)).unwrap()
+        };
+
+        let plain = render(1.0, ScaleMode::Render, None);
+        let (width, height) = (plain.width(), plain.height());
+
+        let post = render(2.0, ScaleMode::Post, None);
+        assert_eq!((post.width(), post.height()), (width * 2, height * 2));
+
+        // at twice the font size, which the cell size only roughly follows
+        let rendered = render(2.0, ScaleMode::Render, None);
+        assert!(rendered.width().abs_diff(width * 2) <= width / 10);
+        assert!(rendered.height().abs_diff(height * 2) <= height / 10);
+        assert_ne!(rendered, post);
+
+        // a smaller font size never exceeds the maximum
+        for scale_mode in [ScaleMode::Render, ScaleMode::Post] {
+            let image = render(2.0, scale_mode, Some(width * 3 / 2));
+            assert!(image.width() <= width * 3 / 2);
+            assert!(image.width() > width);
+        }
+    }
+
+    #[test]
+    fn contact_sheet_gaps() {
+        let cast = concat!(
+            r#"{"version": 2, "width": 4, "height": 2}"#,
+            "\n",
+            r#"[0.5, "o", "a"]"#,
+            "\n"
+        );
+
+        let corner = |flatten| {
+            let mut png = Vec::new();
+            let config = Config {
+                flatten,
+                ..config()
+            };
+            crate::contact_sheet(cast.as_bytes(), &mut png, config, (2, 1)).unwrap();
+            let sheet = tiny_skia::Pixmap::decode_png(&png).unwrap();
+
+            sheet.pixel(0, 0).unwrap().demultiply()
+        };
+
+        assert_eq!(corner(None).alpha(), 0);
+
+        let flattened = corner(Some("#ff0000".to_owned()));
+        assert_eq!(
+            (
+                flattened.red(),
+                flattened.green(),
+                flattened.blue(),
+                flattened.alpha()
+            ),
+            (255, 0, 0, 255)
+        );
+    }
+
+    #[test]
+    fn post_processing() {
+        use crate::{ColorTransform, Seek};
+
+        let cast = concat!(
+            r#"{"version": 2, "width": 4, "height": 2}"#,
+            "\n",
+            r#"[0.5, "o", "\u001b[31ma"]"#,
+            "\n"
+        );
+
+        let plain = crate::render_at(cast.as_bytes(), config(), Seek::Frame(1)).unwrap();
+        let canvas = (plain.width() + 10, plain.height() + 6);
+
+        let config = |post| {
+            if post {
+                Config {
+                    canvas: Some(canvas),
+                    color_transform: Some(ColorTransform::Grayscale),
+                    flatten: Some("#0000ff".to_owned()),
+                    ..config()
+                }
+            } else {
+                config()
+            }
+        };
+
+        let image = crate::render_at(cast.as_bytes(), config(true), Seek::Frame(1)).unwrap();
+
+        assert_eq!((image.width(), image.height()), canvas);
+        assert!(image
+            .pixels()
+            .all(|px| px.r == px.g && px.g == px.b && px.a == 255));
+
+        let sheet_size = |post| {
+            let mut png = Vec::new();
+            crate::contact_sheet(cast.as_bytes(), &mut png, config(post), (1, 1)).unwrap();
+            let sheet = tiny_skia::Pixmap::decode_png(&png).unwrap();
+
+            (sheet.width() as usize, sheet.height() as usize)
+        };
+
+        let (width, height) = sheet_size(false);
+        assert_eq!(sheet_size(true), (width + 10, height + 6));
+
+        let mut svg = Vec::new();
+        crate::run_svg(cast.as_bytes(), &mut svg, config(true)).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+
+        assert!(svg.contains(&format!(r#"width="{}" height="{}""#, canvas.0, canvas.1)));
+        assert!(svg.contains(r#"filter="url(#color-transform)""#));
+        assert!(svg.contains(r##"fill="#0000ff""##));
+        assert!(usvg::Tree::from_str(&svg, &usvg::Options::default()).is_ok());
+    }
+
+    #[test]
+    fn show_exit() {
+        let cast = concat!(
+            r#"{"version": 2, "width": 12, "height": 2, "exit_code": 1}"#,
+            "\n",
+            r#"[0.5, "o", "a"]"#,
+            "\n",
+            r#"[1.0, "o", "b"]"#,
+            "\n",
+            r#"[10.0, "o", "c"]"#,
+            "\n"
+        );
+
+        let config = |show_exit| Config {
+            show_exit,
+            total_duration: Some(5.0),
+            ..config()
+        };
+
+        // the frame at 10s is cut by --total-duration, the one before it
+        // gets the badge
+        let mut svg = Vec::new();
+        crate::run_svg(cast.as_bytes(), &mut svg, config(true)).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        let frames = svg
+            .split(r#"<g display="none">"#)
+            .skip(1)
+            .collect::<Vec<_>>();
+
+        // the blank frame at 0, "a" and "b"
+        assert_eq!(frames.len(), 3);
+        assert!(!frames[0].contains('✘'));
+        assert!(!frames[1].contains('✘'));
+        assert!(frames[2].contains('✘'));
+
+        let dir = std::env::temp_dir().join(format!("agg-show-exit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let last_frame = |show_exit| {
+            let path = dir.join(format!("{show_exit}.png"));
+
+            let config = Config {
+                output_last_frame: Some(path.to_str().unwrap().to_owned()),
+                ..config(show_exit)
+            };
+
+            crate::run(cast.as_bytes(), Vec::new(), config).unwrap();
+            std::fs::read(path).unwrap()
+        };
+
+        assert_ne!(last_frame(true), last_frame(false));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Delays (in centiseconds) of the frames of a GIF.
+    fn gif_delays(gif: &[u8]) -> Vec<u16> {
+        let sub_blocks = |mut pos: usize| {
+            while gif[pos] != 0 {
+                pos += gif[pos] as usize + 1;
+            }
+
+            pos + 1
+        };
+
+        let color_table = |flags: u8| {
+            if flags & 0x80 != 0 {
+                3 << ((flags & 7) + 1)
+            } else {
+                0
+            }
+        };
+        let mut pos = 13 + color_table(gif[10]);
+        let mut delays = vec![];
+
+        loop {
+            match gif[pos] {
+                0x21 => {
+                    if gif[pos + 1] == 0xf9 {
+                        delays.push(u16::from_le_bytes([gif[pos + 4], gif[pos + 5]]));
+                    }
+
+                    pos = sub_blocks(pos + 2);
+                }
+
+                0x2c => pos = sub_blocks(pos + 11 + color_table(gif[pos + 9])),
+                _ => return delays,
+            }
+        }
+    }
+
+    #[test]
+    fn frame_durations() {
+        let cast = concat!(
+            r#"{"version": 2, "width": 4, "height": 2}"#,
+            "\n",
+            r#"[0.5, "o", "a"]"#,
+            "\n",
+            r#"[1.0, "o", "b"]"#,
+            "\n",
+            r#"[2.0, "o", "c"]"#,
+            "\n"
+        );
+
+        let delays = |config| {
+            let mut gif = Vec::new();
+            crate::run(cast.as_bytes(), &mut gif, config).unwrap();
+            gif_delays(&gif)
+        };
+
+        // the blank frame at 0 comes first
+        assert_eq!(delays(config()), vec![50, 50, 100, 300]);
+
+        let config = |total_duration| Config {
+            last_frame_duration: 0.5,
+            total_duration: Some(total_duration),
+            ..config()
+        };
+
+        assert_eq!(delays(config(10.0)), vec![50, 50, 100, 800]);
+        assert_eq!(delays(config(1.8)), vec![50, 50, 80]);
+    }
+
+    #[test]
+    fn indexed_encode_queue() {
+        let cast = concat!(
+            r#"{"version": 2, "width": 4, "height": 2}"#,
+            "\n",
+            r#"[0.5, "o", "\u001b[31ma"]"#,
+            "\n",
+            r#"[1.0, "o", "\u001b[38;2;1;2;3mb"]"#,
+            "\n"
+        );
+
+        let gif = |indexed| {
+            let mut gif = Vec::new();
+            let config = Config {
+                indexed,
+                ..config()
+            };
+            crate::run(cast.as_bytes(), &mut gif, config).unwrap();
+            gif
+        };
+
+        assert_eq!(gif(true), gif(false));
+    }
+
+    #[test]
+    fn estimate_gif_size() {
+        // full screen redraws of random colored text, and typing at a prompt
+        let estimate = |fixture| {
+            let path = format!("{}/fixtures/{fixture}", env!("CARGO_MANIFEST_DIR"));
+            let cast = std::fs::read(path).unwrap();
+            let mut gif = Vec::new();
+            crate::run(&cast[..], &mut gif, config()).unwrap();
+
+            let (_, events) = crate::asciicast::open(&cast[..]).unwrap();
+            let stdout = crate::asciicast::stdout(events);
+            let frames =
+                crate::vt::frames(stdout, (40, 12), Default::default()).collect::<Vec<_>>();
+            let times = frames.iter().map(|frame| frame.time).collect::<Vec<_>>();
+
+            let changes = frames
+                .iter()
+                .enumerate()
+                .map(|(i, frame)| {
+                    crate::vt::changed_cells(i.checked_sub(1).map(|i| &frames[i]), frame)
+                })
+                .collect::<Vec<_>>();
+
+            let canvas_size = (
+                u16::from_le_bytes([gif[6], gif[7]]) as usize,
+                u16::from_le_bytes([gif[8], gif[9]]) as usize,
+            );
+
+            let timeline = super::timeline(&times, &config());
+            let estimate = super::estimate_gif_size(canvas_size, (40, 12), &timeline, &changes);
+
+            (estimate, gif.len() as f64)
+        };
+
+        let (noise, noise_size) = estimate("noise.cast");
+        let (typing, typing_size) = estimate("typing.cast");
+
+        // the real sizes differ by a factor of 20, the estimates should
+        // fall on the same side of a threshold between them
+        let threshold = (noise_size * typing_size).sqrt();
+        assert!(noise > threshold, "{noise} vs {noise_size}");
+        assert!(typing < threshold, "{typing} vs {typing_size}");
+
+        for (estimate, size) in [(noise, noise_size), (typing, typing_size)] {
+            assert!(
+                estimate > size / 2.0 && estimate < size * 2.0,
+                "{estimate} vs {size}"
+            );
+        }
+    }
+}
@@ -1,10 +1,18 @@
 use anyhow::{anyhow, Result};
-use clap::{ArgAction, ArgEnum, Parser};
+use clap::{ArgAction, ArgEnum, CommandFactory, ErrorKind, Parser};
+#[cfg(feature = "net")]
 use reqwest::header;
-use std::io;
-use std::{fs::File, io::BufReader, iter};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{fs, fs::File, iter, process, thread};
 
+#[cfg(feature = "net")]
 static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
 
 #[derive(Clone)]
 pub struct Theme(agg::Theme);
@@ -45,18 +53,34 @@ impl clap::builder::TypedValueParser for ThemeValueParser {
 }
 
 #[derive(Parser)]
-#[clap(author, version, about, long_about = None)]
+#[clap(
+    author,
+    version,
+    about,
+    long_about = None,
+    after_help = "EXIT CODES:\n    1  other error\n    2  invalid input (missing or unparsable asciicast, bad theme)\n    3  no matching font found\n    4  frame rendering failed\n    5  GIF encoding/writing failed\n    130  interrupted (Ctrl-C)"
+)]
 struct Cli {
-    /// asciicast path/filename or URL
-    input_filename: String,
+    /// asciicast path/filename or URL (repeat to concatenate), then GIF path/filename
+    #[clap(value_name = "INPUT_FILENAME... OUTPUT_FILENAME")]
+    filenames: Vec<String>,
 
-    /// GIF path/filename
-    output_filename: String,
+    /// Pause between concatenated recordings (in seconds)
+    #[clap(long, default_value_t = 1.0)]
+    gap: f64,
+
+    /// Reset the terminal between concatenated recordings
+    #[clap(long)]
+    reset_between: bool,
 
     /// Select frame rendering backend
     #[clap(long, arg_enum, default_value_t = agg::Renderer::default())]
     renderer: agg::Renderer,
 
+    /// Re-rasterize only the rows that changed between frames (resvg renderer only)
+    #[clap(long)]
+    dirty_rects: bool,
+
     /// Specify font family
     #[clap(long, default_value_t = String::from(agg::DEFAULT_FONT_FAMILY))]
     font_family: String,
@@ -65,38 +89,205 @@ struct Cli {
     #[clap(long, default_value_t = agg::DEFAULT_FONT_SIZE)]
     font_size: usize,
 
+    /// Round cell width and height to whole pixels, so cells don't shimmer between frames
+    #[clap(long)]
+    snap_to_grid: bool,
+
     /// Specify line height
     #[clap(long, default_value_t = agg::DEFAULT_LINE_HEIGHT)]
     line_height: f64,
 
+    /// Shift glyphs vertically within cells, in ems (e.g. 0.05 moves them down, -0.05 up)
+    #[clap(
+        long,
+        value_name = "EM",
+        default_value_t = 0.0,
+        allow_hyphen_values = true
+    )]
+    baseline_offset: f64,
+
+    /// Select default cursor shape (programs can change it with DECSCUSR)
+    #[clap(long, arg_enum, default_value_t = agg::CursorShape::default())]
+    cursor: agg::CursorShape,
+
+    /// Set cursor color (hex triplet, defaults to inverting the cell under it)
+    #[clap(long)]
+    cursor_color: Option<String>,
+
+    /// Set color of the text under a block cursor (hex triplet)
+    #[clap(long)]
+    cursor_text_color: Option<String>,
+
+    /// Select how plain underlines are drawn (styled ones, e.g. curly, keep their style)
+    #[clap(long, arg_enum, default_value_t = agg::UnderlineStyle::default())]
+    underline_style: agg::UnderlineStyle,
+
     /// Select color theme
     #[clap(long, value_parser = ThemeValueParser)]
     theme: Option<Theme>,
 
+    /// Derive color theme from a PNG image (e.g. album cover)
+    #[clap(long, conflicts_with = "theme")]
+    theme_from_image: Option<String>,
+
+    /// Load color theme from a TOML or YAML file
+    #[clap(long, conflicts_with = "theme")]
+    theme_file: Option<String>,
+
+    /// Number of colors of the recording's terminal (8 has no bright colors, 8 and 16 approximate the 256-color range)
+    #[clap(long, value_name = "8|16|256", default_value_t = 256, value_parser = parse_colors)]
+    colors: u16,
+
     /// Use additional font directory
     #[clap(long)]
     font_dir: Vec<String>,
 
+    /// Use font from a file (takes precedence over --font-family, can be repeated for fallbacks)
+    #[clap(long)]
+    font_file: Vec<String>,
+
+    /// Scale glyphs so the x-height is a whole number of pixels, for sharper small text (fontdue renderer only)
+    #[clap(long, arg_enum, default_value_t = agg::Hinting::default())]
+    hinting: agg::Hinting,
+
+    /// Reorder right-to-left (Hebrew, Arabic) text for display, as bidi-aware terminals do
+    #[clap(long)]
+    bidi: bool,
+
     /// Adjust playback speed
     #[clap(long, default_value_t = agg::DEFAULT_SPEED)]
     speed: f64,
 
+    /// Pre-populate the screen with contents of a file (e.g. a banner or earlier output)
+    #[clap(long, value_name = "FILE")]
+    preamble: Option<String>,
+
+    /// Adjust playback speed per time range, from a file with start-end:speed entries
+    #[clap(long)]
+    retime: Option<String>,
+
+    /// Play the recording backward (shows screen states in reverse order)
+    #[clap(long)]
+    reverse: bool,
+
     /// Disable animation loop
     #[clap(long)]
     no_loop: bool,
 
+    /// Clamp timestamps jumping backward (clock skew) to the previous one
+    #[clap(long)]
+    normalize_timestamps: bool,
+
+    /// Convert text to a Unicode normalization form (nfc fixes decomposed accents from macOS)
+    #[clap(long, arg_enum, default_value_t = agg::Normalization::default())]
+    normalize: agg::Normalization,
+
     /// Limit idle time to max number of seconds [default: 5]
     #[clap(long)]
     idle_time_limit: Option<f64>,
 
-    /// Set FPS cap
-    #[clap(long, default_value_t = agg::DEFAULT_FPS_CAP)]
-    fps_cap: u8,
+    /// Set FPS cap, "auto" picks it from the recording's update rate, 0 means unlimited
+    #[clap(
+        long,
+        alias = "fps",
+        value_name = "FPS_CAP",
+        default_value_t = FpsCap::Fixed(agg::DEFAULT_FPS_CAP),
+        value_parser = parse_fps_cap
+    )]
+    fps_cap: FpsCap,
+
+    /// Emit exactly one frame every N seconds (time-lapse), instead of using FPS cap
+    #[clap(long, value_name = "SECS", value_parser = parse_frame_interval)]
+    frame_interval: Option<f64>,
+
+    /// Emit frames when bursts of output land, coalescing events within one FPS interval
+    #[clap(long)]
+    frame_on_event: bool,
+
+    /// Render only frames with index in START..END (END is exclusive and optional)
+    #[clap(long = "frames", value_name = "START..END", value_parser = parse_frame_range)]
+    frame_range: Option<(usize, usize)>,
+
+    /// Warn when the GIF is estimated to be larger than this (in MB, 0 disables)
+    #[clap(long, value_name = "MB", default_value_t = agg::DEFAULT_OVERSIZE_WARNING)]
+    oversize_warning: f64,
+
+    /// Limit number of colors per frame (2-256), small palettes degrade anti-aliased text
+    #[clap(long, value_parser = clap::value_parser!(u16).range(2..=256))]
+    palette_size: Option<u16>,
+
+    /// Render frames at N times the resolution and downsample for smoother edges
+    #[clap(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=4))]
+    supersample: u8,
+
+    /// Limit consecutive identical frames to N, extending the last one's duration
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_idle_frames: Option<u32>,
+
+    /// Merge frames shorter than this (in milliseconds) into the following frame
+    #[clap(long, value_name = "MS")]
+    min_frame_duration: Option<u64>,
+
+    /// Simulate color vision deficiency (or grayscale) on rendered frames
+    #[clap(long, arg_enum)]
+    color_transform: Option<agg::ColorTransform>,
 
     /// Set last frame duration
     #[clap(long, default_value_t = agg::DEFAULT_LAST_FRAME_DURATION)]
     last_frame_duration: f64,
 
+    /// Make the animation exactly this long (in seconds), holding the last frame or truncating
+    #[clap(long)]
+    total_duration: Option<f64>,
+
+    /// Downscale output to fit max width (in pixels)
+    #[clap(long)]
+    max_width: Option<usize>,
+
+    /// Downscale output to fit max height (in pixels)
+    #[clap(long)]
+    max_height: Option<usize>,
+
+    /// Scale output by the given factor, e.g. 2 for high-DPI screens
+    #[clap(long, value_name = "FACTOR", default_value_t = 1.0, value_parser = parse_scale)]
+    scale: f64,
+
+    /// Apply --scale and --max-width/--max-height by rendering at a scaled font size (render) or by resampling frames rendered at the unscaled size (post)
+    #[clap(long, arg_enum, default_value_t = agg::ScaleMode::default())]
+    scale_mode: agg::ScaleMode,
+
+    /// Pad output to the given aspect ratio, e.g. 16:9 or 1:1
+    #[clap(long, value_name = "W:H", value_parser = parse_aspect)]
+    aspect: Option<(usize, usize)>,
+
+    /// Center output on a canvas of exactly the given size in pixels, e.g. 1280x720
+    #[clap(long, value_name = "WxH", value_parser = parse_canvas, conflicts_with = "aspect")]
+    canvas: Option<(usize, usize)>,
+
+    /// Crop terminal content larger than the --canvas instead of failing
+    #[clap(long, requires = "canvas")]
+    allow_clip: bool,
+
+    /// Set color of the padding added by --aspect or --canvas (hex triplet, defaults to theme background)
+    #[clap(long)]
+    pad_color: Option<String>,
+
+    /// Crop to the area of the terminal actually used by the recording
+    #[clap(long)]
+    fit_to_content: bool,
+
+    /// Show a rectangle of cells magnified over the terminal, e.g. 0,20,40,2 (column,row,width,height)
+    #[clap(long, value_name = "X,Y,W,H", value_parser = parse_region)]
+    emphasis_region: Option<(usize, usize, usize, usize)>,
+
+    /// Font size multiplier for --emphasis-region
+    #[clap(long, default_value_t = agg::DEFAULT_EMPHASIS_ZOOM, requires = "emphasis-region")]
+    emphasis_zoom: f64,
+
+    /// Composite frames over a solid color, removing any transparency (hex triplet)
+    #[clap(long, value_name = "HEX")]
+    flatten: Option<String>,
+
     /// Override terminal width (number of columns)
     #[clap(long)]
     cols: Option<usize>,
@@ -105,11 +296,284 @@ struct Cli {
     #[clap(long)]
     rows: Option<usize>,
 
+    /// Draw PNG image behind the terminal
+    #[clap(long)]
+    background_image: Option<String>,
+
+    /// Set terminal opacity over the background image
+    #[clap(long, default_value_t = agg::DEFAULT_TERMINAL_OPACITY)]
+    terminal_opacity: f64,
+
+    /// Write animated SVG to a file instead of GIF (uses resvg layout)
+    #[clap(long)]
+    output_svg: Option<String>,
+
+    /// Write the SVG of frame N to the output path (or stdout) instead of a GIF, for debugging
+    #[clap(long, value_name = "N")]
+    dump_svg_frame: Option<usize>,
+
+    /// Write a PNG grid of evenly spaced frames (e.g. 4x3) to output path instead of GIF
+    #[clap(long, value_name = "COLSxROWS", value_parser = parse_grid)]
+    contact_sheet: Option<(usize, usize)>,
+
+    /// Write a PNG of the screen at each labeled marker to a directory instead of GIF
+    #[clap(long, value_name = "DIR")]
+    chapter_thumbnails: Option<String>,
+
+    /// Also write the final frame of the GIF as a still PNG
+    #[clap(long, value_name = "PNG_FILENAME")]
+    output_last_frame: Option<String>,
+
+    /// Store semi-transparent pixels of PNG output with straight or premultiplied alpha
+    #[clap(long, arg_enum, default_value_t = agg::AlphaMode::default())]
+    alpha_mode: agg::AlphaMode,
+
+    /// Write post-processed (retimed) asciicast to a file
+    #[clap(long)]
+    write_cast: Option<String>,
+
+    /// Generate frames right before rendering instead of keeping all of them in memory (slower)
+    #[clap(long)]
+    low_memory: bool,
+
+    /// Keep queued and retained frames as 8-bit palette indices when they have at most 256 colors
+    #[clap(long)]
+    indexed: bool,
+
+    /// Fail on rendering anomalies (missing or fallback glyphs, clipped content), for CI
+    #[clap(long)]
+    strict: bool,
+
+    /// Keep partially rendered GIF when interrupted with Ctrl-C
+    #[clap(long)]
+    keep_partial: bool,
+
+    /// Open the result in the system default viewer
+    #[clap(long)]
+    preview: bool,
+
+    /// Re-render whenever the input file changes (until Ctrl-C)
+    #[clap(long)]
+    watch: bool,
+
+    /// Render every .cast file in the input directory to a GIF in the output directory
+    #[clap(
+        long,
+        conflicts_with_all = &[
+            "watch",
+            "output-svg",
+            "dump-svg-frame",
+            "contact-sheet",
+            "chapter-thumbnails",
+            "output-last-frame",
+            "write-cast",
+        ]
+    )]
+    batch: bool,
+
+    /// Skip rendering when the input and options match those of the existing output
+    #[clap(
+        long,
+        conflicts_with_all = &[
+            "batch",
+            "output-svg",
+            "dump-svg-frame",
+            "contact-sheet",
+            "chapter-thumbnails",
+        ]
+    )]
+    skip_if_unchanged: bool,
+
     /// Enable verbose logging
     #[clap(short, long, action = ArgAction::Count)]
     verbose: u8,
+
+    /// Print a timing breakdown of parsing, rendering and encoding at the end
+    #[clap(long)]
+    profile: bool,
+
+    /// Burn frame index and timestamp into each frame (for debugging)
+    #[clap(long)]
+    hud: bool,
+
+    /// Show the date of the recording (from its header) in the top-right corner
+    #[clap(long)]
+    show_date: bool,
+
+    /// Show a success/failure badge with the exit code on the last frame
+    #[clap(long)]
+    show_exit: bool,
+
+    /// Show the label of each marker as a caption for a couple of seconds
+    #[clap(long)]
+    marker_label: bool,
+
+    /// Show the window title set by the recorded programs in a bar covering the top row
+    #[clap(long)]
+    show_window_title: bool,
+
+    /// Title to show with --show-window-title until a program sets one
+    #[clap(long, requires = "show-window-title")]
+    window_title: Option<String>,
+
+    /// Embed a comment in the output file (GIF comment or PNG text chunk)
+    #[clap(long)]
+    comment: Option<String>,
+
+    /// Don't embed agg version, source file and render settings in the output file
+    #[clap(long)]
+    no_metadata: bool,
+
+    /// Print shell completion script and exit
+    #[clap(long, arg_enum, hide = true)]
+    generate_completions: Option<clap_complete::Shell>,
+
+    /// Print features supported by each renderer and exit
+    #[clap(long)]
+    backend_caps: bool,
+
+    /// Print a summary of a recording's header and events without rendering it
+    #[clap(long, value_name = "INPUT_FILENAME", conflicts_with = "filenames")]
+    inspect: Option<String>,
+
+    /// Render a built-in test recording and verify the resulting GIF, optionally keeping it
+    #[clap(
+        long,
+        value_name = "OUTPUT_FILENAME",
+        min_values = 0,
+        max_values = 1,
+        conflicts_with_all = &["filenames", "inspect"]
+    )]
+    selftest: Option<Option<String>>,
+}
+
+fn parse_grid(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("invalid grid {value:?}, expected COLSxROWS, e.g. 4x3");
+    let (cols, rows) = value.split_once('x').ok_or_else(invalid)?;
+    let cols: usize = cols.parse().map_err(|_| invalid())?;
+    let rows: usize = rows.parse().map_err(|_| invalid())?;
+
+    if cols == 0 || rows == 0 {
+        return Err(invalid());
+    }
+
+    Ok((cols, rows))
+}
+
+fn parse_canvas(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("invalid canvas size {value:?}, expected WxH, e.g. 1280x720");
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+    let width: usize = width.parse().map_err(|_| invalid())?;
+    let height: usize = height.parse().map_err(|_| invalid())?;
+
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+
+    Ok((width, height))
 }
 
+fn parse_region(value: &str) -> Result<(usize, usize, usize, usize), String> {
+    let invalid = || format!("invalid region {value:?}, expected X,Y,W,H, e.g. 0,20,40,2");
+
+    let parts = value
+        .split(',')
+        .map(|n| n.trim().parse::<usize>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match parts[..] {
+        [x, y, w, h] if w > 0 && h > 0 => Ok((x, y, w, h)),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_colors(value: &str) -> Result<u16, String> {
+    match value {
+        "8" => Ok(8),
+        "16" => Ok(16),
+        "256" => Ok(256),
+        _ => Err(format!(
+            "invalid number of colors {value:?}, expected 8, 16 or 256"
+        )),
+    }
+}
+
+fn parse_frame_range(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("invalid frame range {value:?}, expected START..END, e.g. 400..420");
+    let (start, end) = value.split_once("..").ok_or_else(invalid)?;
+    let start: usize = start.parse().map_err(|_| invalid())?;
+
+    let end: usize = if end.is_empty() {
+        usize::MAX
+    } else {
+        end.parse().map_err(|_| invalid())?
+    };
+
+    if end <= start {
+        return Err(invalid());
+    }
+
+    Ok((start, end))
+}
+
+fn parse_aspect(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("invalid aspect ratio {value:?}, expected W:H, e.g. 16:9");
+    let (w, h) = value.split_once(':').ok_or_else(invalid)?;
+    let w: usize = w.parse().map_err(|_| invalid())?;
+    let h: usize = h.parse().map_err(|_| invalid())?;
+
+    if w == 0 || h == 0 {
+        return Err(invalid());
+    }
+
+    Ok((w, h))
+}
+
+fn parse_frame_interval(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs > 0.0 => Ok(secs),
+        _ => Err(format!(
+            "invalid frame interval {value:?}, expected a number of seconds greater than 0"
+        )),
+    }
+}
+
+fn parse_scale(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(factor) if factor.is_finite() && factor > 0.0 && factor <= 8.0 => Ok(factor),
+        _ => Err(format!(
+            "invalid scale {value:?}, expected a number greater than 0, up to 8"
+        )),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FpsCap {
+    Fixed(u8),
+    Auto,
+}
+
+impl std::fmt::Display for FpsCap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FpsCap::Fixed(fps) => write!(f, "{fps}"),
+            FpsCap::Auto => f.write_str("auto"),
+        }
+    }
+}
+
+fn parse_fps_cap(value: &str) -> Result<FpsCap, String> {
+    if value == "auto" {
+        return Ok(FpsCap::Auto);
+    }
+
+    value
+        .parse::<u8>()
+        .map(FpsCap::Fixed)
+        .map_err(|_| format!("invalid FPS cap {value:?}, expected a number (0-255) or \"auto\""))
+}
+
+#[cfg(feature = "net")]
 fn download(url: &str) -> Result<impl io::Read> {
     let client = reqwest::blocking::Client::builder()
         .user_agent(USER_AGENT)
@@ -139,6 +603,13 @@ fn download(url: &str) -> Result<impl io::Read> {
     Ok(Box::new(response))
 }
 
+#[cfg(not(feature = "net"))]
+fn download(url: &str) -> Result<io::Empty> {
+    Err(anyhow!(
+        "can't fetch {url}, agg was built without the net feature"
+    ))
+}
+
 fn reader(path: &str) -> Result<Box<dyn io::Read>> {
     if path == "-" {
         Ok(Box::new(io::stdin()))
@@ -149,38 +620,815 @@ fn reader(path: &str) -> Result<Box<dyn io::Read>> {
     }
 }
 
-fn main() -> Result<()> {
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("Error: {err:?}");
+
+        let code = err
+            .downcast_ref::<agg::Error>()
+            .map_or(1, |e| e.exit_code());
+
+        process::exit(code);
+    }
+}
+
+fn print_backend_caps() {
+    let renderers = agg::Renderer::value_variants();
+    let caps = renderers.iter().map(agg::capabilities).collect::<Vec<_>>();
+
+    print!("{:<20}", "feature");
+
+    for renderer in renderers {
+        print!("{:<10}", renderer.to_string());
+    }
+
+    println!();
+
+    for (i, (name, _)) in caps[0].features().iter().enumerate() {
+        print!("{name:<20}");
+
+        for c in &caps {
+            print!("{:<10}", if c.features()[i].1 { "yes" } else { "no" });
+        }
+
+        println!();
+    }
+}
+
+fn preview(path: &str) {
+    if let Err(e) = opener::open(path) {
+        eprintln!("warning: can't open {path} for preview: {e}");
+    }
+}
+
+fn required<T>(value: Option<T>, name: &str) -> T {
+    value.unwrap_or_else(|| {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                format!("<{name}> argument is required"),
+            )
+            .exit()
+    })
+}
+
+fn run() -> Result<()> {
     let cli = Cli::parse();
 
+    if let Some(shell) = cli.generate_completions {
+        let mut cmd = Cli::command();
+        clap_complete::generate(shell, &mut cmd, env!("CARGO_PKG_NAME"), &mut io::stdout());
+
+        return Ok(());
+    }
+
+    if cli.backend_caps {
+        print_backend_caps();
+
+        return Ok(());
+    }
+
+    if cli.frame_interval.is_some() && cli.frame_on_event {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--frame-interval can't be used with --frame-on-event",
+            )
+            .exit();
+    }
+
+    if cli.theme_file.is_some() && cli.theme_from_image.is_some() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--theme-file can't be used with --theme-from-image",
+            )
+            .exit();
+    }
+
+    if cli.gap < 0.0 {
+        Cli::command()
+            .error(ErrorKind::InvalidValue, "--gap can't be negative")
+            .exit();
+    }
+
+    if cli.emphasis_zoom <= 0.0 {
+        Cli::command()
+            .error(
+                ErrorKind::InvalidValue,
+                "--emphasis-zoom must be greater than 0",
+            )
+            .exit();
+    }
+
+    if let Some(total) = cli.total_duration {
+        if total <= 0.0 {
+            Cli::command()
+                .error(
+                    ErrorKind::InvalidValue,
+                    "--total-duration must be greater than 0",
+                )
+                .exit();
+        }
+    }
+
     let log_level = match cli.verbose {
-        0 => "error",
+        0 => "error,agg=warn",
         1 => "info",
         _ => "debug",
     };
 
+    // the --profile report is logged, make sure it's shown
+    let log_level = if cli.profile {
+        format!("{log_level},agg::profile=info")
+    } else {
+        log_level.to_owned()
+    };
+
     let env = env_logger::Env::default().default_filter_or(log_level);
     env_logger::Builder::from_env(env)
         .format_timestamp(None)
         .init();
 
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let flag = cancelled.clone();
+
+    ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::SeqCst) {
+            process::exit(130);
+        }
+    })?;
+
+    if let Some(input) = &cli.inspect {
+        let reader = reader(input).map_err(agg::Error::Input)?;
+        print!("{}", agg::inspect(io::BufReader::new(reader))?);
+
+        return Ok(());
+    }
+
+    if let Some(output) = cli.selftest {
+        let config = agg::Config {
+            cancelled: Some(cancelled),
+            show_progress_bar: false,
+            ..Default::default()
+        };
+
+        let summary = match output {
+            Some(path) => {
+                let output = File::create(path).map_err(|e| agg::Error::Encode(e.into()))?;
+                agg::selftest(output, config)?
+            }
+
+            None => agg::selftest(io::sink(), config)?,
+        };
+
+        println!("selftest passed: {summary}");
+
+        return Ok(());
+    }
+
+    let mut filenames = cli.filenames;
+
+    // with more than one path, the last one is the output
+    let output_filename = if filenames.len() > 1 {
+        filenames.pop()
+    } else {
+        None
+    };
+
+    if filenames.is_empty() {
+        required::<String>(None, "INPUT_FILENAME");
+    }
+
     let config = agg::Config {
+        allow_clip: cli.allow_clip,
+        alpha_mode: cli.alpha_mode,
+        aspect: cli.aspect,
+        background_image: cli.background_image,
+        baseline_offset: cli.baseline_offset,
+        bidi: cli.bidi,
+        cancelled: Some(cancelled.clone()),
+        canvas: cli.canvas,
+        color_transform: cli.color_transform,
+        colors: cli.colors,
         cols: cli.cols,
+        comment: cli.comment,
+        cursor: cli.cursor,
+        cursor_color: cli.cursor_color,
+        cursor_text_color: cli.cursor_text_color,
+        dirty_rects: cli.dirty_rects,
+        emphasis_region: cli.emphasis_region,
+        emphasis_zoom: cli.emphasis_zoom,
         font_dirs: cli.font_dir,
         font_family: cli.font_family,
+        font_files: cli.font_file,
         font_size: cli.font_size,
-        fps_cap: cli.fps_cap,
+        fit_to_content: cli.fit_to_content,
+        flatten: cli.flatten,
+        fps_cap: match cli.fps_cap {
+            FpsCap::Fixed(fps) => fps,
+            FpsCap::Auto => agg::DEFAULT_FPS_CAP,
+        },
+        auto_fps: matches!(cli.fps_cap, FpsCap::Auto),
+        frame_callback: None,
+        frame_interval: cli.frame_interval,
+        frame_on_event: cli.frame_on_event,
+        frame_range: cli.frame_range,
+        hinting: cli.hinting,
+        hud: cli.hud,
         idle_time_limit: cli.idle_time_limit,
+        indexed: cli.indexed,
         last_frame_duration: cli.last_frame_duration,
         line_height: cli.line_height,
+        low_memory: cli.low_memory,
+        marker_label: cli.marker_label,
+        max_height: cli.max_height,
+        max_idle_frames: cli.max_idle_frames.map(|n| n as usize),
+        max_width: cli.max_width,
+        metadata: !cli.no_metadata,
+        min_frame_duration: cli.min_frame_duration.map(|ms| ms as f64 / 1000.0),
         no_loop: cli.no_loop,
+        normalize: cli.normalize,
+        normalize_timestamps: cli.normalize_timestamps,
+        output_last_frame: cli.output_last_frame,
+        oversize_warning: cli.oversize_warning,
+        pad_color: cli.pad_color,
+        palette_size: cli.palette_size,
+        preamble: cli.preamble,
+        profile: cli.profile,
         renderer: cli.renderer,
+        retime: cli.retime,
+        reverse: cli.reverse,
         rows: cli.rows,
+        scale: cli.scale,
+        scale_mode: cli.scale_mode,
+        show_date: cli.show_date,
+        show_exit: cli.show_exit,
+        show_window_title: cli.show_window_title,
+        snap_to_grid: cli.snap_to_grid,
+        source: Some(filenames.join(", ")),
         speed: cli.speed,
-        theme: cli.theme.map(|theme| theme.0),
+        strict: cli.strict,
+        supersample: cli.supersample as usize,
+        terminal_opacity: cli.terminal_opacity,
+        theme: cli
+            .theme_file
+            .map(agg::Theme::File)
+            .or(cli.theme_from_image.map(agg::Theme::FromImage))
+            .or(cli.theme.map(|theme| theme.0)),
+        total_duration: cli.total_duration,
+        underline_style: cli.underline_style,
+        window_title: cli.window_title,
         show_progress_bar: true,
     };
 
-    let input = BufReader::new(reader(&cli.input_filename)?);
-    let mut output = File::create(&cli.output_filename)?;
-    agg::run(input, &mut output, config)
+    if cli.batch {
+        let (input_dir, output_dir) = match (&filenames[..], &output_filename) {
+            ([input_dir], Some(output_dir)) => (input_dir, output_dir),
+            _ => Cli::command()
+                .error(
+                    ErrorKind::WrongNumberOfValues,
+                    "--batch takes an input directory and an output directory",
+                )
+                .exit(),
+        };
+
+        let summary = agg::batch(Path::new(input_dir), Path::new(output_dir), config)?;
+
+        for (path, err) in &summary.failed {
+            eprintln!("failed: {}: {err:#}", path.display());
+        }
+
+        eprintln!(
+            "{} rendered, {} failed",
+            summary.rendered.len(),
+            summary.failed.len()
+        );
+
+        if !summary.failed.is_empty() {
+            return Err(anyhow!(
+                "{} recordings failed to render",
+                summary.failed.len()
+            ));
+        }
+
+        return Ok(());
+    }
+
+    if cli.watch {
+        if let Some(path) = filenames
+            .iter()
+            .find(|path| *path == "-" || path.contains("://"))
+        {
+            Cli::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!("--watch needs local input files, can't watch {path}"),
+                )
+                .exit();
+        }
+    }
+
+    let render = || -> Result<Option<String>> {
+        let config = config.clone();
+
+        let inputs = filenames
+            .iter()
+            .map(|path| {
+                let mut input = Vec::new();
+
+                reader(path)
+                    .and_then(|mut r| Ok(r.read_to_end(&mut input)?))
+                    .map_err(agg::Error::Input)?;
+
+                Ok(input)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let input = if inputs.len() > 1 {
+            agg::concat(
+                inputs.iter().map(|input| &input[..]).collect(),
+                cli.gap,
+                cli.reset_between,
+            )?
+        } else {
+            inputs.into_iter().next().unwrap()
+        };
+
+        if let Some(path) = &cli.write_cast {
+            let output = File::create(path).map_err(|e| agg::Error::Encode(e.into()))?;
+            agg::write_cast(&input[..], output, &config)?;
+
+            if output_filename.is_none() && cli.output_svg.is_none() {
+                return Ok(None);
+            }
+        }
+
+        if let Some(index) = cli.dump_svg_frame {
+            match &output_filename {
+                Some(path) => {
+                    let output = File::create(path).map_err(|e| agg::Error::Encode(e.into()))?;
+                    agg::dump_svg_frame(&input[..], output, config, index)?;
+                }
+
+                None => {
+                    agg::dump_svg_frame(&input[..], io::stdout().lock(), config, index)?;
+                }
+            }
+
+            return Ok(None);
+        }
+
+        if let Some(dir) = &cli.chapter_thumbnails {
+            let paths = agg::chapter_thumbnails(&input[..], Path::new(dir), config)?;
+            eprintln!("wrote {} chapter thumbnails to {dir}", paths.len());
+
+            return Ok(None);
+        }
+
+        let output_filename = if let Some(grid) = cli.contact_sheet {
+            let path = required(output_filename.clone(), "OUTPUT_FILENAME");
+
+            write_output(&path, false, |output| {
+                agg::contact_sheet(&input[..], output, config, grid)
+            })?;
+
+            path
+        } else if let Some(path) = &cli.output_svg {
+            write_output(path, false, |output| {
+                agg::run_svg(&input[..], output, config)
+            })?;
+
+            path.clone()
+        } else {
+            let path = required(output_filename.clone(), "OUTPUT_FILENAME");
+            let hash_path = format!("{path}.hash");
+            let hash = fingerprint(&input, &config);
+
+            if cli.skip_if_unchanged
+                && Path::new(&path).exists()
+                && fs::read_to_string(&hash_path).is_ok_and(|stored| stored.trim() == hash)
+            {
+                eprintln!("{path} is up to date, skipping");
+
+                return Ok(Some(path));
+            }
+
+            write_output(&path, cli.keep_partial, |output| {
+                agg::run(&input[..], output, config)
+            })?;
+
+            if cli.skip_if_unchanged {
+                fs::write(&hash_path, format!("{hash}\n"))
+                    .map_err(|e| agg::Error::Encode(e.into()))?;
+            }
+
+            path
+        };
+
+        Ok(Some(output_filename))
+    };
+
+    if !cli.watch {
+        if let (Some(path), true) = (render()?, cli.preview) {
+            preview(&path);
+        }
+
+        return Ok(());
+    }
+
+    let mut previewed = false;
+
+    loop {
+        match render() {
+            Ok(Some(path)) => {
+                eprintln!("rendered {path}");
+
+                if cli.preview && !previewed {
+                    preview(&path);
+                    previewed = true;
+                }
+            }
+
+            Ok(None) => {}
+            Err(err) => eprintln!("Error: {err:?}"),
+        }
+
+        eprintln!(
+            "watching {} for changes, press Ctrl-C to exit",
+            filenames.join(", ")
+        );
+
+        if !wait_for_change(&filenames, &cancelled) {
+            return Ok(());
+        }
+    }
+}
+
+/// Hashes the input recording together with the agg version and command line
+/// arguments, which determine the rendered output. A different hash (e.g.
+/// after upgrading the Rust toolchain) only causes an unnecessary re-render.
+/// Writes output through a temporary file next to `path`, renamed to `path`
+/// only when `write` succeeds, so that a failed render (e.g. in strict mode)
+/// doesn't leave a file behind, nor replace an earlier one. A cancelled
+/// render's partial output is kept when `keep_partial` is set.
+fn write_output(
+    path: &str,
+    keep_partial: bool,
+    write: impl FnOnce(&mut File) -> Result<()>,
+) -> Result<()> {
+    let tmp_path = format!("{path}.tmp");
+    let mut output = File::create(&tmp_path).map_err(|e| agg::Error::Encode(e.into()))?;
+    let result = write(&mut output);
+    drop(output);
+
+    let keep = match &result {
+        Ok(()) => true,
+        Err(err) => {
+            keep_partial
+                && matches!(
+                    err.downcast_ref::<agg::Error>(),
+                    Some(agg::Error::Cancelled)
+                )
+        }
+    };
+
+    if keep {
+        fs::rename(&tmp_path, path).map_err(|e| agg::Error::Encode(e.into()))?;
+    } else {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    result
+}
+
+/// 64-bit FNV-1a, stable across Rust versions and platforms unlike std's
+/// `DefaultHasher`, so stored fingerprints stay valid.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    /// Hashes the length first, so that consecutive fields can't run into
+    /// each other.
+    fn write(&mut self, bytes: &[u8]) {
+        for b in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// Identifies a GIF for `--skip-if-unchanged`: the agg version, the
+/// recording, every option affecting the output (however it was spelled on
+/// the command line) and the contents of the files they refer to.
+fn fingerprint(input: &[u8], config: &agg::Config) -> String {
+    // exhaustive, so that new options have to be considered here
+    let agg::Config {
+        allow_clip,
+        alpha_mode,
+        aspect,
+        auto_fps,
+        background_image,
+        baseline_offset,
+        bidi,
+        cancelled: _,
+        canvas,
+        color_transform,
+        colors,
+        cols,
+        comment,
+        cursor,
+        cursor_color,
+        cursor_text_color,
+        dirty_rects,
+        emphasis_region,
+        emphasis_zoom,
+        font_dirs,
+        font_family,
+        font_files,
+        font_size,
+        fit_to_content,
+        flatten,
+        fps_cap,
+        frame_callback: _,
+        frame_interval,
+        frame_on_event,
+        frame_range,
+        hinting,
+        hud,
+        idle_time_limit,
+        indexed: _,
+        last_frame_duration,
+        line_height,
+        low_memory: _,
+        marker_label,
+        max_height,
+        max_idle_frames,
+        max_width,
+        metadata,
+        min_frame_duration,
+        no_loop,
+        normalize,
+        normalize_timestamps,
+        output_last_frame,
+        oversize_warning: _,
+        pad_color,
+        palette_size,
+        preamble,
+        profile: _,
+        renderer,
+        retime,
+        reverse,
+        rows,
+        scale,
+        scale_mode,
+        show_date,
+        show_exit,
+        show_window_title,
+        snap_to_grid,
+        source,
+        speed,
+        strict: _,
+        supersample,
+        terminal_opacity,
+        theme,
+        total_duration,
+        underline_style,
+        window_title,
+        show_progress_bar: _,
+    } = config;
+
+    let options: [&dyn std::fmt::Debug; 64] = [
+        allow_clip,
+        alpha_mode,
+        aspect,
+        auto_fps,
+        background_image,
+        baseline_offset,
+        bidi,
+        canvas,
+        color_transform,
+        colors,
+        cols,
+        comment,
+        cursor,
+        cursor_color,
+        cursor_text_color,
+        dirty_rects,
+        emphasis_region,
+        emphasis_zoom,
+        font_dirs,
+        font_family,
+        font_files,
+        font_size,
+        fit_to_content,
+        flatten,
+        fps_cap,
+        frame_interval,
+        frame_on_event,
+        frame_range,
+        hinting,
+        hud,
+        idle_time_limit,
+        last_frame_duration,
+        line_height,
+        marker_label,
+        max_height,
+        max_idle_frames,
+        max_width,
+        metadata,
+        min_frame_duration,
+        no_loop,
+        normalize,
+        normalize_timestamps,
+        output_last_frame,
+        pad_color,
+        palette_size,
+        preamble,
+        renderer,
+        retime,
+        reverse,
+        rows,
+        scale,
+        scale_mode,
+        show_date,
+        show_exit,
+        show_window_title,
+        snap_to_grid,
+        source,
+        speed,
+        supersample,
+        terminal_opacity,
+        theme,
+        total_duration,
+        underline_style,
+        window_title,
+    ];
+
+    let theme_file = match theme {
+        Some(agg::Theme::File(path)) => Some(path),
+        _ => None,
+    };
+
+    let mut hasher = Fnv::new();
+    hasher.write(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.write(input);
+
+    for option in options {
+        hasher.write(format!("{option:?}").as_bytes());
+    }
+
+    for path in [retime, preamble, background_image]
+        .into_iter()
+        .flatten()
+        .chain(theme_file)
+    {
+        // a missing file fails the render anyway
+        hasher.write(&fs::read(shellexpand::tilde(path).as_ref()).unwrap_or_default());
+    }
+
+    format!("{:016x}", hasher.0)
+}
+
+/// Polls modification times of the files until one of them changes and then
+/// stays unchanged for a moment (editors often write in several steps).
+/// Returns false when interrupted with Ctrl-C.
+fn wait_for_change(paths: &[String], cancelled: &AtomicBool) -> bool {
+    let mtimes = || {
+        paths
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect::<Vec<_>>()
+    };
+
+    let initial = mtimes();
+    let mut last = initial.clone();
+    let mut stable_since = None;
+
+    loop {
+        if cancelled.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        thread::sleep(WATCH_POLL_INTERVAL);
+        let current = mtimes();
+
+        if current != last {
+            last = current;
+            stable_since = Some(Instant::now());
+        } else if let Some(since) = stable_since {
+            if last != initial && since.elapsed() >= WATCH_DEBOUNCE {
+                return true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn write_output() {
+        let dir = std::env::temp_dir().join(format!("agg-write-output-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.gif").display().to_string();
+        let tmp_path = format!("{path}.tmp");
+
+        let failing = |output: &mut fs::File| {
+            output.write_all(b"partial")?;
+
+            Err(agg::Error::Render(anyhow!("1 rendering anomalies found in strict mode")).into())
+        };
+
+        // nothing left behind when rendering fails after writing
+        assert!(super::write_output(&path, false, failing).is_err());
+        assert!(!fs::exists(&path).unwrap());
+        assert!(!fs::exists(&tmp_path).unwrap());
+
+        super::write_output(&path, false, |output| Ok(output.write_all(b"gif")?)).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"gif");
+        assert!(!fs::exists(&tmp_path).unwrap());
+
+        // a failed render doesn't replace an earlier output
+        assert!(super::write_output(&path, true, failing).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"gif");
+
+        // unless it was cancelled and partial output is to be kept
+        let cancelled = |output: &mut fs::File| {
+            output.write_all(b"partial")?;
+
+            Err(agg::Error::Cancelled.into())
+        };
+
+        assert!(super::write_output(&path, true, cancelled).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"partial");
+
+        assert!(super::write_output(&path, false, cancelled).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"partial");
+        assert!(!fs::exists(&tmp_path).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn inputs_named_like_actions() {
+        use clap::Parser;
+
+        let cli = super::Cli::try_parse_from(["agg", "selftest", "inspect"]).unwrap();
+        assert_eq!(cli.filenames, vec!["selftest", "inspect"]);
+        assert!(cli.selftest.is_none());
+        assert!(cli.inspect.is_none());
+
+        let cli = super::Cli::try_parse_from(["agg", "--selftest"]).unwrap();
+        assert_eq!(cli.selftest, Some(None));
+
+        let cli = super::Cli::try_parse_from(["agg", "--selftest", "out.gif"]).unwrap();
+        assert_eq!(cli.selftest, Some(Some("out.gif".to_owned())));
+        assert!(cli.filenames.is_empty());
+
+        let cli = super::Cli::try_parse_from(["agg", "--inspect", "demo.cast"]).unwrap();
+        assert_eq!(cli.inspect.as_deref(), Some("demo.cast"));
+
+        assert!(super::Cli::try_parse_from(["agg", "--inspect", "a.cast", "b.gif"]).is_err());
+    }
+
+    #[test]
+    fn fingerprint() {
+        let dir = std::env::temp_dir().join(format!("agg-fingerprint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let retime = dir.join("retime.txt");
+        fs::write(&retime, "0-1 x2\n").unwrap();
+
+        let config = |speed| agg::Config {
+            speed,
+            retime: Some(retime.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+
+        let hash = super::fingerprint(b"cast", &config(1.0));
+
+        assert_eq!(hash.len(), 16);
+        assert_eq!(super::fingerprint(b"cast", &config(1.0)), hash);
+        assert_ne!(super::fingerprint(b"other", &config(1.0)), hash);
+        assert_ne!(super::fingerprint(b"cast", &config(2.0)), hash);
+
+        // options not changing the output
+        let quiet = agg::Config {
+            show_progress_bar: false,
+            profile: true,
+            ..config(1.0)
+        };
+
+        assert_eq!(super::fingerprint(b"cast", &quiet), hash);
+
+        fs::write(&retime, "0-1 x3\n").unwrap();
+        assert_ne!(super::fingerprint(b"cast", &config(1.0)), hash);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,77 @@
+use imgref::ImgVec;
+use rgb::RGBA8;
+
+/// Returns the smallest size with the given aspect ratio that fits an image
+/// of `(width, height)`.
+pub fn aspect_size((width, height): (usize, usize), (w, h): (usize, usize)) -> (usize, usize) {
+    if width * h < height * w {
+        (((height * w) as f64 / h as f64).round() as usize, height)
+    } else {
+        (width, ((width * h) as f64 / w as f64).round() as usize)
+    }
+}
+
+/// Places the image in the middle of a `(width, height)` canvas filled with
+/// `fill`. Parts of the image not fitting in the canvas are clipped.
+pub fn center(
+    image: &ImgVec<RGBA8>,
+    (width, height): (usize, usize),
+    fill: RGBA8,
+) -> ImgVec<RGBA8> {
+    let mut buf = vec![fill; width * height];
+    let x = (width as isize - image.width() as isize) / 2;
+    let y = (height as isize - image.height() as isize) / 2;
+
+    for (row, pixels) in image.rows().enumerate() {
+        let dy = y + row as isize;
+
+        if dy < 0 || dy >= height as isize {
+            continue;
+        }
+
+        for (col, px) in pixels.iter().enumerate() {
+            let dx = x + col as isize;
+
+            if dx >= 0 && dx < width as isize {
+                buf[dy as usize * width + dx as usize] = *px;
+            }
+        }
+    }
+
+    ImgVec::new(buf, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use imgref::ImgVec;
+    use rgb::RGBA8;
+
+    #[test]
+    fn aspect_size() {
+        assert_eq!(super::aspect_size((800, 400), (1, 1)), (800, 800));
+        assert_eq!(super::aspect_size((400, 800), (1, 1)), (800, 800));
+        assert_eq!(super::aspect_size((800, 400), (16, 9)), (800, 450));
+        assert_eq!(super::aspect_size((600, 450), (16, 9)), (800, 450));
+        assert_eq!(super::aspect_size((1600, 900), (16, 9)), (1600, 900));
+    }
+
+    #[test]
+    fn center() {
+        let fg = RGBA8::new(255, 255, 255, 255);
+        let bg = RGBA8::new(0, 0, 0, 255);
+        let image = ImgVec::new(vec![fg; 2], 2, 1);
+
+        let padded = super::center(&image, (4, 3), bg);
+
+        assert_eq!((padded.width(), padded.height()), (4, 3));
+
+        #[rustfmt::skip]
+        let expected = vec![
+            bg, bg, bg, bg,
+            bg, fg, fg, bg,
+            bg, bg, bg, bg,
+        ];
+
+        assert_eq!(padded.buf(), &expected);
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::Result;
+use imagequant::{Attributes, Histogram, QuantizationResult};
+use imgref::ImgVec;
+use rgb::RGBA8;
+
+// how much worse than the frame it was built from a frame may remap with the
+// current palette (on imagequant's 0-100 quality scale) before it's rebuilt
+const QUALITY_TOLERANCE: u8 = 5;
+
+/// Reduces frames to a bounded number of colors. The palette is shared by
+/// consecutive frames, so that unchanged parts of the screen keep their exact
+/// colors instead of flickering, and is only rebuilt (from the colors of all
+/// frames so far) when a frame brings in colors it can't represent well.
+/// Dithering is off, as its noise would differ from frame to frame.
+pub struct Palette {
+    attr: Attributes,
+    histogram: Histogram,
+    current: Option<(QuantizationResult, u8)>,
+}
+
+impl Palette {
+    pub fn new(max_colors: u16) -> Result<Self> {
+        let mut attr = imagequant::new();
+        attr.set_max_colors(max_colors as u32)?;
+        let histogram = Histogram::new(&attr);
+
+        Ok(Self {
+            attr,
+            histogram,
+            current: None,
+        })
+    }
+
+    pub fn reduce(&mut self, image: ImgVec<RGBA8>) -> Result<ImgVec<RGBA8>> {
+        let (width, height) = (image.width(), image.height());
+        let buf = image.into_buf();
+        let mut sample = self.attr.new_image(buf.clone(), width, height, 0.0)?;
+        self.histogram.add_image(&self.attr, &mut sample)?;
+        let mut img = self.attr.new_image(buf, width, height, 0.0)?;
+
+        if let Some((result, quality)) = &mut self.current {
+            let (palette, indices) = result.remapped(&mut img)?;
+
+            if result.remapping_quality().unwrap_or(0) + QUALITY_TOLERANCE >= *quality {
+                return Ok(remap(&palette, indices, width, height));
+            }
+        }
+
+        let mut result = self.histogram.quantize(&self.attr)?;
+        result.set_dithering_level(0.0)?;
+        let (palette, indices) = result.remapped(&mut img)?;
+        let quality = result.remapping_quality().unwrap_or(0);
+        self.current = Some((result, quality));
+
+        Ok(remap(&palette, indices, width, height))
+    }
+}
+
+fn remap(palette: &[RGBA8], indices: Vec<u8>, width: usize, height: usize) -> ImgVec<RGBA8> {
+    let buf = indices.into_iter().map(|i| palette[i as usize]).collect();
+
+    ImgVec::new(buf, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Palette;
+    use imgref::ImgVec;
+    use rgb::RGBA8;
+    use std::collections::HashSet;
+
+    fn gradient(width: usize, shift: u8) -> ImgVec<RGBA8> {
+        let buf = (0..width * 8)
+            .map(|i| {
+                let x = (i % width) as u8;
+                RGBA8::new(x.wrapping_mul(3), x.wrapping_add(shift), 255 - x, 255)
+            })
+            .collect();
+
+        ImgVec::new(buf, width, 8)
+    }
+
+    fn colors(image: &ImgVec<RGBA8>) -> HashSet<RGBA8> {
+        image.pixels().collect()
+    }
+
+    #[test]
+    fn color_count() {
+        let mut palette = Palette::new(16).unwrap();
+        let image = palette.reduce(gradient(80, 0)).unwrap();
+
+        assert!(colors(&gradient(80, 0)).len() > 16);
+        assert!(colors(&image).len() <= 16);
+    }
+
+    #[test]
+    fn stable_across_frames() {
+        let mut palette = Palette::new(16).unwrap();
+        let first = palette.reduce(gradient(80, 0)).unwrap();
+        let second = palette.reduce(gradient(80, 0)).unwrap();
+        let shifted = palette.reduce(gradient(80, 1)).unwrap();
+        let third = palette.reduce(gradient(80, 0)).unwrap();
+
+        assert_eq!(first.buf(), second.buf());
+        assert_eq!(first.buf(), third.buf());
+        assert!(colors(&shifted).is_subset(&colors(&first)));
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::{anyhow, Result};
+use imgref::ImgVec;
+use rgb::RGBA8;
+
+/// Multiplies color channels by alpha, for consumers expecting premultiplied
+/// alpha in PNG files (which normally store straight alpha).
+pub fn premultiply(image: &ImgVec<RGBA8>) -> ImgVec<RGBA8> {
+    let buf = image
+        .pixels()
+        .map(|c| {
+            let mul = |v: u8| ((v as u16 * c.a as u16 + 127) / 255) as u8;
+
+            RGBA8::new(mul(c.r), mul(c.g), mul(c.b), c.a)
+        })
+        .collect();
+
+    ImgVec::new(buf, image.width(), image.height())
+}
+
+pub fn encode(image: &ImgVec<RGBA8>) -> Result<Vec<u8>> {
+    let mut pixmap = tiny_skia::Pixmap::new(image.width() as u32, image.height() as u32)
+        .ok_or_else(|| anyhow!("invalid image size {}x{}", image.width(), image.height()))?;
+
+    // tiny-skia pixmaps use premultiplied alpha
+    for (px, c) in pixmap.pixels_mut().iter_mut().zip(image.pixels()) {
+        *px = tiny_skia::ColorU8::from_rgba(c.r, c.g, c.b, c.a).premultiply();
+    }
+
+    pixmap.encode_png().map_err(|e| e.into())
+}
+
+/// Inserts a text chunk right after the IHDR chunk of an encoded PNG. Uses
+/// tEXt for ASCII text and iTXt (UTF-8) otherwise.
+pub fn add_text(png: &mut Vec<u8>, keyword: &str, text: &str) {
+    // signature (8 bytes) and IHDR chunk (25 bytes)
+    const IHDR_END: usize = 33;
+
+    let (kind, data) = if text.is_ascii() {
+        (
+            b"tEXt",
+            [keyword.as_bytes(), &[0], text.as_bytes()].concat(),
+        )
+    } else {
+        // no compression, no language tag, no translated keyword
+        let flags = [0u8; 5];
+
+        (
+            b"iTXt",
+            [keyword.as_bytes(), &flags, text.as_bytes()].concat(),
+        )
+    };
+
+    let mut crc = flate2::Crc::new();
+    crc.update(kind);
+    crc.update(&data);
+
+    let chunk = [
+        &(data.len() as u32).to_be_bytes(),
+        kind.as_slice(),
+        &data,
+        &crc.sum().to_be_bytes(),
+    ]
+    .concat();
+
+    png.splice(IHDR_END..IHDR_END, chunk);
+}
+
+#[cfg(test)]
+mod tests {
+    use imgref::ImgVec;
+    use rgb::RGBA8;
+
+    #[test]
+    fn alpha_modes() {
+        let image = ImgVec::new(vec![RGBA8::new(200, 100, 50, 128)], 1, 1);
+
+        let stored = |image: &ImgVec<RGBA8>| {
+            let png = super::encode(image).unwrap();
+            let c = tiny_skia::Pixmap::decode_png(&png).unwrap().pixels()[0].demultiply();
+
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        };
+
+        let near = |a: [u8; 4], b: [u8; 4]| a.iter().zip(b).all(|(a, b)| a.abs_diff(b) <= 1);
+
+        assert!(near(stored(&image), [200, 100, 50, 128]));
+        assert!(near(
+            stored(&super::premultiply(&image)),
+            [100, 50, 25, 128]
+        ));
+    }
+
+    #[test]
+    fn add_text() {
+        let image = ImgVec::new(vec![RGBA8::new(0, 0, 0, 255); 4], 2, 2);
+        let mut png = super::encode(&image).unwrap();
+        let len = png.len();
+
+        super::add_text(&mut png, "Comment", "agg");
+
+        assert_eq!(png.len(), len + 12 + 11);
+        assert_eq!(&png[33..37], &[0, 0, 0, 11]);
+        assert_eq!(&png[37..41], b"tEXt");
+        assert_eq!(&png[41..52], b"Comment\0agg");
+        assert!(tiny_skia::Pixmap::decode_png(&png).is_ok());
+
+        super::add_text(&mut png, "Comment", "żółw");
+
+        assert_eq!(&png[37..41], b"iTXt");
+        assert!(tiny_skia::Pixmap::decode_png(&png).is_ok());
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+pub struct Profile {
+    pub parsing: Duration,
+    pub frame_timing: Duration,
+    pub terminal: Duration,
+    pub rendering: Duration,
+    pub encoder_wait: Duration,
+    pub encoding: Duration,
+    pub frames: usize,
+}
+
+/// Runs `f`, adding the time it took to `total`.
+pub fn measure<T>(total: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    *total += start.elapsed();
+
+    result
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let per_frame = self.rendering.as_secs_f64() * 1000.0 / self.frames.max(1) as f64;
+
+        let rows = [
+            ("parsing", self.parsing, String::new()),
+            ("frame timing", self.frame_timing, String::new()),
+            ("terminal emulation", self.terminal, String::new()),
+            (
+                "rendering",
+                self.rendering,
+                format!(" ({} frames, {:.1}ms/frame)", self.frames, per_frame),
+            ),
+            ("waiting for encoder", self.encoder_wait, String::new()),
+            (
+                "encoding",
+                self.encoding,
+                " (concurrent with rendering)".to_owned(),
+            ),
+        ];
+
+        write!(f, "profile:")?;
+
+        for (label, duration, note) in rows {
+            write!(f, "\n  {label:<20}{:.3}s{note}", duration.as_secs_f64())?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,27 +1,84 @@
+mod blocks;
+mod color_glyph;
 mod fontdue;
 mod resvg;
 
 use imgref::ImgVec;
 use rgb::{RGB8, RGBA8};
 
+use crate::graphemes::Clusters;
 use crate::theme::Theme;
+use crate::{CursorShape, UnderlineStyle};
 
 pub trait Renderer {
     fn render(
         &mut self,
         lines: Vec<Vec<(char, avt::Pen)>>,
         cursor: Option<(usize, usize)>,
+        cursor_shape: CursorShape,
     ) -> ImgVec<RGBA8>;
     fn pixel_size(&self) -> (usize, usize);
+
+    /// Width and height of a single terminal cell, in pixels.
+    fn cell_size(&self) -> (f32, f32);
+
+    /// Pixel position of the top-left corner of the first cell, i.e. the
+    /// margin around the terminal content.
+    fn origin(&self) -> (f32, f32);
+
+    /// Number of rendering anomalies (missing or fallback glyphs, text
+    /// rendered blank) encountered so far.
+    fn anomalies(&self) -> usize {
+        0
+    }
 }
 
+#[derive(Clone)]
 pub struct Settings {
     pub terminal_size: (usize, usize),
     pub font_db: fontdb::Database,
     pub font_families: Vec<String>,
     pub font_size: usize,
+    pub hinting: bool,
+    pub dirty_rects: bool,
+    pub snap_to_grid: bool,
     pub line_height: f64,
+    /// Vertical shift of glyphs, in ems (positive moves them down).
+    pub baseline_offset: f64,
+    pub underline_style: UnderlineStyle,
     pub theme: Theme,
+    /// Grapheme clusters of the recording the frames refer to.
+    pub clusters: Clusters,
+}
+
+/// Settings for renderer tests, with `TEST_THEME` and, when `fonts` is set,
+/// the DejaVu Sans Mono font from the fixtures, so that results don't depend
+/// on the fonts installed on the system.
+#[cfg(test)]
+pub fn test_settings(terminal_size: (usize, usize), fonts: bool) -> Settings {
+    let mut font_db = fontdb::Database::new();
+    let mut font_families = vec![];
+
+    if fonts {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/DejaVuSansMono.ttf");
+        font_db.load_font_file(path).unwrap();
+        font_families.push("DejaVu Sans Mono".to_owned());
+    }
+
+    Settings {
+        terminal_size,
+        font_db,
+        font_families,
+        font_size: 14,
+        hinting: false,
+        dirty_rects: false,
+        snap_to_grid: false,
+        line_height: 1.4,
+        baseline_offset: 0.0,
+        underline_style: Default::default(),
+        theme: crate::theme::TEST_THEME.parse().unwrap(),
+        clusters: Default::default(),
+    }
 }
 
 pub fn resvg(settings: Settings) -> resvg::ResvgRenderer {
@@ -32,6 +89,52 @@ pub fn fontdue(settings: Settings) -> fontdue::FontdueRenderer {
     fontdue::FontdueRenderer::new(settings)
 }
 
+/// Features supported by a renderer backend.
+#[derive(Clone, Copy, Debug)]
+pub struct Capabilities {
+    /// Programming ligatures (e.g. `->` in Fira Code) are formed.
+    pub ligatures: bool,
+    /// Multi-codepoint emoji (flags, ZWJ sequences) are drawn as one glyph.
+    pub grapheme_clusters: bool,
+    /// Color emoji from bitmap fonts (CBDT/sbix).
+    pub color_emoji: bool,
+    /// Curly, double and dotted underlines (`--underline-style`).
+    pub underline_styles: bool,
+    /// Block elements and sextants fill cells exactly.
+    pub block_elements: bool,
+    /// Glyphs are hinted with the font's own instructions. fontdue's
+    /// `--hinting on` only snaps the x-height to a whole pixel, so it doesn't
+    /// count.
+    pub hinting: bool,
+    /// `--dirty-rects` has an effect.
+    pub dirty_rects: bool,
+    /// Edges (rounded corners of the background) are anti-aliased against
+    /// transparency rather than being fully opaque.
+    pub alpha_edges: bool,
+}
+
+impl Capabilities {
+    pub fn features(&self) -> [(&'static str, bool); 8] {
+        [
+            ("ligatures", self.ligatures),
+            ("grapheme clusters", self.grapheme_clusters),
+            ("color emoji", self.color_emoji),
+            ("underline styles", self.underline_styles),
+            ("block elements", self.block_elements),
+            ("hinting", self.hinting),
+            ("dirty rects", self.dirty_rects),
+            ("alpha edges", self.alpha_edges),
+        ]
+    }
+}
+
+pub fn capabilities(renderer: &crate::Renderer) -> Capabilities {
+    match renderer {
+        crate::Renderer::Fontdue => fontdue::FontdueRenderer::CAPABILITIES,
+        crate::Renderer::Resvg => resvg::ResvgRenderer::CAPABILITIES,
+    }
+}
+
 struct TextAttrs {
     foreground: Option<avt::Color>,
     background: Option<avt::Color>,
@@ -49,9 +152,10 @@ fn text_attrs(
 ) -> TextAttrs {
     let mut foreground = pen.foreground();
     let mut background = pen.background();
-    let inverse = cursor.map_or(false, |(cx, cy)| cx == x && cy == y);
+    let inverse = cursor.is_some_and(|(cx, cy)| cx == x && cy == y);
 
-    if pen.is_bold() {
+    // terminals without bright colors don't brighten bold and blinking text
+    if pen.is_bold() && theme.colors > 8 {
         if let Some(avt::Color::Indexed(n)) = foreground {
             if n < 8 {
                 foreground = Some(avt::Color::Indexed(n + 8));
@@ -59,7 +163,7 @@ fn text_attrs(
         }
     }
 
-    if pen.is_blink() {
+    if pen.is_blink() && theme.colors > 8 {
         if let Some(avt::Color::Indexed(n)) = background {
             if n < 8 {
                 background = Some(avt::Color::Indexed(n + 8));
@@ -74,6 +178,16 @@ fn text_attrs(
         background = Some(bg);
     }
 
+    if inverse {
+        if let Some(color) = theme.cursor {
+            background = Some(avt::Color::RGB(color));
+        }
+
+        if let Some(color) = theme.cursor_text {
+            foreground = Some(avt::Color::RGB(color));
+        }
+    }
+
     TextAttrs {
         foreground,
         background,
@@ -83,9 +197,247 @@ fn text_attrs(
     }
 }
 
+/// Block cursor is rendered by inverting the cell, other shapes are drawn
+/// separately on top of it.
+fn block_cursor(
+    cursor: Option<(usize, usize)>,
+    cursor_shape: CursorShape,
+) -> Option<(usize, usize)> {
+    if cursor_shape == CursorShape::Block {
+        cursor
+    } else {
+        None
+    }
+}
+
 fn color_to_rgb(c: &avt::Color, theme: &Theme) -> RGB8 {
     match c {
         avt::Color::RGB(c) => *c,
         avt::Color::Indexed(c) => theme.color(*c),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{test_settings, Renderer};
+    use crate::theme::TEST_THEME;
+    use crate::{scale, vt, CursorShape};
+    use rgb::RGB8;
+
+    #[test]
+    fn backends_consistency() {
+        let (fontdue_settings, resvg_settings) =
+            (test_settings((20, 4), true), test_settings((20, 4), true));
+
+        let stdout = [(
+            0.0,
+            "$ echo hello\r\n\x1b[1;32mhello\x1b[0m\r\n\x1b[7m inverse \x1b[0m".to_owned(),
+        )];
+
+        let frame = vt::frames(stdout.into_iter(), (20, 4), Default::default())
+            .next()
+            .unwrap();
+        let (lines, cursor) = (frame.lines, frame.cursor);
+
+        let a = super::fontdue(fontdue_settings).render(lines.clone(), cursor, CursorShape::Block);
+        let b = super::resvg(resvg_settings).render(lines, cursor, CursorShape::Block);
+        let b = scale::resize(&b, (a.width(), a.height()));
+
+        let diff = a
+            .pixels()
+            .zip(b.pixels())
+            .map(|(a, b)| {
+                (a.r as i32 - b.r as i32).abs()
+                    + (a.g as i32 - b.g as i32).abs()
+                    + (a.b as i32 - b.b as i32).abs()
+            })
+            .sum::<i32>() as f64
+            / (a.width() * a.height() * 3) as f64;
+
+        assert!(diff < 24.0, "average per-channel diff too high: {diff}");
+    }
+
+    #[test]
+    fn completion_menu() {
+        let stdout = [
+            (0.0, "$ git ch".to_owned()),
+            (
+                0.5,
+                "\x1b[s\x1b[2;3H\x1b[44m checkout    \x1b[3;3H\x1b[30;47m cherry-pick \x1b[0m\x1b[u"
+                    .to_owned(),
+            ),
+        ];
+
+        let frame = vt::frames(stdout.into_iter(), (20, 4), Default::default())
+            .last()
+            .unwrap();
+        let blue = RGB8::new(0xbd, 0x93, 0xf9).alpha(255);
+        let white = RGB8::new(0xf8, 0xf8, 0xf2).alpha(255);
+        let background = RGB8::new(0x28, 0x2a, 0x36).alpha(255);
+
+        let renderers: [Box<dyn Renderer>; 2] = [
+            Box::new(super::fontdue(test_settings((20, 4), true))),
+            Box::new(super::resvg(test_settings((20, 4), true))),
+        ];
+
+        for mut renderer in renderers {
+            let image = renderer.render(frame.lines.clone(), frame.cursor, CursorShape::Bar);
+            let (w, h) = renderer.cell_size();
+            let (x, y) = renderer.origin();
+
+            // center of a (blank) cell
+            let pixel = |col: usize, row: usize| {
+                let px = (x + (col as f32 + 0.5) * w) as usize;
+                let py = (y + (row as f32 + 0.5) * h) as usize;
+
+                image.buf()[py * image.stride() + px]
+            };
+
+            assert_eq!(pixel(2, 1), blue);
+            assert_eq!(pixel(14, 1), blue);
+            assert_eq!(pixel(2, 2), white);
+            assert_eq!(pixel(14, 2), white);
+            assert_eq!(pixel(1, 1), background);
+            assert_eq!(pixel(15, 2), background);
+        }
+    }
+
+    #[test]
+    fn blocks() {
+        // a tiny image: half blocks, then a sextant (top-right, middle-left, bottom-left)
+        let stdout = [(
+            0.0,
+            "\x1b[31;42m▀\x1b[34;43m▄\x1b[35;46m\u{1fb14}\x1b[0m".to_owned(),
+        )];
+
+        let frame = vt::frames(stdout.into_iter(), (20, 4), Default::default())
+            .next()
+            .unwrap();
+        let rgb = |hex: u32| RGB8::new((hex >> 16) as u8, (hex >> 8) as u8, hex as u8).alpha(255);
+        let (red, green, yellow, blue) =
+            (rgb(0xff5555), rgb(0x50fa7b), rgb(0xf1fa8c), rgb(0xbd93f9));
+        let (magenta, cyan) = (rgb(0xff79c6), rgb(0x8be9fd));
+
+        let renderers: [Box<dyn Renderer>; 2] = [
+            Box::new(super::fontdue(test_settings((20, 4), true))),
+            Box::new(super::resvg(test_settings((20, 4), true))),
+        ];
+
+        for mut renderer in renderers {
+            let image = renderer.render(frame.lines.clone(), None, CursorShape::Block);
+            let (w, h) = renderer.cell_size();
+            let (x, y) = renderer.origin();
+
+            let pixel = |col: f32, row: f32| {
+                let px = (x + col * w) as usize;
+                let py = (y + row * h) as usize;
+
+                image.buf()[py * image.stride() + px]
+            };
+
+            assert_eq!(pixel(0.5, 0.25), red);
+            assert_eq!(pixel(0.5, 0.75), green);
+            assert_eq!(pixel(1.5, 0.25), yellow);
+            assert_eq!(pixel(1.5, 0.75), blue);
+
+            assert_eq!(pixel(2.25, 1.0 / 6.0), cyan);
+            assert_eq!(pixel(2.75, 1.0 / 6.0), magenta);
+            assert_eq!(pixel(2.25, 0.5), magenta);
+            assert_eq!(pixel(2.75, 0.5), cyan);
+            assert_eq!(pixel(2.25, 5.0 / 6.0), magenta);
+            assert_eq!(pixel(2.75, 5.0 / 6.0), cyan);
+        }
+    }
+
+    #[test]
+    fn lines_not_matching_terminal_size() {
+        // REP fills wider and taller lines than the renderers' terminal size
+        let stdout = [(0.0, "\x1b[41m \x1b[99b".to_owned())];
+        let frame = vt::frames(stdout.into_iter(), (30, 6), Default::default())
+            .next()
+            .unwrap();
+
+        let mut lines = frame.lines;
+        lines[3].truncate(3);
+        let red = RGB8::new(0xff, 0x55, 0x55).alpha(255);
+        let background = RGB8::new(0x28, 0x2a, 0x36).alpha(255);
+
+        let renderers: [Box<dyn Renderer>; 2] = [
+            Box::new(super::fontdue(test_settings((20, 4), true))),
+            Box::new(super::resvg(test_settings((20, 4), true))),
+        ];
+
+        for mut renderer in renderers {
+            let image = renderer.render(lines.clone(), Some((25, 5)), CursorShape::Block);
+            let (w, h) = renderer.cell_size();
+            let (x, y) = renderer.origin();
+
+            assert_eq!((image.width(), image.height()), renderer.pixel_size());
+
+            let pixel = |col: f32, row: f32| {
+                let px = (x + col * w) as usize;
+                let py = (y + row * h) as usize;
+
+                image.buf()[py * image.stride() + px]
+            };
+
+            // truncated at the last column and row, padded when short
+            assert_eq!(pixel(19.5, 0.5), red);
+            assert_eq!(pixel(20.5, 0.5), background);
+            assert_eq!(pixel(2.5, 3.5), red);
+            assert_eq!(pixel(5.5, 3.5), background);
+            assert_eq!(pixel(10.5, 4.25), background);
+        }
+    }
+
+    #[test]
+    fn text_attrs_8_colors() {
+        let mut theme: crate::theme::Theme = TEST_THEME.parse().unwrap();
+
+        let stdout = [(0.0, "\x1b[1;31ma\x1b[0;5;42mb".to_owned())];
+        let frame = vt::frames(stdout.into_iter(), (4, 1), Default::default())
+            .next()
+            .unwrap();
+        let (mut bold, mut blink) = (frame.lines[0][0].1, frame.lines[0][1].1);
+
+        let attrs = super::text_attrs(&mut bold, &None, 0, 0, &theme);
+        assert!(matches!(attrs.foreground, Some(avt::Color::Indexed(9))));
+        let attrs = super::text_attrs(&mut blink, &None, 1, 0, &theme);
+        assert!(matches!(attrs.background, Some(avt::Color::Indexed(10))));
+
+        theme.colors = 8;
+
+        let attrs = super::text_attrs(&mut bold, &None, 0, 0, &theme);
+        assert!(matches!(attrs.foreground, Some(avt::Color::Indexed(1))));
+        let attrs = super::text_attrs(&mut blink, &None, 1, 0, &theme);
+        assert!(matches!(attrs.background, Some(avt::Color::Indexed(2))));
+    }
+
+    #[test]
+    fn text_attrs_cursor_color() {
+        let mut theme: crate::theme::Theme = TEST_THEME.parse().unwrap();
+
+        let stdout = [(0.0, "\x1b[31mab".to_owned())];
+        let frame = vt::frames(stdout.into_iter(), (4, 1), Default::default())
+            .next()
+            .unwrap();
+        let mut pen = frame.lines[0][0].1;
+
+        let attrs = super::text_attrs(&mut pen, &Some((0, 0)), 0, 0, &theme);
+        assert!(matches!(attrs.foreground, Some(avt::Color::RGB(c)) if c == theme.background));
+        assert!(matches!(attrs.background, Some(avt::Color::Indexed(1))));
+
+        theme.cursor = Some(RGB8::new(0xff, 0x80, 0x00));
+        theme.cursor_text = Some(RGB8::new(0, 0, 0));
+
+        let attrs = super::text_attrs(&mut pen, &Some((0, 0)), 0, 0, &theme);
+        assert!(matches!(attrs.foreground, Some(avt::Color::RGB(c)) if c == RGB8::new(0, 0, 0)));
+        assert!(
+            matches!(attrs.background, Some(avt::Color::RGB(c)) if c == RGB8::new(0xff, 0x80, 0))
+        );
+
+        let attrs = super::text_attrs(&mut pen, &Some((0, 0)), 1, 0, &theme);
+        assert!(matches!(attrs.foreground, Some(avt::Color::Indexed(1))));
+        assert!(attrs.background.is_none());
+    }
+}
@@ -0,0 +1,132 @@
+/// Parts of a cell covered by a block element or sextant character, as
+/// rectangles in cell fractions (left, top, right, bottom), along with the
+/// opacity they're filled with (below 1.0 for shades). These characters are
+/// drawn procedurally rather than with font glyphs, which rarely fill the
+/// cell exactly and leave gaps between cells of "images" made of them.
+pub fn shape(ch: char) -> Option<(Vec<[f64; 4]>, f64)> {
+    let cp = ch as u32;
+
+    let rects = match cp {
+        // upper half
+        0x2580 => vec![[0.0, 0.0, 1.0, 0.5]],
+
+        // lower one eighth to full block
+        0x2581..=0x2588 => {
+            let n = (cp - 0x2580) as f64;
+
+            vec![[0.0, 1.0 - n / 8.0, 1.0, 1.0]]
+        }
+
+        // left seven eighths to left one eighth
+        0x2589..=0x258f => {
+            let n = (0x2590 - cp) as f64;
+
+            vec![[0.0, 0.0, n / 8.0, 1.0]]
+        }
+
+        // right half
+        0x2590 => vec![[0.5, 0.0, 1.0, 1.0]],
+
+        // light, medium and dark shade
+        0x2591..=0x2593 => return Some((vec![[0.0, 0.0, 1.0, 1.0]], (cp - 0x2590) as f64 / 4.0)),
+
+        // upper one eighth
+        0x2594 => vec![[0.0, 0.0, 1.0, 0.125]],
+
+        // right one eighth
+        0x2595 => vec![[0.875, 0.0, 1.0, 1.0]],
+
+        // quadrants
+        0x2596..=0x259f => {
+            const QUADRANTS: [u8; 10] = [
+                0b0100, 0b1000, 0b0001, 0b1101, 0b1001, 0b0111, 0b1011, 0b0010, 0b0110, 0b1110,
+            ];
+
+            cells(QUADRANTS[(cp - 0x2596) as usize], 2)
+        }
+
+        // sextants, skipping the patterns of left and right half blocks
+        0x1fb00..=0x1fb3b => {
+            let mut n = cp - 0x1fb00 + 1;
+
+            if n >= 21 {
+                n += 1;
+            }
+
+            if n >= 42 {
+                n += 1;
+            }
+
+            cells(n as u8, 3)
+        }
+
+        _ => return None,
+    };
+
+    Some((rects, 1.0))
+}
+
+/// Rectangles for a 2 columns by `rows` grid with bits set for filled cells,
+/// left to right then top to bottom.
+fn cells(bits: u8, rows: usize) -> Vec<[f64; 4]> {
+    (0..2 * rows)
+        .filter(|i| bits & (1 << i) != 0)
+        .map(|i| {
+            let (col, row) = ((i % 2) as f64, (i / 2) as f64);
+            let rows = rows as f64;
+
+            [col / 2.0, row / rows, (col + 1.0) / 2.0, (row + 1.0) / rows]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::shape;
+
+    #[test]
+    fn block_elements() {
+        assert_eq!(shape('▀'), Some((vec![[0.0, 0.0, 1.0, 0.5]], 1.0)));
+        assert_eq!(shape('▄'), Some((vec![[0.0, 0.5, 1.0, 1.0]], 1.0)));
+        assert_eq!(shape('█'), Some((vec![[0.0, 0.0, 1.0, 1.0]], 1.0)));
+        assert_eq!(shape('▌'), Some((vec![[0.0, 0.0, 0.5, 1.0]], 1.0)));
+        assert_eq!(shape('▏'), Some((vec![[0.0, 0.0, 0.125, 1.0]], 1.0)));
+        assert_eq!(shape('▒'), Some((vec![[0.0, 0.0, 1.0, 1.0]], 0.5)));
+        assert_eq!(shape('a'), None);
+    }
+
+    #[test]
+    fn quadrants() {
+        assert_eq!(shape('▖'), Some((vec![[0.0, 0.5, 0.5, 1.0]], 1.0)));
+        assert_eq!(
+            shape('▚'),
+            Some((vec![[0.0, 0.0, 0.5, 0.5], [0.5, 0.5, 1.0, 1.0]], 1.0))
+        );
+        assert_eq!(shape('▟').unwrap().0.len(), 3);
+    }
+
+    #[test]
+    fn sextants() {
+        let third = 1.0 / 3.0;
+
+        // BLOCK SEXTANT-1
+        assert_eq!(
+            shape('\u{1fb00}'),
+            Some((vec![[0.0, 0.0, 0.5, third]], 1.0))
+        );
+
+        // BLOCK SEXTANT-235, after the skipped left half pattern
+        assert_eq!(
+            shape('\u{1fb14}').unwrap().0,
+            vec![
+                [0.5, 0.0, 1.0, third],
+                [0.0, third, 0.5, 2.0 * third],
+                [0.0, 2.0 * third, 0.5, 1.0]
+            ]
+        );
+
+        // BLOCK SEXTANT-23456
+        assert_eq!(shape('\u{1fb3b}').unwrap().0.len(), 5);
+        assert_eq!(shape('\u{1fb3c}'), None);
+    }
+}
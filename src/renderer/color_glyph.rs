@@ -0,0 +1,189 @@
+use imgref::ImgVec;
+use rgb::RGBA8;
+
+/// Rasterizes a color bitmap glyph (CBDT/CBLC or sbix table, as used by Noto
+/// Color Emoji and Apple Color Emoji) for a char or grapheme cluster, scaled
+/// so that the font's em square is `size` pixels high. Returns `None` when the
+/// face has no bitmap for it. Color glyphs defined by layers (COLR/CPAL)
+/// aren't supported.
+pub fn rasterize(
+    font_data: &[u8],
+    face_index: u32,
+    text: &str,
+    size: f32,
+) -> Option<ImgVec<RGBA8>> {
+    let face = ttf_parser::Face::parse(font_data, face_index).ok()?;
+    let mut chars = text.chars();
+
+    // clusters the font has no ligature for are drawn as their base char
+    let glyph_id = match (chars.next()?, chars.next()) {
+        (ch, None) => face.glyph_index(ch)?,
+        (ch, Some(_)) => ligature(&face, text)
+            .or_else(|| ligature(&face, &text.replace('\u{fe0f}', "")))
+            .or_else(|| face.glyph_index(ch))?,
+    };
+    let image = face.glyph_raster_image(glyph_id, size.round() as u16)?;
+
+    if !matches!(image.format, ttf_parser::RasterImageFormat::PNG) {
+        return None;
+    }
+
+    let pixmap = tiny_skia::Pixmap::decode_png(image.data).ok()?;
+    let scale = size / image.pixels_per_em as f32;
+    let width = ((pixmap.width() as f32 * scale).round() as u32).max(1);
+    let height = ((pixmap.height() as f32 * scale).round() as u32).max(1);
+    let mut scaled = tiny_skia::Pixmap::new(width, height)?;
+
+    scaled.draw_pixmap(
+        0,
+        0,
+        pixmap.as_ref(),
+        &tiny_skia::PixmapPaint {
+            quality: tiny_skia::FilterQuality::Bicubic,
+            ..Default::default()
+        },
+        tiny_skia::Transform::from_scale(
+            width as f32 / pixmap.width() as f32,
+            height as f32 / pixmap.height() as f32,
+        ),
+        None,
+    );
+
+    let buf = scaled
+        .pixels()
+        .iter()
+        .map(|px| {
+            let c = px.demultiply();
+            RGBA8::new(c.red(), c.green(), c.blue(), c.alpha())
+        })
+        .collect();
+
+    Some(ImgVec::new(buf, width as usize, height as usize))
+}
+
+/// Finds the glyph for a sequence of chars (flag, ZWJ sequence, skin tone
+/// modifier) in the font's ligature substitutions, which is how emoji fonts
+/// map them. Other GSUB lookups and feature selection are ignored.
+fn ligature(face: &ttf_parser::Face, text: &str) -> Option<ttf_parser::GlyphId> {
+    use ttf_parser::gsub::SubstitutionSubtable;
+
+    let glyphs = text
+        .chars()
+        .map(|ch| face.glyph_index(ch))
+        .collect::<Option<Vec<_>>>()?;
+
+    let (first, rest) = glyphs.split_first()?;
+
+    for lookup in face.tables().gsub?.lookups {
+        for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+            let SubstitutionSubtable::Ligature(table) = subtable else {
+                continue;
+            };
+
+            let Some(set) = table
+                .coverage
+                .get(*first)
+                .and_then(|i| table.ligature_sets.get(i))
+            else {
+                continue;
+            };
+
+            let found = set
+                .into_iter()
+                .find(|lig| lig.components.into_iter().eq(rest.iter().copied()));
+
+            if let Some(lig) = found {
+                return Some(lig.glyph);
+            }
+        }
+    }
+
+    None
+}
+
+/// Whether a cluster asks for emoji presentation: a base char followed by
+/// variation selector 16, a zero width joiner, a keycap or a skin tone
+/// modifier, or a pair of regional indicators (a flag).
+pub fn is_emoji_sequence(text: &str) -> bool {
+    text.chars().skip(1).any(|ch| {
+        matches!(
+            ch,
+            '\u{fe0f}' | '\u{200d}' | '\u{20e3}' | '\u{1f3fb}'..='\u{1f3ff}' | '\u{1f1e6}'..='\u{1f1ff}'
+        )
+    })
+}
+
+/// Composites a source pixel over a destination pixel (Porter-Duff source
+/// over), both with straight alpha.
+pub fn blend(src: RGBA8, dst: RGBA8) -> RGBA8 {
+    if src.a == 0 {
+        return dst;
+    }
+
+    let sa = src.a as u32;
+    let da = (dst.a as u32 * (255 - sa) + 127) / 255;
+    let a = sa + da;
+
+    let mix = |s: u8, d: u8| ((s as u32 * sa + d as u32 * da + a / 2) / a) as u8;
+
+    RGBA8::new(
+        mix(src.r, dst.r),
+        mix(src.g, dst.g),
+        mix(src.b, dst.b),
+        a as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rgb::RGBA8;
+
+    #[test]
+    fn blend() {
+        let dst = RGBA8::new(0, 0, 0, 255);
+
+        assert_eq!(
+            super::blend(RGBA8::new(255, 0, 0, 255), dst),
+            RGBA8::new(255, 0, 0, 255)
+        );
+        assert_eq!(super::blend(RGBA8::new(255, 0, 0, 0), dst), dst);
+        assert_eq!(
+            super::blend(RGBA8::new(255, 255, 255, 128), dst),
+            RGBA8::new(128, 128, 128, 255)
+        );
+
+        // the destination's alpha is kept where the source doesn't cover it
+        let dst = RGBA8::new(0, 0, 255, 0);
+
+        assert_eq!(
+            super::blend(RGBA8::new(255, 0, 0, 128), dst),
+            RGBA8::new(255, 0, 0, 128)
+        );
+        assert_eq!(super::blend(RGBA8::new(255, 0, 0, 0), dst), dst);
+
+        let dst = RGBA8::new(0, 0, 255, 128);
+
+        assert_eq!(
+            super::blend(RGBA8::new(255, 0, 0, 128), dst),
+            RGBA8::new(170, 0, 85, 192)
+        );
+    }
+
+    #[test]
+    fn is_emoji_sequence() {
+        assert!(!super::is_emoji_sequence("😀"));
+        assert!(!super::is_emoji_sequence("©"));
+        assert!(!super::is_emoji_sequence("e\u{301}"));
+        assert!(super::is_emoji_sequence("©\u{fe0f}"));
+        assert!(super::is_emoji_sequence("👍\u{1f3fd}"));
+        assert!(super::is_emoji_sequence("\u{1f1fa}\u{1f1f8}"));
+        assert!(super::is_emoji_sequence("1\u{fe0f}\u{20e3}"));
+        assert!(super::is_emoji_sequence("👩\u{200d}💻"));
+    }
+
+    #[test]
+    fn rasterize_invalid_font() {
+        assert!(super::rasterize(b"not a font", 0, "😀", 14.0).is_none());
+        assert!(super::rasterize(b"not a font", 0, "\u{1f1fa}\u{1f1f8}", 14.0).is_none());
+    }
+}
@@ -1,25 +1,37 @@
-use crate::renderer::{color_to_rgb, text_attrs, Renderer, Settings};
+use crate::graphemes::{self, Clusters};
+use crate::renderer::{
+    block_cursor, blocks, color_glyph, color_to_rgb, text_attrs, Capabilities, Renderer, Settings,
+};
 use crate::theme::Theme;
+use crate::{CursorShape, UnderlineStyle};
 use imgref::ImgVec;
-use log::debug;
+use log::{debug, warn};
 use rgb::RGBA8;
 use std::collections::HashMap;
+use unicode_normalization::char::is_combining_mark;
 
 type CharVariant = (char, bool, bool);
 type FontFace = (String, bool, bool);
 type Glyph = (fontdue::Metrics, Vec<u8>);
 
 pub struct FontdueRenderer {
+    terminal_size: (usize, usize),
     font_families: Vec<String>,
     theme: Theme,
     pixel_width: usize,
     pixel_height: usize,
     font_size: usize,
+    glyph_size: f32,
     col_width: f64,
     row_height: f64,
+    baseline_offset: i32,
+    underline_style: UnderlineStyle,
     font_db: fontdb::Database,
     glyph_cache: HashMap<CharVariant, Option<Glyph>>,
+    color_glyph_cache: HashMap<char, Option<ImgVec<RGBA8>>>,
     font_cache: HashMap<FontFace, Option<fontdue::Font>>,
+    anomalies: usize,
+    clusters: Clusters,
 }
 
 fn get_font<T: AsRef<str> + std::fmt::Debug>(
@@ -60,6 +72,17 @@ fn get_font<T: AsRef<str> + std::fmt::Debug>(
 }
 
 impl FontdueRenderer {
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        ligatures: false,
+        grapheme_clusters: false,
+        color_emoji: true,
+        underline_styles: true,
+        block_elements: true,
+        hinting: false,
+        dirty_rects: false,
+        alpha_edges: false,
+    };
+
     pub fn new(settings: Settings) -> Self {
         let default_font = get_font(
             &settings.font_db,
@@ -71,20 +94,38 @@ impl FontdueRenderer {
 
         let metrics = default_font.metrics('/', settings.font_size as f32);
         let (cols, rows) = settings.terminal_size;
-        let col_width = metrics.advance_width as f64;
-        let row_height = (settings.font_size as f64) * settings.line_height;
+        let mut col_width = metrics.advance_width as f64;
+        let mut row_height = (settings.font_size as f64) * settings.line_height;
+
+        if settings.snap_to_grid {
+            col_width = col_width.round().max(1.0);
+            row_height = row_height.round().max(1.0);
+        }
+
+        let glyph_size = if settings.hinting {
+            snap_x_height(&default_font, settings.font_size as f32)
+        } else {
+            settings.font_size as f32
+        };
 
         Self {
+            terminal_size: settings.terminal_size,
             font_db: settings.font_db,
             font_families: settings.font_families,
             theme: settings.theme,
             pixel_width: ((cols + 2) as f64 * col_width).round() as usize,
             pixel_height: ((rows + 1) as f64 * row_height).round() as usize,
             font_size: settings.font_size,
+            glyph_size,
             col_width,
             row_height,
+            baseline_offset: (settings.baseline_offset * settings.font_size as f64).round() as i32,
+            underline_style: settings.underline_style,
             font_cache: HashMap::new(),
             glyph_cache: HashMap::new(),
+            color_glyph_cache: HashMap::new(),
+            anomalies: 0,
+            clusters: settings.clusters,
         }
     }
 
@@ -114,37 +155,111 @@ impl FontdueRenderer {
             return;
         }
 
-        if let Some(glyph) = self.rasterize_glyph(ch, bold, italic) {
-            self.glyph_cache.insert(key, Some(glyph));
-            return;
-        }
+        let found = self.rasterize_glyph(ch, bold, italic).or_else(|| {
+            if bold || italic {
+                self.rasterize_glyph(ch, false, false)
+            } else {
+                None
+            }
+        });
+
+        match found {
+            Some((family, glyph)) => {
+                if family > 0 {
+                    debug!(
+                        "using fallback font {} for {:?}",
+                        self.font_families[family], ch
+                    );
+
+                    self.anomalies += 1;
+                }
 
-        if bold || italic {
-            if let Some(glyph) = self.rasterize_glyph(ch, false, false) {
                 self.glyph_cache.insert(key, Some(glyph));
-                return;
             }
-        }
 
-        self.glyph_cache.insert(key, None);
+            None => {
+                warn!("no font has a glyph for {:?}", ch);
+                self.anomalies += 1;
+                self.glyph_cache.insert(key, None);
+            }
+        }
     }
 
     fn get_glyph(&self, ch: char, bold: bool, italic: bool) -> &Option<Glyph> {
         self.glyph_cache.get(&(ch, bold, italic)).unwrap()
     }
 
-    fn rasterize_glyph(&mut self, ch: char, bold: bool, italic: bool) -> Option<Glyph> {
-        let font_size = self.font_size as f32;
+    /// Looks up a color bitmap glyph (emoji) for `text`, the cluster stood in
+    /// for by `ch`. A single char is looked up only in the first font family
+    /// that has it, so that text fonts listed before the emoji font keep
+    /// drawing chars like © or ▶. Emoji sequences are looked up in all of them.
+    fn get_color_glyph(&mut self, ch: char, text: &str) -> &Option<ImgVec<RGBA8>> {
+        if !self.color_glyph_cache.contains_key(&ch) {
+            let len = self.font_families.len();
+
+            // fontdue can't load bitmap-only fonts, so chars no family
+            // covers are looked up everywhere too
+            let families = match text.chars().next() {
+                Some(first) if !color_glyph::is_emoji_sequence(text) => {
+                    self.covering_family(first).map_or(0..len, |i| i..i + 1)
+                }
+
+                _ => 0..len,
+            };
+
+            let image = self.rasterize_color_glyph(&self.font_families[families], text);
+            self.color_glyph_cache.insert(ch, image);
+        }
+
+        self.color_glyph_cache.get(&ch).unwrap()
+    }
+
+    fn rasterize_color_glyph(&self, families: &[String], text: &str) -> Option<ImgVec<RGBA8>> {
+        let font_db = &self.font_db;
+        let size = self.glyph_size;
+
+        families.iter().find_map(|name| {
+            let query = fontdb::Query {
+                families: &[fontdb::Family::Name(name)],
+                weight: fontdb::Weight::NORMAL,
+                stretch: fontdb::Stretch::Normal,
+                style: fontdb::Style::Normal,
+            };
+
+            let id = font_db.query(&query)?;
+
+            font_db
+                .with_face_data(id, |data, index| {
+                    color_glyph::rasterize(data, index, text, size)
+                })
+                .flatten()
+        })
+    }
+
+    /// Returns the index of the first font family with a glyph for `ch`.
+    fn covering_family(&mut self, ch: char) -> Option<usize> {
+        self.font_families.clone().iter().position(|name| {
+            self.get_font(name, false, false)
+                .as_ref()
+                .is_some_and(|font| font.lookup_glyph_index(ch) > 0)
+        })
+    }
+
+    /// Returns the glyph along with the index of the font family it was
+    /// found in.
+    fn rasterize_glyph(&mut self, ch: char, bold: bool, italic: bool) -> Option<(usize, Glyph)> {
+        let font_size = self.glyph_size;
 
         self.font_families
             .clone()
             .iter()
-            .find_map(|name| match self.get_font(name, bold, italic) {
+            .enumerate()
+            .find_map(|(i, name)| match self.get_font(name, bold, italic) {
                 Some(font) => {
                     let idx = font.lookup_glyph_index(ch);
 
                     if idx > 0 {
-                        Some(font.rasterize_indexed(idx, font_size))
+                        Some((i, font.rasterize_indexed(idx, font_size)))
                     } else {
                         None
                     }
@@ -155,6 +270,52 @@ impl FontdueRenderer {
     }
 }
 
+/// fontdue has no hinting interpreter, so approximate it by adjusting the
+/// rasterization size so that the x-height lands on a whole pixel. This keeps
+/// the tops and bottoms of lowercase glyphs crisp at small sizes.
+fn snap_x_height(font: &fontdue::Font, font_size: f32) -> f32 {
+    let x_height = font.metrics('x', font_size).bounds.height;
+
+    if x_height <= 0.0 {
+        return font_size;
+    }
+
+    let snapped = x_height.round().max(1.0);
+    let glyph_size = font_size * snapped / x_height;
+
+    debug!(
+        "hinting: snapping x-height {x_height:.2}px to {snapped}px, glyph size {glyph_size:.2}px"
+    );
+
+    glyph_size
+}
+
+/// Returns the pixel rows to paint in column `x` of an underline at row `y`.
+/// Patterns depend on the absolute column so they continue across cells.
+fn underline_rows(
+    style: UnderlineStyle,
+    y: usize,
+    x: usize,
+    font_size: usize,
+) -> impl Iterator<Item = usize> {
+    let rows = match style {
+        UnderlineStyle::Single => [Some(y), None],
+        UnderlineStyle::Double => [Some(y), Some(y + 2)],
+        UnderlineStyle::Dotted if x & 2 == 0 => [Some(y), None],
+        UnderlineStyle::Dotted => [None, None],
+
+        UnderlineStyle::Curly => {
+            let amplitude = (font_size as f64 / 10.0).max(1.0);
+            let period = font_size as f64 * 0.6;
+            let dy = amplitude * (2.0 * std::f64::consts::PI * x as f64 / period).sin();
+
+            [Some((y as f64 + 1.0 + dy).round() as usize), None]
+        }
+    };
+
+    rows.into_iter().flatten()
+}
+
 fn mix_colors(fg: RGBA8, bg: RGBA8, ratio: u8) -> RGBA8 {
     let ratio = ratio as u16;
 
@@ -171,20 +332,33 @@ impl Renderer for FontdueRenderer {
         &mut self,
         lines: Vec<Vec<(char, avt::Pen)>>,
         cursor: Option<(usize, usize)>,
+        cursor_shape: CursorShape,
     ) -> ImgVec<RGBA8> {
         let mut buf: Vec<RGBA8> =
             vec![self.theme.background.alpha(255); self.pixel_width * self.pixel_height];
         let margin_l = self.col_width;
         let margin_t = (self.row_height / 2.0).round() as usize;
+        let (cols, rows) = self.terminal_size;
+        let block_cursor = block_cursor(cursor, cursor_shape);
 
-        for (row, chars) in lines.iter().enumerate() {
+        for (row, chars) in lines.iter().take(rows).enumerate() {
             let y_t = margin_t + (row as f64 * self.row_height).round() as usize;
             let y_b = margin_t + ((row + 1) as f64 * self.row_height).round() as usize;
 
-            for (col, (ch, mut pen)) in chars.iter().enumerate() {
+            for (col, (ch, mut pen)) in chars.iter().take(cols).enumerate() {
+                // a double-width cluster covers its spacer cell
+                if *ch == graphemes::SPACER && col > 0 {
+                    continue;
+                }
+
+                let span = match chars.get(col + 1) {
+                    Some((graphemes::SPACER, _)) => 2,
+                    _ => 1,
+                };
+
                 let x_l = (margin_l + col as f64 * self.col_width).round() as usize;
-                let x_r = (margin_l + (col + 1) as f64 * self.col_width).round() as usize;
-                let attrs = text_attrs(&mut pen, &cursor, col, row, &self.theme);
+                let x_r = (margin_l + (col + span) as f64 * self.col_width).round() as usize;
+                let attrs = text_attrs(&mut pen, &block_cursor, col, row, &self.theme);
 
                 if let Some(c) = attrs.background {
                     let c = color_to_rgb(&c, &self.theme);
@@ -209,51 +383,160 @@ impl Renderer for FontdueRenderer {
                         + (row as f64 * self.row_height + self.font_size as f64 * 1.2).round()
                             as usize;
 
+                    let style = self
+                        .clusters
+                        .underline_style(*ch)
+                        .unwrap_or(self.underline_style);
+
                     for x in x_l..x_r {
-                        buf[y * self.pixel_width + x] = fg;
+                        for y in underline_rows(style, y, x, self.font_size) {
+                            if y < self.pixel_height {
+                                buf[y * self.pixel_width + x] = fg;
+                            }
+                        }
                     }
                 }
 
-                if ch == &' ' {
+                if ch == &' ' || ch == &graphemes::SPACER {
                     continue;
                 }
 
-                self.ensure_glyph(*ch, attrs.bold, attrs.italic);
-                let glyph = self.get_glyph(*ch, attrs.bold, attrs.italic);
+                if let Some((rects, opacity)) = blocks::shape(*ch) {
+                    let (w, h) = ((x_r - x_l) as f64, (y_b - y_t) as f64);
+                    let alpha = (opacity * 255.0).round() as u8;
+
+                    // edges snap to whole pixels so adjacent cells tile seamlessly
+                    for [l, t, r, b] in rects {
+                        let x0 = x_l + (l * w).round() as usize;
+                        let x1 = x_l + (r * w).round() as usize;
+                        let y0 = y_t + (t * h).round() as usize;
+                        let y1 = y_t + (b * h).round() as usize;
+
+                        for y in y0..y1 {
+                            for x in x0..x1 {
+                                let idx = y * self.pixel_width + x;
+
+                                buf[idx] = if opacity < 1.0 {
+                                    mix_colors(fg, buf[idx], alpha)
+                                } else {
+                                    fg
+                                };
+                            }
+                        }
+                    }
 
-                if glyph.is_none() {
                     continue;
                 }
 
-                let (metrics, bitmap) = glyph.as_ref().unwrap();
+                let cluster = self.clusters.resolve(*ch);
+                let mut utf8 = [0; 4];
+                let text = cluster
+                    .as_deref()
+                    .unwrap_or_else(|| ch.encode_utf8(&mut utf8));
+
+                if !text.starts_with(|c: char| c.is_ascii()) {
+                    let (width, height) = (self.pixel_width, self.pixel_height);
+                    let baseline_offset = self.baseline_offset;
+
+                    if let Some(image) = self.get_color_glyph(*ch, text) {
+                        // vertically centered in the row, starting at the cell's left edge
+                        let x_offset = x_l as i32;
+                        let y_offset = y_t as i32 + (y_b - y_t) as i32 / 2
+                            - image.height() as i32 / 2
+                            + baseline_offset;
+
+                        for (bmap_y, pixels) in image.rows().enumerate() {
+                            let y = y_offset + bmap_y as i32;
+
+                            if y < 0 || y >= height as i32 {
+                                continue;
+                            }
+
+                            for (bmap_x, px) in pixels.iter().enumerate() {
+                                let x = x_offset + bmap_x as i32;
+
+                                if x >= 0 && x < width as i32 {
+                                    let idx = (y as usize) * width + (x as usize);
+                                    buf[idx] = color_glyph::blend(*px, buf[idx]);
+                                }
+                            }
+                        }
+
+                        continue;
+                    }
+                }
 
-                let y_offset = (margin_t + self.font_size - metrics.height) as i32
-                    + (row as f64 * self.row_height).round() as i32
-                    - metrics.ymin;
+                // without shaping, a cluster is drawn as its base char with its
+                // combining marks (designed to overlap the preceding glyph)
+                let marks = text.chars().skip(1).filter(|c| is_combining_mark(*c));
 
-                for bmap_y in 0..metrics.height {
-                    let y = y_offset + bmap_y as i32;
+                for ch in text.chars().take(1).chain(marks) {
+                    self.ensure_glyph(ch, attrs.bold, attrs.italic);
+                    let glyph = self.get_glyph(ch, attrs.bold, attrs.italic);
 
-                    if y < 0 || y >= self.pixel_height as i32 {
+                    if glyph.is_none() {
                         continue;
                     }
 
-                    let x_offset = margin_l as i32
-                        + (col as f64 * self.col_width).round() as i32
-                        + metrics.xmin;
+                    let (metrics, bitmap) = glyph.as_ref().unwrap();
+
+                    let y_offset = (margin_t + self.font_size - metrics.height) as i32
+                        + (row as f64 * self.row_height).round() as i32
+                        - metrics.ymin
+                        + self.baseline_offset;
 
-                    for bmap_x in 0..metrics.width {
-                        let x = x_offset + bmap_x as i32;
+                    for bmap_y in 0..metrics.height {
+                        let y = y_offset + bmap_y as i32;
 
-                        if x < 0 || x >= self.pixel_width as i32 {
+                        if y < 0 || y >= self.pixel_height as i32 {
                             continue;
                         }
 
-                        let v = bitmap[bmap_y * metrics.width + bmap_x];
-                        let idx = (y as usize) * self.pixel_width + (x as usize);
-                        let bg = buf[idx];
+                        let x_offset = margin_l as i32
+                            + (col as f64 * self.col_width).round() as i32
+                            + metrics.xmin;
 
-                        buf[idx] = mix_colors(fg, bg, v);
+                        for bmap_x in 0..metrics.width {
+                            let x = x_offset + bmap_x as i32;
+
+                            if x < 0 || x >= self.pixel_width as i32 {
+                                continue;
+                            }
+
+                            let v = bitmap[bmap_y * metrics.width + bmap_x];
+                            let idx = (y as usize) * self.pixel_width + (x as usize);
+                            let bg = buf[idx];
+
+                            buf[idx] = mix_colors(fg, bg, v);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some((col, row)) = cursor.filter(|(col, row)| *col < cols && *row < rows) {
+            let x_l = (margin_l + col as f64 * self.col_width).round() as usize;
+            let x_r = (margin_l + (col + 1) as f64 * self.col_width).round() as usize;
+            let y_t = margin_t + (row as f64 * self.row_height).round() as usize;
+            let y_b = margin_t + ((row + 1) as f64 * self.row_height).round() as usize;
+            let thickness = ((self.font_size as f64 / 7.0).round() as usize).max(1);
+
+            let area = match cursor_shape {
+                CursorShape::Block => None,
+                CursorShape::Underline => Some((x_l, x_r, y_b - thickness, y_b)),
+                CursorShape::Bar => Some((x_l, x_l + thickness, y_t, y_b)),
+            };
+
+            if let Some((x_l, x_r, y_t, y_b)) = area {
+                let fg = self
+                    .theme
+                    .cursor
+                    .unwrap_or(self.theme.foreground)
+                    .alpha(255);
+
+                for y in y_t..y_b {
+                    for x in x_l..x_r {
+                        buf[y * self.pixel_width + x] = fg;
                     }
                 }
             }
@@ -265,4 +548,84 @@ impl Renderer for FontdueRenderer {
     fn pixel_size(&self) -> (usize, usize) {
         (self.pixel_width, self.pixel_height)
     }
+
+    fn cell_size(&self) -> (f32, f32) {
+        (self.col_width as f32, self.row_height as f32)
+    }
+
+    fn origin(&self) -> (f32, f32) {
+        (
+            self.col_width as f32,
+            (self.row_height / 2.0).round() as f32,
+        )
+    }
+
+    fn anomalies(&self) -> usize {
+        self.anomalies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FontdueRenderer;
+    use crate::renderer::{Renderer, Settings};
+    use crate::{vt, CursorShape};
+
+    #[test]
+    fn zwj_sequence() {
+        let clusters = crate::graphemes::Clusters::default();
+
+        let render = |text: &str| {
+            let stdout = [(0.0, text.to_owned())];
+            let frame = vt::frames(stdout.into_iter(), (6, 1), clusters.clone())
+                .next()
+                .unwrap();
+
+            let mut renderer = FontdueRenderer::new(Settings {
+                clusters: clusters.clone(),
+                ..crate::renderer::test_settings((6, 1), true)
+            });
+
+            renderer.render(frame.lines, None, CursorShape::Block)
+        };
+
+        // the fixture font has no emoji, so the sequence is left blank, taking
+        // two cells, like two spaces
+        let zwj = render("a\u{1f469}\u{200d}\u{1f4bb}b");
+        let blank = render("a  b");
+
+        assert!(zwj.pixels().eq(blank.pixels()));
+    }
+
+    #[test]
+    fn hinting() {
+        for font_size in [10, 13, 16, 20] {
+            let settings = |hinting| Settings {
+                font_size,
+                hinting,
+                ..crate::renderer::test_settings((4, 2), true)
+            };
+
+            let unhinted = FontdueRenderer::new(settings(false));
+            let hinted = FontdueRenderer::new(settings(true));
+            let font = super::get_font(
+                &hinted.font_db,
+                &hinted.font_families,
+                fontdb::Weight::NORMAL,
+                fontdb::Style::Normal,
+            )
+            .unwrap();
+
+            let x_height = font.metrics('x', hinted.glyph_size).bounds.height;
+
+            assert_eq!(unhinted.glyph_size, font_size as f32);
+            assert!(
+                (x_height - x_height.round()).abs() < 0.05,
+                "{font_size}px: {x_height}"
+            );
+            // only glyphs are scaled, the grid stays the same
+            assert_eq!(hinted.col_width, unhinted.col_width);
+            assert_eq!(hinted.row_height, unhinted.row_height);
+        }
+    }
 }
@@ -1,11 +1,27 @@
 use imgref::ImgVec;
+use log::warn;
 use resvg::usvg_text_layout::TreeTextToPath;
-use rgb::{FromSlice, RGBA8};
+use rgb::{RGB8, RGBA8};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::fmt::Write as _;
 
+use crate::graphemes::{self, Clusters};
 use crate::theme::Theme;
+use crate::{CursorShape, UnderlineStyle};
 
-use super::{color_to_rgb, text_attrs, Renderer, Settings, TextAttrs};
+use super::{
+    block_cursor, blocks, color_to_rgb, text_attrs, Capabilities, Renderer, Settings, TextAttrs,
+};
+
+type Lines = Vec<Vec<(char, avt::Pen)>>;
+
+struct PrevFrame {
+    lines: Lines,
+    cursor: Option<(usize, usize)>,
+    cursor_shape: CursorShape,
+    pixmap: tiny_skia::Pixmap,
+}
 
 pub struct ResvgRenderer {
     terminal_size: (usize, usize),
@@ -13,12 +29,22 @@ pub struct ResvgRenderer {
     pixel_width: usize,
     pixel_height: usize,
     char_width: f64,
+    font_size: f64,
+    col_width: f64,
     row_height: f64,
+    baseline_offset: f64,
+    underline_style: UnderlineStyle,
+    cursor_thickness: f64,
     options: usvg::Options,
     transform: tiny_skia::Transform,
     fit_to: usvg::FitTo,
     header: String,
     font_db: fontdb::Database,
+    dirty_rects: bool,
+    prev: Option<PrevFrame>,
+    coverage: RefCell<HashMap<char, bool>>,
+    uncovered_frames: Cell<usize>,
+    clusters: Clusters,
 }
 
 fn color_to_style(color: &avt::Color, theme: &Theme) -> String {
@@ -52,6 +78,17 @@ fn text_style(attrs: &TextAttrs, theme: &Theme) -> String {
         .unwrap_or_else(|| "".to_owned())
 }
 
+fn push_char(svg: &mut String, ch: char) {
+    match ch {
+        '\'' => svg.push_str("&#39;"),
+        '"' => svg.push_str("&quot;"),
+        '&' => svg.push_str("&amp;"),
+        '>' => svg.push_str("&gt;"),
+        '<' => svg.push_str("&lt;"),
+        _ => svg.push(ch),
+    }
+}
+
 fn rect_style(attrs: &TextAttrs, theme: &Theme) -> String {
     attrs
         .background
@@ -59,11 +96,41 @@ fn rect_style(attrs: &TextAttrs, theme: &Theme) -> String {
         .unwrap_or_else(|| "".to_owned())
 }
 
+// tiny-skia stores premultiplied alpha while gifski expects straight alpha
+fn demultiply(pixmap: &tiny_skia::Pixmap) -> Vec<RGBA8> {
+    pixmap
+        .pixels()
+        .iter()
+        .map(|c| {
+            let c = c.demultiply();
+
+            RGBA8::new(c.red(), c.green(), c.blue(), c.alpha())
+        })
+        .collect()
+}
+
 impl ResvgRenderer {
+    pub const CAPABILITIES: Capabilities = Capabilities {
+        ligatures: false,
+        grapheme_clusters: true,
+        color_emoji: false,
+        underline_styles: true,
+        block_elements: true,
+        hinting: false,
+        dirty_rects: true,
+        alpha_edges: true,
+    };
+
     pub fn new(settings: Settings) -> Self {
         let char_width = 100.0 / (settings.terminal_size.0 as f64 + 2.0);
         let font_size = settings.font_size as f64;
-        let row_height = font_size * settings.line_height;
+        let mut col_width = font_size * 0.6;
+        let mut row_height = font_size * settings.line_height;
+
+        if settings.snap_to_grid {
+            col_width = col_width.round().max(1.0);
+            row_height = row_height.round().max(1.0);
+        }
         let options = usvg::Options::default();
         let fit_to = usvg::FitTo::Original;
         let transform = tiny_skia::Transform::default();
@@ -72,7 +139,7 @@ impl ResvgRenderer {
             settings.terminal_size,
             settings.font_families.join(","),
             font_size,
-            row_height,
+            (col_width, row_height),
             &settings.theme,
         );
 
@@ -90,12 +157,22 @@ impl ResvgRenderer {
             pixel_width,
             pixel_height,
             char_width,
+            font_size,
+            col_width,
             row_height,
+            baseline_offset: settings.baseline_offset,
+            underline_style: settings.underline_style,
+            cursor_thickness: (font_size / 7.0).round().max(1.0),
             options,
             transform,
             fit_to,
             header,
             font_db: settings.font_db,
+            dirty_rects: settings.dirty_rects,
+            prev: None,
+            coverage: RefCell::new(HashMap::new()),
+            uncovered_frames: Cell::new(0),
+            clusters: settings.clusters,
         }
     }
 
@@ -103,10 +180,10 @@ impl ResvgRenderer {
         (cols, rows): (usize, usize),
         font_family: String,
         font_size: f64,
-        row_height: f64,
+        (col_width, row_height): (f64, f64),
         theme: &Theme,
     ) -> String {
-        let width = (cols + 2) as f64 * (font_size * 0.6);
+        let width = (cols + 2) as f64 * col_width;
         let height = (rows + 1) as f64 * row_height;
         let x = 1.0 * 100.0 / (cols as f64 + 2.0);
         let y = 0.5 * 100.0 / (rows as f64 + 1.0);
@@ -129,6 +206,27 @@ impl ResvgRenderer {
         "</svg></svg>"
     }
 
+    pub fn frame_svg(
+        &self,
+        lines: Vec<Vec<(char, avt::Pen)>>,
+        cursor: Option<(usize, usize)>,
+        cursor_shape: CursorShape,
+    ) -> String {
+        let mut svg = String::new();
+        self.push_lines(&mut svg, lines, block_cursor(cursor, cursor_shape));
+        self.push_cursor(&mut svg, cursor, cursor_shape);
+
+        svg
+    }
+
+    pub fn document(&self, body: &str) -> String {
+        let mut svg = self.header.clone();
+        svg.push_str(body);
+        svg.push_str(Self::footer());
+
+        svg
+    }
+
     fn push_lines(
         &self,
         svg: &mut String,
@@ -136,7 +234,51 @@ impl ResvgRenderer {
         cursor: Option<(usize, usize)>,
     ) {
         self.push_background(svg, &lines, cursor);
+        self.push_blocks(svg, &lines, cursor);
         self.push_text(svg, &lines, cursor);
+        self.push_underlines(svg, &lines, cursor);
+    }
+
+    fn push_cursor(
+        &self,
+        svg: &mut String,
+        cursor: Option<(usize, usize)>,
+        cursor_shape: CursorShape,
+    ) {
+        let (cols, rows) = self.terminal_size;
+
+        let (col, row) = match cursor {
+            Some((col, row)) if col < cols && row < rows => (col, row),
+            _ => return,
+        };
+
+        let x = 100.0 * (col as f64) / (cols as f64 + 2.0);
+        let y = 100.0 * (row as f64) / (rows as f64 + 1.0);
+        let color = self.theme.cursor.unwrap_or(self.theme.foreground);
+        let style = color_to_style(&avt::Color::RGB(color), &self.theme);
+
+        match cursor_shape {
+            CursorShape::Block => {}
+
+            CursorShape::Underline => {
+                let y = y + 100.0 * (self.row_height - self.cursor_thickness)
+                    / self.pixel_height as f64;
+
+                let _ = write!(
+                    svg,
+                    r#"<rect x="{:.3}%" y="{:.3}%" width="{:.3}%" height="{:.3}" style="{}" />"#,
+                    x, y, self.char_width, self.cursor_thickness, style
+                );
+            }
+
+            CursorShape::Bar => {
+                let _ = write!(
+                    svg,
+                    r#"<rect x="{:.3}%" y="{:.3}%" width="{:.3}" height="{:.3}" style="{}" />"#,
+                    x, y, self.cursor_thickness, self.row_height, style
+                );
+            }
+        }
     }
 
     fn push_background(
@@ -149,10 +291,10 @@ impl ResvgRenderer {
 
         svg.push_str(r#"<g style="shape-rendering: optimizeSpeed">"#);
 
-        for (row, line) in lines.iter().enumerate() {
+        for (row, line) in lines.iter().take(rows).enumerate() {
             let y = 100.0 * (row as f64) / (rows as f64 + 1.0);
 
-            for (col, (_ch, mut pen)) in line.iter().enumerate() {
+            for (col, (_ch, mut pen)) in line.iter().take(cols).enumerate() {
                 let attrs = text_attrs(&mut pen, &cursor, col, row, &self.theme);
 
                 if attrs.background.is_none() {
@@ -173,6 +315,139 @@ impl ResvgRenderer {
         svg.push_str("</g>");
     }
 
+    /// Draws block elements and sextants as rectangles in the text color,
+    /// instead of glyphs (see `blocks::shape`).
+    fn push_blocks(
+        &self,
+        svg: &mut String,
+        lines: &[Vec<(char, avt::Pen)>],
+        cursor: Option<(usize, usize)>,
+    ) {
+        let (cols, rows) = self.terminal_size;
+
+        svg.push_str(r#"<g style="shape-rendering: optimizeSpeed">"#);
+
+        for (row, line) in lines.iter().take(rows).enumerate() {
+            for (col, (ch, mut pen)) in line.iter().take(cols).enumerate() {
+                let Some((rects, opacity)) = blocks::shape(*ch) else {
+                    continue;
+                };
+
+                let attrs = text_attrs(&mut pen, &cursor, col, row, &self.theme);
+
+                let color = color_to_rgb(
+                    &attrs
+                        .foreground
+                        .unwrap_or(avt::Color::RGB(self.theme.foreground)),
+                    &self.theme,
+                );
+
+                for [l, t, r, b] in rects {
+                    let _ = write!(
+                        svg,
+                        r#"<rect x="{:.3}" y="{:.3}" width="{:.3}" height="{:.3}" style="fill: rgb({},{},{}); fill-opacity: {}" />"#,
+                        (col as f64 + l) * self.col_width,
+                        (row as f64 + t) * self.row_height,
+                        (r - l) * self.col_width,
+                        (b - t) * self.row_height,
+                        color.r,
+                        color.g,
+                        color.b,
+                        opacity
+                    );
+                }
+            }
+        }
+
+        svg.push_str("</g>");
+    }
+
+    /// Returns a cell's explicit underline style, or the configured one.
+    fn underline_style(&self, ch: char) -> UnderlineStyle {
+        self.clusters
+            .underline_style(ch)
+            .unwrap_or(self.underline_style)
+    }
+
+    /// Draws non-single underlines as paths, one per run of adjacent
+    /// underlined cells of the same color and style, so that dash and wave
+    /// patterns stay continuous.
+    fn push_underlines(
+        &self,
+        svg: &mut String,
+        lines: &[Vec<(char, avt::Pen)>],
+        cursor: Option<(usize, usize)>,
+    ) {
+        let (cols, rows) = self.terminal_size;
+
+        for (row, line) in lines.iter().take(rows).enumerate() {
+            let mut runs: Vec<(usize, usize, RGB8, UnderlineStyle)> = Vec::new();
+
+            for (col, (ch, mut pen)) in line.iter().take(cols).enumerate() {
+                let attrs = text_attrs(&mut pen, &cursor, col, row, &self.theme);
+                let style = self.underline_style(*ch);
+
+                if !attrs.underline || style == UnderlineStyle::Single {
+                    continue;
+                }
+
+                let color = color_to_rgb(
+                    &attrs
+                        .foreground
+                        .unwrap_or(avt::Color::RGB(self.theme.foreground)),
+                    &self.theme,
+                );
+
+                match runs.last_mut() {
+                    Some((_, end, c, s)) if *end == col && *c == color && *s == style => *end += 1,
+                    _ => runs.push((col, col + 1, color, style)),
+                }
+            }
+
+            for (start, end, color, style) in runs {
+                let x = start as f64 * self.col_width;
+                let y = row as f64 * self.row_height + self.font_size * 1.2 + 0.5;
+                let width = (end - start) as f64 * self.col_width;
+
+                let (d, dash) = match style {
+                    UnderlineStyle::Single => continue,
+                    UnderlineStyle::Double => (
+                        format!("M{x:.3} {y:.3}h{width:.3}M{x:.3} {:.3}h{width:.3}", y + 2.0),
+                        "",
+                    ),
+                    UnderlineStyle::Dotted => (
+                        format!("M{x:.3} {y:.3}h{width:.3}"),
+                        "; stroke-dasharray: 2 2",
+                    ),
+                    UnderlineStyle::Curly => {
+                        let amplitude = (self.font_size / 10.0).max(1.0);
+                        let half = self.font_size * 0.3;
+                        let waves = (width / half).ceil() as usize;
+
+                        let mut d = format!(
+                            "M{x:.3} {:.3}q{:.3} {:.3} {half:.3} 0",
+                            y + 1.0,
+                            half / 2.0,
+                            amplitude * 2.0
+                        );
+
+                        for _ in 1..waves {
+                            let _ = write!(d, "t{half:.3} 0");
+                        }
+
+                        (d, "")
+                    }
+                };
+
+                let _ = write!(
+                    svg,
+                    r#"<path d="{}" style="fill: none; stroke: rgb({},{},{}); stroke-width: 1{}" />"#,
+                    d, color.r, color.g, color.b, dash
+                );
+            }
+        }
+    }
+
     fn push_text(
         &self,
         svg: &mut String,
@@ -183,23 +458,32 @@ impl ResvgRenderer {
 
         svg.push_str(r#"<text class="default-text-fill">"#);
 
-        for (row, line) in lines.iter().enumerate() {
+        for (row, line) in lines.iter().take(rows).enumerate() {
             let y = 100.0 * (row as f64) / (rows as f64 + 1.0);
             let mut did_dy = false;
 
             let _ = write!(svg, r#"<tspan y="{y:.3}%">"#);
 
-            for (col, (ch, mut pen)) in line.iter().enumerate() {
-                if ch == &' ' {
+            for (col, (ch, mut pen)) in line.iter().take(cols).enumerate() {
+                if ch == &' ' || ch == &graphemes::SPACER || blocks::shape(*ch).is_some() {
                     continue;
                 }
 
-                let attrs = text_attrs(&mut pen, &cursor, col, row, &self.theme);
+                let text = self.clusters.resolve(*ch);
+
+                if text.as_deref() == Some(" ") {
+                    continue;
+                }
+
+                let mut attrs = text_attrs(&mut pen, &cursor, col, row, &self.theme);
+
+                // other styles are drawn as paths, see push_underlines
+                attrs.underline &= self.underline_style(*ch) == UnderlineStyle::Single;
 
                 svg.push_str("<tspan ");
 
                 if !did_dy {
-                    svg.push_str(r#"dy="1em" "#);
+                    let _ = write!(svg, r#"dy="{}em" "#, 1.0 + self.baseline_offset);
                     did_dy = true;
                 }
 
@@ -209,30 +493,9 @@ impl ResvgRenderer {
 
                 let _ = write!(svg, r#"x="{x:.3}%" class="{class}" style="{style}">"#);
 
-                match ch {
-                    '\'' => {
-                        svg.push_str("&#39;");
-                    }
-
-                    '"' => {
-                        svg.push_str("&quot;");
-                    }
-
-                    '&' => {
-                        svg.push_str("&amp;");
-                    }
-
-                    '>' => {
-                        svg.push_str("&gt;");
-                    }
-
-                    '<' => {
-                        svg.push_str("&lt;");
-                    }
-
-                    _ => {
-                        svg.push(*ch);
-                    }
+                match text {
+                    Some(text) => text.chars().for_each(|ch| push_char(svg, ch)),
+                    None => push_char(svg, *ch),
                 }
 
                 svg.push_str("</tspan>");
@@ -250,18 +513,45 @@ impl Renderer for ResvgRenderer {
         &mut self,
         lines: Vec<Vec<(char, avt::Pen)>>,
         cursor: Option<(usize, usize)>,
+        cursor_shape: CursorShape,
     ) -> ImgVec<RGBA8> {
-        let mut svg = self.header.clone();
-        self.push_lines(&mut svg, lines, cursor);
-        svg.push_str(Self::footer());
-        let mut tree = usvg::Tree::from_str(&svg, &self.options).unwrap();
-        tree.convert_text(&self.font_db, true);
+        // when only the cursor moved, re-stamp just the cells around its old
+        // and new position
+        let pixmap = match self.prev.take() {
+            Some(mut prev) if prev.lines == lines => {
+                if cursor != prev.cursor || cursor_shape != prev.cursor_shape {
+                    for cell in prev.cursor.into_iter().chain(cursor) {
+                        self.render_cell(&mut prev.pixmap, &lines, cursor, cursor_shape, cell);
+                    }
+                }
 
-        let mut pixmap =
-            tiny_skia::Pixmap::new(self.pixel_width as u32, self.pixel_height as u32).unwrap();
+                prev.pixmap
+            }
+
+            Some(mut prev) if self.dirty_rects => {
+                if let Some(dirty) = self.dirty_rows(&prev, &lines, cursor, cursor_shape) {
+                    self.render_rows(&mut prev.pixmap, &lines, cursor, cursor_shape, dirty);
+                }
+
+                prev.pixmap
+            }
+
+            _ => self.rasterize(
+                lines.clone(),
+                cursor,
+                cursor_shape,
+                (0, 0, self.pixel_width, self.pixel_height),
+            ),
+        };
+
+        let buf = demultiply(&pixmap);
 
-        resvg::render(&tree, self.fit_to, self.transform, pixmap.as_mut()).unwrap();
-        let buf = pixmap.take().as_rgba().to_vec();
+        self.prev = Some(PrevFrame {
+            lines,
+            cursor,
+            cursor_shape,
+            pixmap,
+        });
 
         ImgVec::new(buf, self.pixel_width, self.pixel_height)
     }
@@ -269,4 +559,489 @@ impl Renderer for ResvgRenderer {
     fn pixel_size(&self) -> (usize, usize) {
         (self.pixel_width, self.pixel_height)
     }
+
+    fn cell_size(&self) -> (f32, f32) {
+        (self.col_width as f32, self.row_height as f32)
+    }
+
+    fn origin(&self) -> (f32, f32) {
+        (self.col_width as f32, (self.row_height / 2.0) as f32)
+    }
+
+    fn anomalies(&self) -> usize {
+        self.uncovered_frames.get()
+    }
+}
+
+impl ResvgRenderer {
+    /// Renders the frame to a pixmap covering pixels `left..right` of rows
+    /// `top..bottom` only.
+    fn rasterize(
+        &self,
+        lines: Lines,
+        cursor: Option<(usize, usize)>,
+        cursor_shape: CursorShape,
+        (left, top, right, bottom): (usize, usize, usize, usize),
+    ) -> tiny_skia::Pixmap {
+        // usvg silently draws missing glyphs as tofu, or nothing at all
+        let clusters = self.clusters(&lines);
+        let covered = clusters.iter().filter(|c| self.is_covered(c)).count();
+
+        if covered < clusters.len() {
+            if self.uncovered_frames.get() == 0 {
+                warn!("text rendered as tofu or blank, try another --font-family or --renderer fontdue");
+            }
+
+            self.uncovered_frames.set(self.uncovered_frames.get() + 1);
+        }
+
+        let svg = self.document(&self.frame_svg(lines, cursor, cursor_shape));
+        let mut tree = usvg::Tree::from_str(&svg, &self.options).unwrap();
+        tree.convert_text(&self.font_db, true);
+
+        let mut pixmap =
+            tiny_skia::Pixmap::new((right - left) as u32, (bottom - top) as u32).unwrap();
+
+        let transform = self.transform.pre_translate(-(left as f32), -(top as f32));
+        resvg::render(&tree, self.fit_to, transform, pixmap.as_mut()).unwrap();
+
+        pixmap
+    }
+
+    /// Text of the visible cells drawn with font glyphs (one grapheme
+    /// cluster each).
+    fn clusters(&self, lines: &Lines) -> Vec<String> {
+        let (cols, rows) = self.terminal_size;
+
+        lines
+            .iter()
+            .take(rows)
+            .flat_map(|line| line.iter().take(cols))
+            .filter(|(ch, _)| *ch != ' ' && blocks::shape(*ch).is_none())
+            .map(|(ch, _)| self.clusters.resolve(*ch).unwrap_or_else(|| ch.to_string()))
+            .filter(|cluster| cluster != " ")
+            .collect()
+    }
+
+    /// Whether the fonts have glyphs for all chars of a cluster. usvg falls
+    /// back to any font in the database, so all faces are checked.
+    fn is_covered(&self, cluster: &str) -> bool {
+        cluster.chars().filter(|ch| !is_ignorable(*ch)).all(|ch| {
+            *self.coverage.borrow_mut().entry(ch).or_insert_with(|| {
+                let found = self.font_db.faces().iter().any(|face| {
+                    self.font_db
+                        .with_face_data(face.id, |data, index| {
+                            ttf_parser::Face::parse(data, index)
+                                .ok()
+                                .and_then(|face| face.glyph_index(ch))
+                        })
+                        .flatten()
+                        .is_some()
+                });
+
+                if !found {
+                    warn!("no font has a glyph for {:?}", ch);
+                }
+
+                found
+            })
+        })
+    }
+
+    /// Returns the first and last terminal row which differ from the
+    /// previous frame, or None if nothing changed.
+    fn dirty_rows(
+        &self,
+        prev: &PrevFrame,
+        lines: &Lines,
+        cursor: Option<(usize, usize)>,
+        cursor_shape: CursorShape,
+    ) -> Option<(usize, usize)> {
+        let rows = self.terminal_size.1.min(lines.len());
+
+        let mut dirty = (0..rows)
+            .filter(|&row| prev.lines.get(row) != lines.get(row))
+            .collect::<Vec<_>>();
+
+        if cursor != prev.cursor || cursor_shape != prev.cursor_shape {
+            dirty.extend(prev.cursor.map(|(_, row)| row));
+            dirty.extend(cursor.map(|(_, row)| row));
+        }
+
+        let first = dirty.iter().min()?;
+        let last = dirty.iter().max()?;
+
+        Some((*first, (*last).min(self.terminal_size.1 - 1)))
+    }
+
+    /// Re-rasterizes terminal rows `first..=last` (plus one row of slack on
+    /// each side, for glyphs overflowing their cell) onto the pixmap.
+    fn render_rows(
+        &self,
+        pixmap: &mut tiny_skia::Pixmap,
+        lines: &Lines,
+        cursor: Option<(usize, usize)>,
+        cursor_shape: CursorShape,
+        (first, last): (usize, usize),
+    ) {
+        let rows = self.terminal_size.1;
+        let first = first.saturating_sub(1);
+        let last = (last + 1).min(rows - 1);
+        let row_px = self.pixel_height as f64 / (rows as f64 + 1.0);
+
+        let top = if first == 0 {
+            0
+        } else {
+            ((first as f64 + 0.5) * row_px).floor() as usize
+        };
+
+        let bottom = if last == rows - 1 {
+            self.pixel_height
+        } else {
+            (((last as f64 + 1.5) * row_px).ceil() as usize).min(self.pixel_height)
+        };
+
+        // neighbouring rows can draw into the band too
+        let visible = lines
+            .iter()
+            .enumerate()
+            .map(|(row, line)| {
+                if row + 1 >= first && row <= last + 1 {
+                    line.clone()
+                } else {
+                    vec![]
+                }
+            })
+            .collect();
+
+        let band = self.rasterize(
+            visible,
+            cursor,
+            cursor_shape,
+            (0, top, self.pixel_width, bottom),
+        );
+        let stride = self.pixel_width * 4;
+
+        pixmap.data_mut()[top * stride..bottom * stride].copy_from_slice(band.data());
+    }
+
+    /// Re-rasterizes the cell at `(col, row)` and its direct neighbours onto
+    /// the pixmap.
+    fn render_cell(
+        &self,
+        pixmap: &mut tiny_skia::Pixmap,
+        lines: &Lines,
+        cursor: Option<(usize, usize)>,
+        cursor_shape: CursorShape,
+        (col, row): (usize, usize),
+    ) {
+        let (cols, rows) = self.terminal_size;
+
+        if col >= cols || row >= rows {
+            return;
+        }
+
+        // cells up to two away are drawn too, for glyphs overflowing their
+        // cell, the rest is left out
+        let visible = lines
+            .iter()
+            .enumerate()
+            .map(|(r, line)| {
+                if r + 2 < row || r > row + 2 {
+                    return vec![];
+                }
+
+                line.iter()
+                    .enumerate()
+                    .map(|(c, cell)| {
+                        if c + 2 < col || c > col + 2 {
+                            (' ', avt::Pen::default())
+                        } else {
+                            *cell
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // glyphs cut by the edge of the rasterized area are approximated a
+        // bit differently, so it spans one more cell than is drawn
+        let outer = self.cells_rect((col, row), 3);
+        let (left, top, right, bottom) = self.cells_rect((col, row), 1);
+        let patch = self.rasterize(visible, cursor, cursor_shape, outer);
+        let patch_stride = patch.width() as usize * 4;
+        let stride = self.pixel_width * 4;
+        let width = (right - left) * 4;
+
+        for y in top..bottom {
+            let src = (y - outer.1) * patch_stride + (left - outer.0) * 4;
+            let dst = y * stride + left * 4;
+            pixmap.data_mut()[dst..dst + width].copy_from_slice(&patch.data()[src..src + width]);
+        }
+    }
+
+    /// Pixel bounds (left, top, right, bottom) of the cells up to `radius`
+    /// away from `(col, row)`, clamped to the image.
+    fn cells_rect(
+        &self,
+        (col, row): (usize, usize),
+        radius: usize,
+    ) -> (usize, usize, usize, usize) {
+        let (x, y) = self.origin();
+        let (col_px, row_px) = self.cell_size();
+        let (col, row, radius) = (col as f32, row as f32, radius as f32);
+        let left = (x + col_px * (col - radius)).floor().max(0.0) as usize;
+        let top = (y + row_px * (row - radius)).floor().max(0.0) as usize;
+        let right = (x + col_px * (col + radius + 1.0)).ceil() as usize;
+        let bottom = (y + row_px * (row + radius + 1.0)).ceil() as usize;
+
+        (
+            left,
+            top,
+            right.min(self.pixel_width),
+            bottom.min(self.pixel_height),
+        )
+    }
+}
+
+// joiners and variation selectors, which have no glyphs of their own
+fn is_ignorable(ch: char) -> bool {
+    matches!(ch, '\u{200c}' | '\u{200d}' | '\u{fe00}'..='\u{fe0f}' | '\u{e0100}'..='\u{e01ef}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResvgRenderer;
+    use crate::graphemes::Clusters;
+    use crate::renderer::{Renderer, Settings};
+    use crate::{vt, CursorShape, UnderlineStyle};
+
+    fn settings(terminal_size: (usize, usize), dirty_rects: bool) -> Settings {
+        Settings {
+            dirty_rects,
+            ..crate::renderer::test_settings(terminal_size, false)
+        }
+    }
+
+    #[test]
+    fn render_straight_alpha() {
+        let mut renderer = ResvgRenderer::new(settings((10, 2), false));
+        let image = renderer.render(vec![], None, CursorShape::Block);
+
+        // the rounded corners of the background rect are anti-aliased
+        let edges = image
+            .pixels()
+            .filter(|c| c.a >= 64 && c.a < 255)
+            .collect::<Vec<_>>();
+
+        assert!(!edges.is_empty());
+
+        for c in edges {
+            assert!((c.r as i16 - 0x28).abs() <= 4);
+            assert!((c.g as i16 - 0x2a).abs() <= 4);
+            assert!((c.b as i16 - 0x36).abs() <= 4);
+        }
+    }
+
+    #[test]
+    fn render_dirty_rects() {
+        let stdout = [
+            (0.0, "\x1b[41m  \x1b[0m\r\n\r\n\x1b[42m    ".to_owned()),
+            (1.0, "\x1b[3;1H\x1b[44m \x1b[0m".to_owned()),
+            (2.0, "\x1b[5;5H\x1b[43m   ".to_owned()),
+        ];
+
+        let mut dirty = ResvgRenderer::new(settings((8, 6), true));
+        let mut full = ResvgRenderer::new(settings((8, 6), false));
+
+        for frame in vt::frames(stdout.into_iter(), (8, 6), Default::default()) {
+            let expected = full.render(frame.lines.clone(), frame.cursor, CursorShape::Bar);
+            let actual = dirty.render(frame.lines, frame.cursor, CursorShape::Bar);
+
+            assert!(expected.pixels().eq(actual.pixels()));
+        }
+    }
+
+    #[test]
+    fn render_cursor_only_changes() {
+        let stdout = [
+            (
+                0.0,
+                "\x1b[41mfoo\x1b[0m\r\nbar\r\n\x1b[1mWWWWWWWWWWWW\x1b[0m".to_owned(),
+            ),
+            (1.0, "\x1b[1;2H".to_owned()),
+            (2.0, "\x1b[4;6H".to_owned()),
+            (3.0, "\x1b[3;12H".to_owned()),
+            (4.0, "\x1b[1;1H".to_owned()),
+            (5.0, "\x1b[6;16H".to_owned()),
+        ];
+
+        let mut renderer = ResvgRenderer::new(settings((16, 6), false));
+
+        for frame in vt::frames(stdout.into_iter(), (16, 6), Default::default()) {
+            let mut full = ResvgRenderer::new(settings((16, 6), false));
+            let expected = full.render(frame.lines.clone(), frame.cursor, CursorShape::Block);
+            let actual = renderer.render(frame.lines, frame.cursor, CursorShape::Block);
+
+            assert!(expected.pixels().eq(actual.pixels()));
+        }
+    }
+
+    #[test]
+    fn render_blank_cells() {
+        let stdout = [(0.0, "\x1b[41mab  \x1b[0m\r\n  ".to_owned())];
+        let frame = vt::frames(stdout.into_iter(), (8, 2), Default::default())
+            .last()
+            .unwrap();
+        let mut renderer = ResvgRenderer::new(settings((8, 2), false));
+        let image = renderer.render(frame.lines, frame.cursor, CursorShape::Block);
+
+        let pixel = |col: usize, row: usize| {
+            let x = (8.4 * (col as f64 + 1.5)) as usize;
+            let y = (9.8 + 19.6 * (row as f64 + 0.5)) as usize;
+            let c = image.buf()[y * image.width() + x];
+
+            (c.r, c.g, c.b)
+        };
+
+        // trailing spaces keep their background
+        assert_eq!(pixel(3, 0), (0xff, 0x55, 0x55));
+        assert_eq!(pixel(4, 0), (0x28, 0x2a, 0x36));
+
+        // block cursor over a blank cell is drawn inverted
+        assert_eq!(frame.cursor, Some((2, 1)));
+        assert_eq!(pixel(2, 1), (0xf8, 0xf8, 0xf2));
+        assert_eq!(pixel(3, 1), (0x28, 0x2a, 0x36));
+    }
+
+    #[test]
+    fn uncovered_text() {
+        let stdout = [
+            (0.0, "\x1b[41m  \x1b[0m \u{2588}".to_owned()),
+            (1.0, "foo".to_owned()),
+        ];
+
+        // no fonts at all, so usvg can't shape anything
+        let mut renderer = ResvgRenderer::new(settings((8, 2), false));
+        let mut frames = vt::frames(stdout.into_iter(), (8, 2), Default::default());
+
+        let frame = frames.next().unwrap();
+        renderer.render(frame.lines, frame.cursor, CursorShape::Block);
+        assert_eq!(renderer.anomalies(), 0);
+
+        let frame = frames.next().unwrap();
+        renderer.render(frame.lines, frame.cursor, CursorShape::Block);
+        assert_eq!(renderer.anomalies(), 1);
+
+        // a single char the font lacks among ones it has
+        let stdout = [(0.0, "foo".to_owned()), (1.0, " \u{4e2d}".to_owned())];
+
+        let mut renderer = ResvgRenderer::new(crate::renderer::test_settings((8, 2), true));
+
+        let mut frames = vt::frames(stdout.into_iter(), (8, 2), Default::default());
+
+        let frame = frames.next().unwrap();
+        renderer.render(frame.lines, frame.cursor, CursorShape::Block);
+        assert_eq!(renderer.anomalies(), 0);
+
+        let frame = frames.next().unwrap();
+        renderer.render(frame.lines, frame.cursor, CursorShape::Block);
+        assert_eq!(renderer.anomalies(), 1);
+    }
+
+    #[test]
+    fn snap_to_grid() {
+        let renderer = ResvgRenderer::new(Settings {
+            snap_to_grid: true,
+            ..settings((10, 2), false)
+        });
+
+        // 8.4px wide, 19.6px high cells snapped to 8x20
+        assert_eq!(renderer.pixel_size(), (12 * 8, 3 * 20));
+    }
+
+    #[test]
+    fn cell_geometry() {
+        let renderer = ResvgRenderer::new(settings((10, 2), false));
+        let (width, height) = renderer.cell_size();
+        let (x, y) = renderer.origin();
+
+        assert!((width - 8.4).abs() < 0.001);
+        assert!((height - 19.6).abs() < 0.001);
+        assert!((x - 8.4).abs() < 0.001);
+        assert!((y - 9.8).abs() < 0.001);
+    }
+
+    #[test]
+    fn zwj_sequence() {
+        let stdout = [(0.0, "a\u{1f469}\u{200d}\u{1f4bb}b".to_owned())];
+        let clusters = Clusters::default();
+        let frame = vt::frames(stdout.into_iter(), (10, 2), clusters.clone())
+            .next()
+            .unwrap();
+
+        let renderer = ResvgRenderer::new(Settings {
+            clusters,
+            ..settings((10, 2), false)
+        });
+
+        let svg = renderer.frame_svg(frame.lines, None, CursorShape::Block);
+
+        // one text span for the whole sequence, for usvg to shape as one
+        // glyph, with the next char two cells further
+        assert_eq!(svg.matches(r#"class="" style="""#).count(), 3);
+        assert!(
+            svg.contains("x=\"8.333%\" class=\"\" style=\"\">\u{1f469}\u{200d}\u{1f4bb}</tspan>")
+        );
+        assert!(svg.contains(r#"x="25.000%" class="" style="">b</tspan>"#));
+    }
+
+    #[test]
+    fn underline_styles() {
+        let stdout = [(0.0, "\x1b[4mfoo\x1b[24m \x1b[4:3mbar".to_owned())];
+        let clusters = Clusters::default();
+        let frame = vt::frames(stdout.into_iter(), (10, 2), clusters.clone())
+            .next()
+            .unwrap();
+
+        // plain underlines in the configured style, styled ones as recorded
+        let renderer = ResvgRenderer::new(Settings {
+            clusters: clusters.clone(),
+            ..settings((10, 2), false)
+        });
+        let svg = renderer.frame_svg(frame.lines.clone(), None, CursorShape::Block);
+        assert_eq!(svg.matches(r#"class=" un""#).count(), 3);
+        assert_eq!(svg.matches("<path").count(), 1);
+        assert!(svg.contains(">r</tspan>"));
+
+        let renderer = ResvgRenderer::new(Settings {
+            underline_style: UnderlineStyle::Curly,
+            clusters,
+            ..settings((10, 2), false)
+        });
+
+        let svg = renderer.frame_svg(frame.lines, None, CursorShape::Block);
+        assert!(!svg.contains(r#"class=" un""#));
+        assert_eq!(svg.matches("<path").count(), 2);
+    }
+
+    #[test]
+    fn baseline_offset() {
+        let stdout = [(0.0, "foo".to_owned())];
+        let frame = vt::frames(stdout.into_iter(), (10, 2), Default::default())
+            .next()
+            .unwrap();
+
+        let renderer = ResvgRenderer::new(settings((10, 2), false));
+        let svg = renderer.frame_svg(frame.lines.clone(), None, CursorShape::Block);
+        assert!(svg.contains(r#"dy="1em""#));
+
+        let renderer = ResvgRenderer::new(Settings {
+            baseline_offset: -0.25,
+            ..settings((10, 2), false)
+        });
+
+        let svg = renderer.frame_svg(frame.lines, None, CursorShape::Block);
+        assert!(svg.contains(r#"dy="0.75em""#));
+    }
 }
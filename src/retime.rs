@@ -0,0 +1,133 @@
+use anyhow::{anyhow, bail, Result};
+use std::fs;
+
+#[derive(Debug, PartialEq)]
+pub struct Segment {
+    start: f64,
+    end: f64,
+    speed: f64,
+}
+
+pub fn load(path: &str) -> Result<Vec<Segment>> {
+    let path = shellexpand::tilde(path).to_string();
+    let data = fs::read_to_string(&path).map_err(|e| anyhow!("{path}: {e}"))?;
+
+    parse(&data).map_err(|e| anyhow!("{path}: {e}"))
+}
+
+/// Parses `start-end:speed` entries separated by commas or newlines, e.g.
+/// `0-5:1.0, 5-10:4.0`. Lines starting with `#` are comments.
+fn parse(data: &str) -> Result<Vec<Segment>> {
+    let mut segments = data
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| line.split(','))
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(parse_segment)
+        .collect::<Result<Vec<_>>>()?;
+
+    segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    for pair in segments.windows(2) {
+        if pair[1].start < pair[0].end {
+            bail!(
+                "overlapping ranges {}-{} and {}-{}",
+                pair[0].start,
+                pair[0].end,
+                pair[1].start,
+                pair[1].end
+            );
+        }
+    }
+
+    Ok(segments)
+}
+
+fn parse_segment(entry: &str) -> Result<Segment> {
+    let invalid = || anyhow!("invalid entry {entry:?}, expected start-end:speed");
+    let (range, speed) = entry.split_once(':').ok_or_else(invalid)?;
+    let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+    let start: f64 = start.trim().parse().map_err(|_| invalid())?;
+    let end: f64 = end.trim().parse().map_err(|_| invalid())?;
+    let speed: f64 = speed.trim().parse().map_err(|_| invalid())?;
+
+    if start < 0.0 || end <= start {
+        bail!("invalid range in {entry:?}, end must be greater than start");
+    }
+
+    if speed <= 0.0 {
+        bail!("invalid speed in {entry:?}, must be greater than 0");
+    }
+
+    Ok(Segment { start, end, speed })
+}
+
+pub fn apply(
+    stdout: impl Iterator<Item = (f64, String)>,
+    segments: Vec<Segment>,
+) -> impl Iterator<Item = (f64, String)> {
+    stdout.map(move |(time, data)| (retime(&segments, time), data))
+}
+
+fn retime(segments: &[Segment], time: f64) -> f64 {
+    let mut new_time = time;
+
+    for segment in segments.iter().take_while(|s| s.start < time) {
+        let elapsed = time.min(segment.end) - segment.start;
+        new_time -= elapsed - elapsed / segment.speed;
+    }
+
+    new_time
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Segment;
+
+    #[test]
+    fn parse() {
+        let segments = super::parse("# slow intro\n0-5:0.5, 10-20:4\n").unwrap();
+
+        assert_eq!(
+            segments,
+            vec![
+                Segment {
+                    start: 0.0,
+                    end: 5.0,
+                    speed: 0.5
+                },
+                Segment {
+                    start: 10.0,
+                    end: 20.0,
+                    speed: 4.0
+                }
+            ]
+        );
+
+        assert!(super::parse("0-5:1, 4-8:2").is_err());
+        assert!(super::parse("5-1:1").is_err());
+        assert!(super::parse("0-5:0").is_err());
+        assert!(super::parse("0-5").is_err());
+    }
+
+    #[test]
+    fn apply() {
+        let segments = super::parse("2-4:2.0, 6-10:4.0").unwrap();
+
+        let stdout = [
+            (0.0, "a".to_owned()),
+            (1.0, "b".to_owned()),
+            (3.0, "c".to_owned()),
+            (5.0, "d".to_owned()),
+            (8.0, "e".to_owned()),
+            (12.0, "f".to_owned()),
+        ];
+
+        let times = super::apply(stdout.into_iter(), segments)
+            .map(|(time, _)| time)
+            .collect::<Vec<_>>();
+
+        assert_eq!(times, vec![0.0, 1.0, 2.5, 4.0, 5.5, 8.0]);
+    }
+}
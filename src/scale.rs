@@ -0,0 +1,142 @@
+use imgref::ImgVec;
+use rgb::{ComponentBytes, FromSlice, RGBA8};
+
+pub fn fit(
+    (width, height): (usize, usize),
+    max_width: Option<usize>,
+    max_height: Option<usize>,
+) -> (usize, usize) {
+    let sx = max_width.map_or(1.0, |w| w as f64 / width as f64);
+    let sy = max_height.map_or(1.0, |h| h as f64 / height as f64);
+    let scale = sx.min(sy).min(1.0);
+
+    if scale < 1.0 {
+        (
+            ((width as f64 * scale).round() as usize).max(1),
+            ((height as f64 * scale).round() as usize).max(1),
+        )
+    } else {
+        (width, height)
+    }
+}
+
+/// Multiplies the size by `factor`, keeping at least a pixel.
+pub fn by((width, height): (usize, usize), factor: f64) -> (usize, usize) {
+    (
+        ((width as f64 * factor).round() as usize).max(1),
+        ((height as f64 * factor).round() as usize).max(1),
+    )
+}
+
+pub fn resize(image: &ImgVec<RGBA8>, (width, height): (usize, usize)) -> ImgVec<RGBA8> {
+    let src = tiny_skia::PixmapRef::from_bytes(
+        image.buf().as_bytes(),
+        image.width() as u32,
+        image.height() as u32,
+    )
+    .unwrap();
+
+    let mut pixmap = tiny_skia::Pixmap::new(width as u32, height as u32).unwrap();
+    let sx = width as f32 / image.width() as f32;
+    let sy = height as f32 / image.height() as f32;
+
+    // bicubic keeps more detail when shrinking, but costs about three times
+    // as much as bilinear on enlarged frames, where it hardly looks different
+    let quality = if sx > 1.0 || sy > 1.0 {
+        tiny_skia::FilterQuality::Bilinear
+    } else {
+        tiny_skia::FilterQuality::Bicubic
+    };
+
+    let paint = tiny_skia::PixmapPaint {
+        quality,
+        ..Default::default()
+    };
+
+    pixmap.draw_pixmap(
+        0,
+        0,
+        src,
+        &paint,
+        tiny_skia::Transform::from_scale(sx, sy),
+        None,
+    );
+
+    ImgVec::new(pixmap.take().as_rgba().to_vec(), width, height)
+}
+
+/// Shrinks the image by an integer factor, averaging each `factor` x `factor`
+/// block of pixels (box filter). Leftover pixels at the right and bottom edges
+/// are dropped.
+pub fn downsample(image: &ImgVec<RGBA8>, factor: usize) -> ImgVec<RGBA8> {
+    let width = (image.width() / factor).max(1);
+    let height = (image.height() / factor).max(1);
+    let mut buf = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let (mut r, mut g, mut b, mut a) = (0u32, 0u32, 0u32, 0u32);
+            let mut n = 0;
+
+            for row in image.rows().skip(y * factor).take(factor) {
+                for px in row.iter().skip(x * factor).take(factor) {
+                    // weight colors by alpha so transparent pixels don't darken edges
+                    let alpha = px.a as u32;
+                    r += px.r as u32 * alpha;
+                    g += px.g as u32 * alpha;
+                    b += px.b as u32 * alpha;
+                    a += alpha;
+                    n += 1;
+                }
+            }
+
+            // fully transparent blocks end up as transparent black
+            let avg = |sum: u32, n: u32| (sum + n / 2).checked_div(n).unwrap_or(0) as u8;
+
+            buf.push(RGBA8::new(avg(r, a), avg(g, a), avg(b, a), avg(a, n)));
+        }
+    }
+
+    ImgVec::new(buf, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use imgref::ImgVec;
+    use rgb::RGBA8;
+
+    #[test]
+    fn by() {
+        assert_eq!(super::by((800, 400), 1.0), (800, 400));
+        assert_eq!(super::by((801, 401), 1.5), (1202, 602));
+        assert_eq!(super::by((3, 3), 0.1), (1, 1));
+    }
+
+    #[test]
+    fn fit() {
+        assert_eq!(super::fit((800, 400), None, None), (800, 400));
+        assert_eq!(super::fit((800, 400), Some(1000), None), (800, 400));
+        assert_eq!(super::fit((800, 400), Some(400), None), (400, 200));
+        assert_eq!(super::fit((800, 400), None, Some(100)), (200, 100));
+        assert_eq!(super::fit((800, 400), Some(400), Some(100)), (200, 100));
+    }
+
+    #[test]
+    fn downsample() {
+        let black = RGBA8::new(0, 0, 0, 255);
+        let white = RGBA8::new(255, 255, 255, 255);
+        let clear = RGBA8::new(0, 0, 0, 0);
+
+        #[rustfmt::skip]
+        let buf = vec![
+            black, white, white, white, black,
+            white, black, white, clear, black,
+        ];
+
+        let image = super::downsample(&ImgVec::new(buf, 5, 2), 2);
+
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.buf()[0], RGBA8::new(128, 128, 128, 255));
+        assert_eq!(image.buf()[1], RGBA8::new(255, 255, 255, 191));
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{bail, Result};
+
+pub const TERMINAL_SIZE: (usize, usize) = (80, 24);
+
+/// Generates an asciicast exercising the 256 color palette, truecolor, text
+/// attributes, box drawing, wide characters and the cursor.
+pub fn cast() -> String {
+    let mut palette = String::from("256 colors:\r\n");
+
+    for n in 0..=255 {
+        palette.push_str(&format!("\x1b[48;5;{n}m  "));
+
+        if n % 32 == 31 {
+            palette.push_str("\x1b[0m\r\n");
+        }
+    }
+
+    let mut truecolor = String::from("truecolor: ");
+
+    for i in 0..64 {
+        let (r, g, b) = (i * 4, 255 - i * 4, 128);
+        truecolor.push_str(&format!("\x1b[48;2;{r};{g};{b}m "));
+    }
+
+    truecolor.push_str("\x1b[0m\r\n");
+
+    let events = [
+        palette,
+        truecolor,
+        "\x1b[1mbold\x1b[0m \x1b[3mitalic\x1b[0m \x1b[4munderline\x1b[0m \x1b[1;3;4;31mall\x1b[0m\r\n"
+            .to_owned(),
+        "┌──┬──┐\r\n│ab│cd│\r\n└──┴──┘\r\n".to_owned(),
+        "wide: 漢字\r\n".to_owned(),
+        "$ ".to_owned(),
+        "\x1b[5 q".to_owned(),
+    ];
+
+    let mut cast = format!(
+        "{{\"version\": 2, \"width\": {}, \"height\": {}}}\n",
+        TERMINAL_SIZE.0, TERMINAL_SIZE.1
+    );
+
+    for (i, data) in events.iter().enumerate() {
+        let time = 0.5 * (i + 1) as f64;
+        cast.push_str(&serde_json::to_string(&(time, "o", data)).unwrap());
+        cast.push('\n');
+    }
+
+    cast
+}
+
+/// Walks the blocks of a GIF file, returning its logical screen size and the
+/// number of frames (image descriptors) in it.
+pub fn gif_info(gif: &[u8]) -> Result<((usize, usize), usize)> {
+    if gif.len() < 13 || !gif.starts_with(b"GIF89a") && !gif.starts_with(b"GIF87a") {
+        bail!("not a GIF file");
+    }
+
+    let width = u16::from_le_bytes([gif[6], gif[7]]) as usize;
+    let height = u16::from_le_bytes([gif[8], gif[9]]) as usize;
+    let mut pos = 13 + color_table_len(gif[10]);
+    let mut frames = 0;
+
+    loop {
+        match gif.get(pos) {
+            Some(0x21) => {
+                pos = skip_sub_blocks(gif, pos + 2)?;
+            }
+
+            Some(0x2c) => {
+                let packed = *gif.get(pos + 9).ok_or_else(truncated)?;
+                pos = skip_sub_blocks(gif, pos + 10 + color_table_len(packed) + 1)?;
+                frames += 1;
+            }
+
+            Some(0x3b) => break,
+            Some(byte) => bail!("unexpected block 0x{byte:02x} at offset {pos}"),
+            None => return Err(truncated()),
+        }
+    }
+
+    Ok(((width, height), frames))
+}
+
+fn color_table_len(packed: u8) -> usize {
+    if packed & 0x80 != 0 {
+        3 << ((packed & 0x07) + 1)
+    } else {
+        0
+    }
+}
+
+fn skip_sub_blocks(gif: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        let len = *gif.get(pos).ok_or_else(truncated)? as usize;
+        pos += 1 + len;
+
+        if len == 0 {
+            return Ok(pos);
+        }
+    }
+}
+
+fn truncated() -> anyhow::Error {
+    anyhow::anyhow!("truncated GIF file")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    #[test]
+    fn cast() {
+        let cast = super::cast();
+        let (header, events) = crate::asciicast::open(BufReader::new(cast.as_bytes())).unwrap();
+
+        assert_eq!(header.terminal_size, super::TERMINAL_SIZE);
+        assert_eq!(events.filter(|e| e.is_ok()).count(), 7);
+    }
+
+    #[test]
+    fn low_memory() {
+        let render = |low_memory| {
+            let config = crate::Config {
+                font_dirs: vec![concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures").to_owned()],
+                font_family: "DejaVu Sans Mono".to_owned(),
+                low_memory,
+                show_progress_bar: false,
+                ..Default::default()
+            };
+
+            let mut gif = Vec::new();
+            crate::run(super::cast().as_bytes(), &mut gif, config).unwrap();
+
+            gif
+        };
+
+        // frames generated on demand end up the same as the ones kept around
+        assert_eq!(render(true), render(false));
+    }
+
+    #[test]
+    fn gif_info() {
+        let gif = [
+            b"GIF89a".as_slice(),
+            &[3, 0, 2, 0, 0x80, 0, 0],
+            &[0, 0, 0, 255, 255, 255],
+            &[0x21, 0xf9, 4, 0, 10, 0, 0, 0],
+            &[0x2c, 0, 0, 0, 0, 3, 0, 2, 0, 0],
+            &[2, 2, 0x44, 0x01, 0],
+            &[0x21, 0xfe, 2, b'h', b'i', 0],
+            &[0x2c, 0, 0, 0, 0, 1, 0, 1, 0, 0x80, 1, 2, 3, 4, 5, 6],
+            &[2, 1, 0x44, 0],
+            &[0x3b],
+        ]
+        .concat();
+
+        assert_eq!(super::gif_info(&gif).unwrap(), ((3, 2), 2));
+        assert!(super::gif_info(&gif[..gif.len() - 3]).is_err());
+        assert!(super::gif_info(b"PNG").is_err());
+    }
+}
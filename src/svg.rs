@@ -0,0 +1,266 @@
+use crate::color_transform::Matrix;
+use rgb::RGB8;
+use std::fmt::Write as _;
+
+/// Where and how a terminal document is placed on the output canvas. These
+/// mirror the pixel passes of raster output.
+pub struct Placement<'a> {
+    /// Size the document is drawn at, scaled from `document_size`.
+    pub size: (usize, usize),
+    pub document_size: (usize, usize),
+    pub canvas_size: (usize, usize),
+    pub pad_color: RGB8,
+    /// PNG shown behind the terminal, drawn at `terminal_opacity` over it.
+    pub backdrop: Option<&'a [u8]>,
+    pub terminal_opacity: f64,
+    /// Applied in linear RGB, like the raster color transform.
+    pub color_matrix: Option<&'a Matrix>,
+    pub flatten: Option<RGB8>,
+}
+
+/// Wraps a complete SVG document (as made by the resvg renderer) in an outer
+/// one according to the placement. The document is returned as is when
+/// there's nothing to do.
+pub fn place(document: &str, placement: &Placement) -> String {
+    let Placement {
+        size,
+        document_size,
+        canvas_size,
+        ..
+    } = *placement;
+
+    let backdrop = placement.backdrop;
+
+    if size == document_size
+        && canvas_size == size
+        && backdrop.is_none()
+        && placement.color_matrix.is_none()
+        && placement.flatten.is_none()
+    {
+        return document.to_owned();
+    }
+
+    let document = document
+        .strip_prefix(r#"<?xml version="1.0"?>"#)
+        .unwrap_or(document);
+
+    let mut svg = String::new();
+
+    let _ = write!(
+        svg,
+        r#"<?xml version="1.0"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{}" height="{}">"#,
+        canvas_size.0, canvas_size.1
+    );
+
+    if let Some(m) = placement.color_matrix {
+        let _ = write!(
+            svg,
+            r#"<defs><filter id="color-transform" x="0" y="0" width="100%" height="100%" color-interpolation-filters="linearRGB"><feColorMatrix type="matrix" values="{} {} {} 0 0 {} {} {} 0 0 {} {} {} 0 0 0 0 0 1 0" /></filter></defs>"#,
+            m[0][0], m[0][1], m[0][2], m[1][0], m[1][1], m[1][2], m[2][0], m[2][1], m[2][2]
+        );
+    }
+
+    if let Some(color) = placement.flatten {
+        let _ = write!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="{}" />"#,
+            hex(color)
+        );
+    }
+
+    if placement.color_matrix.is_some() {
+        svg.push_str(r#"<g filter="url(#color-transform)">"#);
+    } else {
+        svg.push_str("<g>");
+    }
+
+    if canvas_size != size {
+        let _ = write!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="{}" />"#,
+            hex(placement.pad_color)
+        );
+    }
+
+    // centered, clipped by the canvas where it doesn't fit
+    let x = (canvas_size.0 as isize - size.0 as isize) / 2;
+    let y = (canvas_size.1 as isize - size.1 as isize) / 2;
+    let _ = write!(svg, r#"<g transform="translate({x} {y})">"#);
+
+    if let Some(png) = backdrop {
+        let _ = write!(
+            svg,
+            r#"<image width="{}" height="{}" xlink:href="data:image/png;base64,{}" />"#,
+            size.0,
+            size.1,
+            base64(png)
+        );
+    }
+
+    let scale_x = size.0 as f64 / document_size.0 as f64;
+    let scale_y = size.1 as f64 / document_size.1 as f64;
+    let _ = write!(svg, r#"<g transform="scale({scale_x:.5} {scale_y:.5})""#);
+
+    if backdrop.is_some() {
+        let _ = write!(svg, r#" opacity="{:.3}""#, placement.terminal_opacity);
+    }
+
+    svg.push('>');
+    svg.push_str(document.trim());
+    svg.push_str("</g></g></g></svg>\n");
+
+    svg
+}
+
+fn hex(color: RGB8) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+pub fn animate(frames: &[(f64, String)], duration: f64, repeat: bool) -> String {
+    let mut svg = String::new();
+    let repeat_count = if repeat { "indefinite" } else { "1" };
+
+    for (i, (time, frame)) in frames.iter().enumerate() {
+        let begin = key_time(*time, duration);
+
+        svg.push_str(r#"<g display="none">"#);
+
+        match frames.get(i + 1) {
+            Some((next_time, _)) => {
+                let end = key_time(*next_time, duration);
+
+                let _ = write!(
+                    svg,
+                    r#"<animate attributeName="display" values="none;inline;none" keyTimes="0;{begin:.5};{end:.5}" dur="{duration:.3}s" calcMode="discrete" repeatCount="{repeat_count}" fill="freeze" />"#
+                );
+            }
+
+            None => {
+                let _ = write!(
+                    svg,
+                    r#"<animate attributeName="display" values="none;inline" keyTimes="0;{begin:.5}" dur="{duration:.3}s" calcMode="discrete" repeatCount="{repeat_count}" fill="freeze" />"#
+                );
+            }
+        }
+
+        svg.push_str(frame);
+        svg.push_str("</g>");
+    }
+
+    svg
+}
+
+fn key_time(time: f64, duration: f64) -> f64 {
+    if duration > 0.0 {
+        (time / duration).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn animate() {
+        let frames = [
+            (0.0, "<text>a</text>".to_owned()),
+            (1.0, "<text>b</text>".to_owned()),
+        ];
+
+        let svg = super::animate(&frames, 4.0, true);
+
+        assert_eq!(svg.matches("<g display=\"none\">").count(), 2);
+        assert!(svg.contains(r#"keyTimes="0;0.00000;0.25000""#));
+        assert!(svg.contains(r#"keyTimes="0;0.25000""#));
+        assert!(svg.contains(r#"repeatCount="indefinite""#));
+        assert!(svg.contains("<text>a</text>"));
+        assert!(svg.contains("<text>b</text>"));
+
+        let svg = super::animate(&frames, 4.0, false);
+
+        assert!(svg.contains(r#"repeatCount="1""#));
+    }
+
+    #[test]
+    fn place() {
+        use super::Placement;
+        use rgb::RGB8;
+
+        let document = r#"<?xml version="1.0"?>
+<svg width="100" height="50"><text>a</text></svg>
+"#;
+
+        let placement = Placement {
+            size: (100, 50),
+            document_size: (100, 50),
+            canvas_size: (100, 50),
+            pad_color: RGB8::new(0, 0, 0),
+            backdrop: None,
+            terminal_opacity: 1.0,
+            color_matrix: None,
+            flatten: None,
+        };
+
+        assert_eq!(super::place(document, &placement), document);
+
+        let svg = super::place(
+            document,
+            &Placement {
+                size: (50, 25),
+                canvas_size: (60, 45),
+                pad_color: RGB8::new(255, 0, 0),
+                backdrop: Some(b"png"),
+                terminal_opacity: 0.5,
+                color_matrix: Some(crate::color_transform::matrix(
+                    crate::ColorTransform::Grayscale,
+                )),
+                flatten: Some(RGB8::new(0, 0, 255)),
+                ..placement
+            },
+        );
+
+        assert_eq!(svg.matches("<?xml").count(), 1);
+        assert!(svg.contains(r#"width="60" height="45""#));
+        assert!(svg.contains(r##"fill="#ff0000""##));
+        assert!(svg.contains(r##"fill="#0000ff""##));
+        assert!(svg.contains(r#"filter="url(#color-transform)""#));
+        assert!(svg.contains("0.2126 0.7152 0.0722 0 0"));
+        assert!(svg.contains(r#"translate(5 10)"#));
+        assert!(svg.contains(r#"scale(0.50000 0.50000)" opacity="0.500""#));
+        assert!(svg.contains("data:image/png;base64,cG5n"));
+        assert!(svg.contains("<text>a</text>"));
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(super::base64(b""), "");
+        assert_eq!(super::base64(b"f"), "Zg==");
+        assert_eq!(super::base64(b"fo"), "Zm8=");
+        assert_eq!(super::base64(b"foo"), "Zm9v");
+        assert_eq!(super::base64(b"foobar"), "Zm9vYmFy");
+    }
+}
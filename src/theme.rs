@@ -1,16 +1,23 @@
+use std::fs;
+use std::path::Path;
 use std::str::FromStr;
 
-use anyhow::bail;
+use anyhow::{bail, Context};
 use rgb::RGB8;
+use serde::Deserialize;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Theme {
     pub background: RGB8,
     pub foreground: RGB8,
     palette: [RGB8; 16],
+    pub cursor: Option<RGB8>,
+    pub cursor_text: Option<RGB8>,
+    /// Number of colors of the recording's terminal: 8, 16 or 256.
+    pub colors: u16,
 }
 
-fn parse_hex_triplet(triplet: &str) -> anyhow::Result<RGB8> {
+pub fn parse_hex_triplet(triplet: &str) -> anyhow::Result<RGB8> {
     if triplet.len() < 6 || triplet.len() > 6 {
         bail!("{} is not a hex triplet", triplet);
     }
@@ -49,12 +56,34 @@ impl FromStr for Theme {
             background,
             foreground,
             palette,
+            cursor: None,
+            cursor_text: None,
+            colors: 256,
         })
     }
 }
 
 impl Theme {
+    /// Looks up an indexed color as shown by a terminal with `colors` colors:
+    /// without bright colors, 8-15 show as 0-7, and colors beyond the palette
+    /// show as the closest palette color.
     pub fn color(&self, color: u8) -> RGB8 {
+        match color {
+            8..=15 if self.colors <= 8 => self.palette[color as usize - 8],
+            16.. if self.colors <= 16 => {
+                let target = self.xterm_color(color);
+                let palette = &self.palette[..self.colors as usize];
+
+                *palette
+                    .iter()
+                    .min_by_key(|c| distance(**c, target))
+                    .unwrap()
+            }
+            _ => self.xterm_color(color),
+        }
+    }
+
+    fn xterm_color(&self, color: u8) -> RGB8 {
         match color {
             0..=15 => self.palette[color as usize],
 
@@ -88,6 +117,65 @@ impl Theme {
     }
 }
 
+fn distance(a: RGB8, b: RGB8) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+
+    d(a.r, b.r) + d(a.g, b.g) + d(a.b, b.b)
+}
+
+#[derive(Deserialize)]
+struct ThemeFile {
+    background: String,
+    foreground: String,
+    palette: Vec<String>,
+}
+
+impl TryFrom<ThemeFile> for Theme {
+    type Error = anyhow::Error;
+
+    fn try_from(file: ThemeFile) -> Result<Self, Self::Error> {
+        if file.palette.len() != 8 && file.palette.len() != 16 {
+            bail!(
+                "expected 8 or 16 palette colors, got {}",
+                file.palette.len()
+            );
+        }
+
+        [file.background, file.foreground]
+            .into_iter()
+            .chain(file.palette)
+            .map(|color| color.trim_start_matches('#').to_owned())
+            .collect::<Vec<_>>()
+            .join(",")
+            .parse()
+    }
+}
+
+/// Loads a theme from a TOML or YAML file (by extension) with `background`,
+/// `foreground` and `palette` (8 or 16 colors) keys.
+pub fn load(path: &str) -> anyhow::Result<Theme> {
+    let path = shellexpand::tilde(path).to_string();
+    let data = fs::read_to_string(&path).with_context(|| path.clone())?;
+    let ext = Path::new(&path).extension().and_then(|ext| ext.to_str());
+
+    parse_file(&data, ext.unwrap_or("")).with_context(|| path.clone())
+}
+
+fn parse_file(data: &str, ext: &str) -> anyhow::Result<Theme> {
+    let file: ThemeFile = match ext {
+        "toml" => toml::from_str(data)?,
+        "yaml" | "yml" => serde_yaml::from_str(data)?,
+        _ => bail!("unsupported theme file extension, expected .toml, .yaml or .yml"),
+    };
+
+    file.try_into()
+}
+
+/// Dracula with an 8 color palette, for tests.
+#[cfg(test)]
+pub const TEST_THEME: &str =
+    "282a36,f8f8f2,21222c,ff5555,50fa7b,f1fa8c,bd93f9,ff79c6,8be9fd,f8f8f2";
+
 #[cfg(test)]
 mod tests {
     use super::Theme;
@@ -336,4 +424,51 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn parse_file() {
+        let toml = r##"
+            background = "#282a36"
+            foreground = "f8f8f2"
+            palette = ["21222c", "ff5555", "50fa7b", "f1fa8c", "bd93f9", "ff79c6", "8be9fd", "f8f8f2"]
+        "##;
+
+        let yaml = r##"
+background: "#282a36"
+foreground: f8f8f2
+palette: [21222c, ff5555, 50fa7b, f1fa8c, bd93f9, ff79c6, 8be9fd, f8f8f2]
+"##;
+
+        let expected = super::TEST_THEME.parse::<Theme>().unwrap();
+
+        assert_eq!(super::parse_file(toml, "toml").unwrap(), expected);
+        assert_eq!(super::parse_file(yaml, "yml").unwrap(), expected);
+        assert!(super::parse_file(toml, "json").is_err());
+        assert!(super::parse_file("background = \"000000\"", "toml").is_err());
+        assert!(super::parse_file(&toml.replace("ff5555", "red"), "toml").is_err());
+        assert!(super::parse_file(&toml.replace("\"f8f8f2\"]", "]"), "toml").is_err());
+    }
+
+    #[test]
+    fn color_depth() {
+        let mut theme = "000000,ffffff,000000,aa0000,00aa00,aaaa00,0000aa,aa00aa,00aaaa,aaaaaa,555555,ff5555,55ff55,ffff55,5555ff,ff55ff,55ffff,ffffff"
+            .parse::<Theme>()
+            .unwrap();
+
+        let (red, bright_red) = (RGB8::new(0xaa, 0, 0), RGB8::new(0xff, 0x55, 0x55));
+
+        // 196 is ff0000 and 203 is ff5f5f in the 6x6x6 cube
+        assert_eq!(theme.color(9), bright_red);
+        assert_eq!(theme.color(196), RGB8::new(0xff, 0, 0));
+        assert_eq!(theme.color(203), RGB8::new(0xff, 0x5f, 0x5f));
+
+        theme.colors = 16;
+        assert_eq!(theme.color(9), bright_red);
+        assert_eq!(theme.color(196), red);
+        assert_eq!(theme.color(203), bright_red);
+
+        theme.colors = 8;
+        assert_eq!(theme.color(9), red);
+        assert_eq!(theme.color(196), red);
+    }
 }
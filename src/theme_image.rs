@@ -0,0 +1,214 @@
+use crate::theme::Theme;
+use anyhow::{anyhow, Result};
+use log::info;
+use rgb::RGB8;
+
+const MAX_SAMPLES: usize = 65536;
+
+// hues of the red, green, yellow, blue, magenta and cyan ANSI slots
+const SLOT_HUES: [f64; 6] = [0.0, 120.0, 60.0, 240.0, 300.0, 180.0];
+
+pub fn extract(path: &str) -> Result<Theme> {
+    let path = shellexpand::tilde(path).to_string();
+    let image = tiny_skia::Pixmap::load_png(&path).map_err(|e| anyhow!("{path}: {e}"))?;
+    let step = (image.pixels().len() / MAX_SAMPLES).max(1);
+
+    let pixels = image
+        .pixels()
+        .iter()
+        .step_by(step)
+        .map(|c| c.demultiply())
+        .filter(|c| c.alpha() > 0)
+        .map(|c| RGB8::new(c.red(), c.green(), c.blue()))
+        .collect::<Vec<_>>();
+
+    if pixels.is_empty() {
+        return Err(anyhow!("{path}: image has no opaque pixels"));
+    }
+
+    let theme = theme_string(&build(median_cut(pixels, 16)));
+    info!("derived theme from {path}: {theme}");
+
+    theme.parse()
+}
+
+/// Splits the pixels into (up to) `count` boxes along their widest channel
+/// and returns the average color of each box.
+fn median_cut(pixels: Vec<RGB8>, count: usize) -> Vec<RGB8> {
+    let mut boxes = vec![pixels];
+
+    while boxes.len() < count {
+        let (i, channel, range) = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+
+                (i, channel, range)
+            })
+            .max_by_key(|(_, _, range)| *range)
+            .unwrap();
+
+        if range == 0 {
+            break;
+        }
+
+        let mut lower = boxes.swap_remove(i);
+        lower.sort_unstable_by_key(|c| channel_value(c, channel));
+        let upper = lower.split_off(lower.len() / 2);
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map(|pixels| average(pixels)).collect()
+}
+
+fn widest_channel(pixels: &[RGB8]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = pixels.iter().map(|c| channel_value(c, channel));
+            let min = values.clone().min().unwrap_or(0);
+            let max = values.max().unwrap_or(0);
+
+            (channel, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
+
+fn channel_value(c: &RGB8, channel: usize) -> u8 {
+    match channel {
+        0 => c.r,
+        1 => c.g,
+        _ => c.b,
+    }
+}
+
+fn average(pixels: &[RGB8]) -> RGB8 {
+    let len = pixels.len().max(1) as u64;
+
+    let (r, g, b) = pixels.iter().fold((0u64, 0u64, 0u64), |(r, g, b), c| {
+        (r + c.r as u64, g + c.g as u64, b + c.b as u64)
+    });
+
+    RGB8::new((r / len) as u8, (g / len) as u8, (b / len) as u8)
+}
+
+fn luma(c: &RGB8) -> f64 {
+    0.2126 * c.r as f64 + 0.7152 * c.g as f64 + 0.0722 * c.b as f64
+}
+
+fn hue_saturation(c: &RGB8) -> (f64, f64) {
+    let (r, g, b) = (c.r as f64, c.g as f64, c.b as f64);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    if delta == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+
+    (hue, delta / max)
+}
+
+/// Returns the background, foreground and 16 palette colors. The darkest and
+/// lightest colors become background/foreground and black/white slots, the
+/// remaining slots get the color closest in hue to the slot's ANSI color.
+fn build(mut colors: Vec<RGB8>) -> Vec<RGB8> {
+    colors.sort_by(|a, b| luma(a).total_cmp(&luma(b)));
+
+    let darkest = colors[0];
+    let dark = colors[1.min(colors.len() - 1)];
+    let lightest = colors[colors.len() - 1];
+    let light = colors[colors.len().saturating_sub(2)];
+
+    let chromatic = |hue: f64| {
+        *colors
+            .iter()
+            .min_by(|a, b| hue_score(a, hue).total_cmp(&hue_score(b, hue)))
+            .unwrap()
+    };
+
+    let mut theme = vec![darkest, lightest, darkest];
+    theme.extend(SLOT_HUES.iter().map(|hue| chromatic(*hue)));
+    theme.push(light);
+    theme.push(dark);
+    theme.extend(SLOT_HUES.iter().map(|hue| chromatic(*hue)));
+    theme.push(lightest);
+
+    theme
+}
+
+fn hue_score(c: &RGB8, hue: f64) -> f64 {
+    let (h, s) = hue_saturation(c);
+    let distance = (h - hue).abs();
+    let distance = distance.min(360.0 - distance);
+
+    distance / 180.0 - s
+}
+
+fn theme_string(colors: &[RGB8]) -> String {
+    colors
+        .iter()
+        .map(|c| format!("{:02x}{:02x}{:02x}", c.r, c.g, c.b))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use rgb::RGB8;
+
+    #[test]
+    fn median_cut() {
+        let pixels = [
+            RGB8::new(10, 10, 10),
+            RGB8::new(12, 12, 12),
+            RGB8::new(250, 250, 250),
+            RGB8::new(252, 252, 252),
+        ];
+
+        let mut colors = super::median_cut(pixels.repeat(10), 16);
+        colors.sort_by_key(|c| c.r);
+        colors.dedup();
+
+        assert_eq!(
+            colors,
+            vec![
+                RGB8::new(10, 10, 10),
+                RGB8::new(12, 12, 12),
+                RGB8::new(250, 250, 250),
+                RGB8::new(252, 252, 252)
+            ]
+        );
+    }
+
+    #[test]
+    fn build() {
+        let colors = vec![
+            RGB8::new(20, 20, 30),
+            RGB8::new(240, 240, 230),
+            RGB8::new(200, 40, 40),
+            RGB8::new(40, 200, 40),
+            RGB8::new(40, 40, 200),
+        ];
+
+        let theme = super::build(colors);
+
+        assert_eq!(theme.len(), 18);
+        assert_eq!(theme[0], RGB8::new(20, 20, 30));
+        assert_eq!(theme[1], RGB8::new(240, 240, 230));
+        assert_eq!(theme[3], RGB8::new(200, 40, 40));
+        assert_eq!(theme[4], RGB8::new(40, 200, 40));
+        assert_eq!(theme[6], RGB8::new(40, 40, 200));
+        assert!(super::theme_string(&theme).parse::<super::Theme>().is_ok());
+    }
+}
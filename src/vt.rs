@@ -1,26 +1,78 @@
+use crate::graphemes::Clusters;
+use crate::{asciicast, bidi, graphics, CursorShape, Seek, UnderlineStyle};
 use log::debug;
+use std::fmt::Write;
 
+#[derive(Clone)]
+pub struct Frame {
+    pub time: f64,
+    pub lines: Vec<Vec<(char, avt::Pen)>>,
+    pub cursor: Option<(usize, usize)>,
+    pub cursor_shape: Option<CursorShape>,
+    /// Labels of the markers since the previous frame.
+    pub markers: Vec<String>,
+    /// Window title last set with OSC 0 or 2.
+    pub title: Option<String>,
+}
+
+/// Emulates the terminal, producing a frame for each visual change. Grapheme
+/// clusters are recorded in `clusters` for the renderers to resolve.
 pub fn frames(
     stdout: impl Iterator<Item = (f64, String)>,
     terminal_size: (usize, usize),
-) -> impl Iterator<Item = (f64, Vec<Vec<(char, avt::Pen)>>, Option<(usize, usize)>)> {
+    clusters: Clusters,
+) -> impl Iterator<Item = Frame> {
     let mut vt = avt::Vt::new(terminal_size.0, terminal_size.1);
     let mut prev_cursor = None;
+    let mut cursor_shape = None;
+    let mut graphics = graphics::Filter::default();
+    let mut markers = Vec::new();
+    let mut underlines = Underlines::new(terminal_size, clusters.clone());
+    let mut title = None;
+    let mut titles = TitleParser::default();
 
     stdout.filter_map(move |(time, data)| {
-        let (changed_lines, _) = vt.feed_str(&data);
+        let data = graphics.strip(&data);
+        let data = underlines.feed(&data);
+        let (changed_lines, _) = vt.feed_str(&clusters.substitute(&data));
         let cursor = vt.cursor();
+        let prev_cursor_shape = cursor_shape;
+        let prev_title = title.clone();
+
+        if let Some(shape) = parse_cursor_shape(&data) {
+            cursor_shape = shape;
+        }
+
+        markers.extend(parse_markers(&data).filter(|label| !label.is_empty()));
+
+        if let Some(text) = titles.parse(&data) {
+            title = Some(text).filter(|text| !text.is_empty());
+        }
 
-        if !changed_lines.is_empty() || cursor != prev_cursor {
+        if !changed_lines.is_empty()
+            || cursor != prev_cursor
+            || cursor_shape != prev_cursor_shape
+            || !markers.is_empty()
+            || title != prev_title
+        {
             prev_cursor = cursor;
 
-            let lines = vt
+            let mut lines = vt
                 .lines()
                 .iter()
-                .map(|line| line.cells().collect())
-                .collect();
+                .map(|line| line.cells().map(printable).collect())
+                .collect::<Vec<_>>();
 
-            Some((time, lines, cursor))
+            underlines.apply(&mut lines);
+
+            Some(Frame {
+                time,
+                lines,
+                cursor,
+                cursor_shape,
+                markers: std::mem::take(&mut markers),
+                title: title.clone(),
+            })
         } else {
             prev_cursor = cursor;
             debug!("skipping frame with no visual changes: {:?}", data);
@@ -30,6 +82,553 @@ pub fn frames(
     })
 }
 
+/// Blanks C0 and C1 control characters the terminal let through into a cell,
+/// so that stray bytes in imperfect recordings don't render as missing glyphs.
+fn printable((ch, pen): (char, avt::Pen)) -> (char, avt::Pen) {
+    if ch.is_control() {
+        (' ', pen)
+    } else {
+        (ch, pen)
+    }
+}
+
+/// Finds the last DECSCUSR (`CSI Ps SP q`) sequence in the data. The inner
+/// `None` means reset to the default shape.
+fn parse_cursor_shape(data: &str) -> Option<Option<CursorShape>> {
+    let mut shape = None;
+    let mut rest = data;
+
+    while let Some(i) = rest.find("\x1b[") {
+        rest = &rest[i + 2..];
+        let len = rest.bytes().take_while(u8::is_ascii_digit).count();
+
+        if rest[len..].starts_with(" q") {
+            shape = match &rest[..len] {
+                "" | "0" => Some(None),
+                "1" | "2" => Some(Some(CursorShape::Block)),
+                "3" | "4" => Some(Some(CursorShape::Underline)),
+                "5" | "6" => Some(Some(CursorShape::Bar)),
+                _ => shape,
+            };
+        }
+    }
+
+    shape
+}
+
+/// Tracks SGR underline styles (`4:2` for double, `4:3` for curly etc.), which
+/// the terminal emulator doesn't keep. A second emulator is fed the same output
+/// with every SGR sequence touching underline replaced by a foreground color
+/// standing for the style, so its cells hold the styles of the matching cells
+/// of the first one. Sequences split across events are held back until
+/// they're complete.
+struct Underlines {
+    vt: avt::Vt,
+    clusters: Clusters,
+    pending: String,
+    styled: bool,
+}
+
+impl Underlines {
+    fn new((cols, rows): (usize, usize), clusters: Clusters) -> Self {
+        Self {
+            vt: avt::Vt::new(cols, rows),
+            clusters,
+            pending: String::new(),
+            styled: false,
+        }
+    }
+
+    /// Feeds the data to the style-tracking emulator and returns it with
+    /// underline styles turned into plain underline on/off, which the terminal
+    /// emulator understands.
+    fn feed(&mut self, data: &str) -> String {
+        let data = std::mem::take(&mut self.pending) + data;
+        let mut out = String::with_capacity(data.len());
+        let mut shadow = String::with_capacity(data.len());
+        let mut rest = data.as_str();
+
+        while let Some(i) = rest.find("\x1b[") {
+            out.push_str(&rest[..i + 2]);
+            shadow.push_str(&rest[..i]);
+            let seq = &rest[i + 2..];
+
+            let len = seq
+                .bytes()
+                .take_while(|b| b.is_ascii_digit() || *b == b';' || *b == b':')
+                .count();
+
+            if len == seq.len() {
+                out.truncate(out.len() - 2);
+                self.pending = rest[i..].to_owned();
+                rest = "";
+                break;
+            }
+
+            if !seq[len..].starts_with('m') {
+                shadow.push_str("\x1b[");
+                rest = seq;
+                continue;
+            }
+
+            let (params, style) = sgr_underline(&seq[..len]);
+            out.push_str(&params);
+
+            match style {
+                Some(Some(style)) => {
+                    self.styled = true;
+                    let _ = write!(shadow, "\x1b[38;5;{}m", style as u8 + 1);
+                }
+
+                Some(None) => shadow.push_str("\x1b[39m"),
+                None => {}
+            }
+
+            out.push('m');
+            rest = &seq[len + 1..];
+        }
+
+        if rest.ends_with('\x1b') {
+            self.pending.push('\x1b');
+            rest = &rest[..rest.len() - 1];
+        }
+
+        out.push_str(rest);
+        shadow.push_str(rest);
+        self.vt.feed_str(&self.clusters.substitute(&shadow));
+
+        out
+    }
+
+    /// Replaces the chars of cells underlined in an explicit style with
+    /// placeholders carrying the style, see `Clusters::underlined`.
+    fn apply(&self, lines: &mut [Vec<(char, avt::Pen)>]) {
+        if !self.styled {
+            return;
+        }
+
+        for (line, styles) in lines.iter_mut().zip(self.vt.lines()) {
+            for ((ch, pen), (_, style)) in line.iter_mut().zip(styles.cells()) {
+                if let (true, Some(avt::Color::Indexed(n))) =
+                    (pen.is_underline(), style.foreground())
+                {
+                    if let Some(style) = UNDERLINE_STYLES.get(n as usize - 1) {
+                        *ch = self.clusters.underlined(*ch, *style);
+                    }
+                }
+            }
+        }
+    }
+}
+
+const UNDERLINE_STYLES: [UnderlineStyle; 4] = [
+    UnderlineStyle::Single,
+    UnderlineStyle::Double,
+    UnderlineStyle::Curly,
+    UnderlineStyle::Dotted,
+];
+
+/// Rewrites `4:x` subparameters of an SGR sequence to `4`/`24` and returns
+/// the underline style it leaves the pen with: `Some(None)` for none or plain
+/// `4`, `None` when it doesn't touch underline.
+fn sgr_underline(params: &str) -> (String, Option<Option<UnderlineStyle>>) {
+    let params = params.split(';').collect::<Vec<_>>();
+    let mut out = Vec::with_capacity(params.len());
+    let mut style = None;
+    let mut i = 0;
+
+    while i < params.len() {
+        let param = params[i];
+
+        match param {
+            "" | "0" | "4" | "24" => style = Some(None),
+
+            // extended colors, whose arguments aren't SGR params
+            "38" | "48" => {
+                let args = match params.get(i + 1) {
+                    Some(&"5") => 2,
+                    Some(&"2") => 4,
+                    _ => 0,
+                };
+
+                let end = (i + 1 + args).min(params.len());
+                out.extend(&params[i..end]);
+                i = end;
+
+                continue;
+            }
+
+            _ => {}
+        }
+
+        match param.split_once(':') {
+            Some(("4", sub)) => {
+                style = Some(match sub {
+                    "1" => Some(UnderlineStyle::Single),
+                    "2" => Some(UnderlineStyle::Double),
+                    "3" => Some(UnderlineStyle::Curly),
+                    "4" | "5" => Some(UnderlineStyle::Dotted),
+                    _ => None,
+                });
+
+                out.push(if sub == "0" { "24" } else { "4" });
+            }
+
+            _ => out.push(param),
+        }
+
+        i += 1;
+    }
+
+    (out.join(";"), style)
+}
+
+/// Finds the labels of the markers inlined by `asciicast::inline_markers`.
+fn parse_markers(data: &str) -> impl Iterator<Item = String> + '_ {
+    data.split(asciicast::MARKER_OSC)
+        .skip(1)
+        .filter_map(|rest| Some(rest[..rest.find('\x07')?].to_owned()))
+}
+
+/// Longest unterminated title sequence kept waiting for the rest of it.
+const MAX_PENDING_TITLE: usize = 4096;
+
+/// Finds the window title set by OSC 0 or OSC 2 sequences (terminated by BEL or
+/// ST), holding back a sequence split across events until it's complete.
+#[derive(Default)]
+struct TitleParser {
+    pending: String,
+}
+
+impl TitleParser {
+    /// Returns the title set by the last complete sequence.
+    fn parse(&mut self, data: &str) -> Option<String> {
+        let data = std::mem::take(&mut self.pending) + data;
+        let mut title = None;
+        let mut rest = data.as_str();
+
+        while let Some(start) = rest.find("\x1b]") {
+            let seq = &rest[start..];
+            rest = &rest[start + 2..];
+
+            if let Some(text) = rest.strip_prefix("0;").or_else(|| rest.strip_prefix("2;")) {
+                match text.find(['\x07', '\x1b']) {
+                    Some(end) => title = Some(text[..end].to_owned()),
+
+                    None => {
+                        self.pending = seq.to_owned();
+                        break;
+                    }
+                }
+            }
+        }
+
+        if self.pending.is_empty() {
+            if let Some(prefix) = ["\x1b]0", "\x1b]2", "\x1b]", "\x1b"]
+                .into_iter()
+                .find(|prefix| data.ends_with(prefix))
+            {
+                self.pending = prefix.to_owned();
+            }
+        }
+
+        if self.pending.len() > MAX_PENDING_TITLE {
+            self.pending.clear();
+        }
+
+        title
+    }
+}
+
+/// Returns the rightmost column (1-based) addressed by absolute cursor
+/// positioning (CUP, HVP, CHA, HPA). Programs don't address columns outside
+/// of the terminal, so a value larger than the terminal width means the
+/// recording header doesn't match the content.
+pub fn addressed_cols(stdout: impl Iterator<Item = (f64, String)>) -> Option<usize> {
+    let mut max = None;
+
+    for (_, data) in stdout {
+        let mut rest = data.as_str();
+
+        while let Some(i) = rest.find("\x1b[") {
+            rest = &rest[i + 2..];
+            let len = rest
+                .bytes()
+                .take_while(|b| (b'0'..=b'?').contains(b))
+                .count();
+            let (params, tail) = rest.split_at(len);
+
+            let col = match tail.chars().next() {
+                Some('H' | 'f') => params.split(';').nth(1),
+                Some('G' | '`') => params.split(';').next(),
+                _ => None,
+            };
+
+            if let Some(col) = col.and_then(|c| c.parse::<usize>().ok()) {
+                max = max.max(Some(col));
+            }
+
+            rest = tail;
+        }
+    }
+
+    max
+}
+
+/// Returns (col, row, cols, rows) of the smallest area containing all
+/// non-blank cells across all frames.
+pub fn content_box(
+    stdout: impl Iterator<Item = (f64, String)>,
+    terminal_size: (usize, usize),
+) -> Option<(usize, usize, usize, usize)> {
+    let mut bbox: Option<(usize, usize, usize, usize)> = None;
+
+    let mut extend = |x: usize, y: usize| {
+        bbox = Some(match bbox {
+            Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+            None => (x, y, x, y),
+        });
+    };
+
+    // the cursor isn't content: a cursor on blank cells doesn't widen the box
+    for Frame { lines, .. } in frames(stdout, terminal_size, Clusters::default()) {
+        for (y, line) in lines.iter().enumerate() {
+            for (x, (ch, pen)) in line.iter().enumerate() {
+                if *ch != ' ' || pen.background().is_some() || pen.is_inverse() {
+                    extend(x, y);
+                }
+            }
+        }
+    }
+
+    bbox.map(|(x0, y0, x1, y1)| (x0, y0, x1 - x0 + 1, y1 - y0 + 1))
+}
+
+/// Number of cells that differ from the previous frame, or for the first
+/// frame the number of non-blank ones. A moved cursor counts as two cells.
+pub fn changed_cells(prev: Option<&Frame>, frame: &Frame) -> usize {
+    let Some(prev) = prev else {
+        return frame
+            .lines
+            .iter()
+            .flatten()
+            .filter(|(ch, pen)| *ch != ' ' || pen.background().is_some() || pen.is_inverse())
+            .count();
+    };
+
+    let cells = frame
+        .lines
+        .iter()
+        .enumerate()
+        .map(|(row, line)| match prev.lines.get(row) {
+            Some(prev_line) => line
+                .iter()
+                .enumerate()
+                .filter(|(col, cell)| prev_line.get(*col) != Some(cell))
+                .count(),
+
+            None => line.len(),
+        })
+        .sum::<usize>();
+
+    if prev.cursor != frame.cursor {
+        cells + 2
+    } else {
+        cells
+    }
+}
+
+/// Reorders right-to-left runs of each line for display, moving the cursor
+/// with the cell it's on. `clusters` are the ones the frames were emulated
+/// with.
+pub fn bidi(
+    frames: impl Iterator<Item = Frame>,
+    clusters: Clusters,
+) -> impl Iterator<Item = Frame> {
+    frames.map(move |frame| {
+        let cursor = frame
+            .cursor
+            .map(|(col, row)| (bidi::visual_col(&frame.lines[row], col, &clusters), row));
+
+        let lines = frame
+            .lines
+            .into_iter()
+            .map(|line| bidi::reorder(line, &clusters))
+            .collect();
+
+        Frame {
+            lines,
+            cursor,
+            ..frame
+        }
+    })
+}
+
+pub fn crop(
+    frames: impl Iterator<Item = Frame>,
+    (x, y, cols, rows): (usize, usize, usize, usize),
+) -> impl Iterator<Item = Frame> {
+    frames.map(move |frame| {
+        let lines = frame
+            .lines
+            .into_iter()
+            .skip(y)
+            .take(rows)
+            .map(|line| line.into_iter().skip(x).take(cols).collect())
+            .collect();
+
+        let cursor = frame.cursor.and_then(|(cx, cy)| {
+            if cx >= x && cx < x + cols && cy >= y && cy < y + rows {
+                Some((cx - x, cy - y))
+            } else {
+                None
+            }
+        });
+
+        Frame {
+            lines,
+            cursor,
+            ..frame
+        }
+    })
+}
+
+/// Keeps frames with index in `start..end`, shifting their time so the first
+/// kept frame starts at 0. Skipped frames still go through the terminal
+/// emulator, so the kept ones show the correct screen state.
+pub fn select(
+    frames: impl Iterator<Item = Frame>,
+    (start, end): (usize, usize),
+) -> impl Iterator<Item = Frame> {
+    let mut offset = None;
+
+    frames
+        .skip(start)
+        .take(end.saturating_sub(start))
+        .map(move |frame| {
+            let offset = *offset.get_or_insert(frame.time);
+
+            Frame {
+                time: frame.time - offset,
+                ..frame
+            }
+        })
+}
+
+/// Returns the frame showing the screen at the target: the one with the given
+/// index, or the last one shown at or before the given time. Frames past the
+/// target (the first one after it, for a time) are never pulled from the
+/// iterator, so a lazily emulating one stops there.
+pub fn seek(mut frames: impl Iterator<Item = Frame>, target: Seek) -> Option<Frame> {
+    match target {
+        Seek::Frame(index) => frames.nth(index),
+
+        Seek::Time(time) => {
+            let mut found = frames.next()?;
+
+            for frame in frames {
+                if frame.time > time {
+                    break;
+                }
+
+                found = frame;
+            }
+
+            Some(found)
+        }
+    }
+}
+
+/// Repeats the last frame, without markers, `duration` seconds after a frame
+/// with markers unless the screen changes by then, so that marker captions
+/// go away on time rather than with the next change.
+pub fn expire_markers(
+    frames: impl Iterator<Item = Frame>,
+    duration: f64,
+) -> impl Iterator<Item = Frame> {
+    let mut frames = frames.peekable();
+    let mut last: Option<Frame> = None;
+    let mut end = None;
+
+    std::iter::from_fn(move || {
+        if let Some(time) = end {
+            if frames.peek().is_none_or(|next| next.time > time) {
+                end = None;
+
+                return last.take().map(|frame| Frame {
+                    time,
+                    markers: Vec::new(),
+                    ..frame
+                });
+            }
+        }
+
+        let frame = frames.next()?;
+
+        if !frame.markers.is_empty() {
+            end = Some(frame.time + duration);
+        }
+
+        last = end.map(|_| frame.clone());
+
+        Some(frame)
+    })
+}
+
+/// Drops frames identical to the previous one once `max` of them were shown in
+/// a row, so the last kept frame stays on screen until the next change.
+/// Frames carrying a marker are always kept.
+pub fn max_idle_frames(
+    frames: impl Iterator<Item = Frame>,
+    max: usize,
+) -> impl Iterator<Item = Frame> {
+    let mut prev_lines = Vec::new();
+    let mut prev_cursor = None;
+    let mut prev_cursor_shape = None;
+    let mut prev_title = None;
+    let mut run = 0;
+
+    frames.filter(move |frame| {
+        let same = run > 0
+            && frame.lines == prev_lines
+            && frame.cursor == prev_cursor
+            && frame.cursor_shape == prev_cursor_shape
+            && frame.title == prev_title;
+
+        if !same {
+            prev_lines = frame.lines.clone();
+            prev_cursor = frame.cursor;
+            prev_cursor_shape = frame.cursor_shape;
+            prev_title = frame.title.clone();
+            run = 0;
+        }
+
+        run += 1;
+
+        if run > max && frame.markers.is_empty() {
+            debug!("dropping idle frame at {}s", frame.time);
+
+            false
+        } else {
+            true
+        }
+    })
+}
+
+/// Reverses the order of frames, mirroring their times so each frame stays on
+/// screen as long as the gap preceding it in the original recording. Frames
+/// keep their (forward accumulated) screen contents.
+pub fn reverse(mut frames: Vec<Frame>) -> Vec<Frame> {
+    let end = frames.last().map_or(0.0, |frame| frame.time);
+    frames.reverse();
+
+    for frame in &mut frames {
+        frame.time = end - frame.time;
+    }
+
+    frames
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -41,11 +640,16 @@ mod tests {
             (3.0, "!".to_owned()),
         ];
 
-        let fs = super::frames(stdout.into_iter(), (4, 2)).collect::<Vec<_>>();
+        let fs = super::frames(stdout.into_iter(), (4, 2), Default::default()).collect::<Vec<_>>();
 
         assert_eq!(fs.len(), 3);
 
-        let (time, lines, cursor) = &fs[0];
+        let super::Frame {
+            time,
+            lines,
+            cursor,
+            ..
+        } = &fs[0];
 
         assert_eq!(*time, 0.0);
         assert_eq!(*cursor, Some((3, 0)));
@@ -58,7 +662,12 @@ mod tests {
         assert_eq!(lines[1][2].0, ' ');
         assert_eq!(lines[1][3].0, ' ');
 
-        let (time, lines, cursor) = &fs[1];
+        let super::Frame {
+            time,
+            lines,
+            cursor,
+            ..
+        } = &fs[1];
 
         assert_eq!(*time, 2.0);
         assert_eq!(*cursor, Some((2, 1)));
@@ -71,7 +680,12 @@ mod tests {
         assert_eq!(lines[1][2].0, ' ');
         assert_eq!(lines[1][3].0, ' ');
 
-        let (time, lines, cursor) = &fs[2];
+        let super::Frame {
+            time,
+            lines,
+            cursor,
+            ..
+        } = &fs[2];
 
         assert_eq!(*time, 3.0);
         assert_eq!(*cursor, Some((3, 1)));
@@ -84,4 +698,722 @@ mod tests {
         assert_eq!(lines[1][2].0, '!');
         assert_eq!(lines[1][3].0, ' ');
     }
+
+    #[test]
+    fn frames_with_rep() {
+        let stdout = [
+            (0.0, "a\x1b[3b".to_owned()),
+            (1.0, "\r\nb\x1b[100b".to_owned()),
+            (2.0, "\r\n\x1b[5b".to_owned()),
+        ];
+
+        let fs = super::frames(stdout.into_iter(), (6, 3), Default::default()).collect::<Vec<_>>();
+
+        for super::Frame { lines, .. } in &fs {
+            assert_eq!(lines.len(), 3);
+
+            for line in lines {
+                assert_eq!(line.len(), 6);
+            }
+        }
+
+        let text = fs[0].lines[0].iter().map(|(ch, _)| ch).collect::<String>();
+
+        assert_eq!(text, "aaaa  ");
+    }
+
+    #[test]
+    fn frames_with_ignorable_controls() {
+        let stdout = [
+            (0.0, "ab\x07c".to_owned()),
+            (1.0, "\x1b[6n\x1b[5n\x1b[c\x1b[>cd".to_owned()),
+        ];
+
+        let fs = super::frames(stdout.into_iter(), (6, 2), Default::default()).collect::<Vec<_>>();
+        let super::Frame { lines, cursor, .. } = &fs[fs.len() - 1];
+        let text = lines[0].iter().map(|(ch, _)| ch).collect::<String>();
+
+        assert_eq!(text, "abcd  ");
+        assert_eq!(*cursor, Some((4, 0)));
+        assert!(lines[1].iter().all(|(ch, _)| *ch == ' '));
+    }
+
+    #[test]
+    fn frames_with_window_title() {
+        let stdout = [
+            (0.0, "\x1b]0;vim\x07foo".to_owned()),
+            (1.0, "\x1b]2;~/src\x1b\\".to_owned()),
+            (2.0, "\x1b]1;icon\x07bar".to_owned()),
+            (3.0, "\x1b]2;\x07".to_owned()),
+            // split across events
+            (4.0, "\x1b]2;ma".to_owned()),
+            (5.0, "ke\x07".to_owned()),
+            (6.0, "x\x1b]".to_owned()),
+            (7.0, "0;top\x1b\\".to_owned()),
+        ];
+
+        let titles = super::frames(stdout.into_iter(), (8, 2), Default::default())
+            .map(|f| f.title)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            titles,
+            [
+                Some("vim".to_owned()),
+                Some("~/src".to_owned()),
+                Some("~/src".to_owned()),
+                None,
+                Some("make".to_owned()),
+                Some("make".to_owned()),
+                Some("top".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn frames_with_stray_controls() {
+        let stdout = [
+            (0.0, "a\x7fb\x08\x08cd\x00e".to_owned()),
+            (1.0, "\r\nf\u{85}g\u{9f}h\u{80}i\x0e\x0fj".to_owned()),
+        ];
+
+        for frame in super::frames(stdout.into_iter(), (10, 3), Default::default()) {
+            for line in &frame.lines {
+                assert!(line.iter().all(|(ch, _)| !ch.is_control()));
+            }
+        }
+
+        assert_eq!(super::printable(('\u{9b}', avt::Pen::default())).0, ' ');
+    }
+
+    #[test]
+    fn frames_with_clear_scrollback() {
+        let stdout = [
+            (0.0, "a\r\nb\r\nc\r\nd".to_owned()),
+            (1.0, "\x1b[3J".to_owned()),
+            (2.0, "\x1b[H\x1b[2J\x1b[3Jfoo".to_owned()),
+        ];
+
+        let text = |frame: &super::Frame| {
+            frame
+                .lines
+                .iter()
+                .map(|line| line.iter().map(|(ch, _)| ch).collect::<String>())
+                .collect::<Vec<_>>()
+        };
+
+        let fs = super::frames(stdout.into_iter(), (4, 3), Default::default()).collect::<Vec<_>>();
+
+        // clearing scrollback alone leaves the visible screen as it was
+        assert_eq!(text(&fs[0]), ["b   ", "c   ", "d   "]);
+        assert!(fs
+            .iter()
+            .filter(|f| f.time == 1.0)
+            .all(|f| text(f) == text(&fs[0])));
+
+        // as emitted by clear(1)
+        let last = &fs[fs.len() - 1];
+
+        assert_eq!(last.time, 2.0);
+        assert_eq!(text(last), ["foo ", "    ", "    "]);
+        assert_eq!(last.cursor, Some((3, 0)));
+    }
+
+    #[test]
+    fn frames_with_osc8_hyperlinks() {
+        let stdout = [
+            (
+                0.0,
+                "\x1b]8;;file:///tmp/foo\x1b\\foo\x1b]8;;\x1b\\ ".to_owned(),
+            ),
+            (1.0, "\x1b]8;id=1;https://x.y\x07bar\x1b]8;;\x07".to_owned()),
+        ];
+
+        let fs = super::frames(stdout.into_iter(), (8, 1), Default::default()).collect::<Vec<_>>();
+        let super::Frame { lines, .. } = &fs[fs.len() - 1];
+        let text = lines[0].iter().map(|(ch, _)| ch).collect::<String>();
+
+        assert_eq!(text, "foo bar ");
+    }
+
+    #[test]
+    fn frames_with_tabs() {
+        let stdout = [(0.0, "a\tbb\tccc\r\n\tx\t\ty".to_owned())];
+
+        let fs = super::frames(stdout.into_iter(), (30, 2), Default::default()).collect::<Vec<_>>();
+        let super::Frame { lines, cursor, .. } = &fs[0];
+
+        assert_eq!(lines[0].len(), 30);
+        assert_eq!(lines[0][0].0, 'a');
+        assert_eq!(lines[0][8].0, 'b');
+        assert_eq!(lines[0][9].0, 'b');
+        assert_eq!(lines[0][16].0, 'c');
+        assert!(lines[0][1..8].iter().all(|(ch, _)| *ch == ' '));
+        assert!(lines[0][10..16].iter().all(|(ch, _)| *ch == ' '));
+
+        assert_eq!(lines[1][8].0, 'x');
+        assert_eq!(lines[1][24].0, 'y');
+        assert_eq!(*cursor, Some((25, 1)));
+    }
+
+    #[test]
+    fn changed_cells() {
+        let stdout = [
+            (0.0, "foo\x1b[41m \x1b[0m".to_owned()),
+            (1.0, "\x1b[1;2Hxx".to_owned()),
+            (2.0, "\x1b[2;1H".to_owned()),
+        ];
+
+        let fs = super::frames(stdout.into_iter(), (10, 3), Default::default()).collect::<Vec<_>>();
+
+        // "foo" and a red cell, "xx" replacing "oo" with the cursor moving
+        // back, then only the cursor moves
+        assert_eq!(super::changed_cells(None, &fs[0]), 4);
+        assert_eq!(super::changed_cells(Some(&fs[0]), &fs[1]), 4);
+        assert_eq!(super::changed_cells(Some(&fs[1]), &fs[2]), 2);
+        assert_eq!(super::changed_cells(Some(&fs[2]), &fs[2]), 0);
+    }
+
+    #[test]
+    fn content_box() {
+        let stdout = [
+            (0.0, "\x1b[2;3Hfoo".to_owned()),
+            (1.0, "\x1b[4;2Hx\x1b[1;1H".to_owned()),
+        ];
+
+        let bbox = super::content_box(stdout.clone().into_iter(), (10, 6));
+
+        // cursor at the end of "foo" and at the home position is left out
+        assert_eq!(bbox, Some((1, 1, 4, 3)));
+
+        let fs = super::frames(stdout.into_iter(), (10, 6), Default::default());
+        let fs = super::crop(fs, (1, 1, 5, 3)).collect::<Vec<_>>();
+        let super::Frame { lines, cursor, .. } = &fs[0];
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].len(), 5);
+        assert_eq!(lines[0][1].0, 'f');
+        assert_eq!(*cursor, Some((4, 0)));
+
+        let super::Frame { lines, cursor, .. } = &fs[1];
+
+        assert_eq!(lines[2][0].0, 'x');
+        assert_eq!(*cursor, None);
+
+        let on_content = [(0.0, "\x1b[2;3Hfoo\x1b[2;4H".to_owned())];
+
+        assert_eq!(
+            super::content_box(on_content.into_iter(), (10, 6)),
+            Some((2, 1, 3, 1))
+        );
+    }
+
+    #[test]
+    fn frames_with_cursor_shape() {
+        use crate::CursorShape;
+
+        let stdout = [
+            (0.0, "foo".to_owned()),
+            (1.0, "\x1b[6 q".to_owned()),
+            (2.0, "\x1b[2 q\x1b[4 q".to_owned()),
+            (3.0, "\x1b[0 q".to_owned()),
+        ];
+
+        let fs = super::frames(stdout.into_iter(), (4, 2), Default::default()).collect::<Vec<_>>();
+
+        assert_eq!(fs.len(), 4);
+        assert_eq!(fs[0].cursor_shape, None);
+        assert_eq!(fs[1].cursor_shape, Some(CursorShape::Bar));
+        assert_eq!(fs[2].cursor_shape, Some(CursorShape::Underline));
+        assert_eq!(fs[3].cursor_shape, None);
+    }
+
+    #[test]
+    fn frames_with_grapheme_clusters() {
+        let stdout = [(0.0, "a\u{1f1fa}\u{1f1f8}b".to_owned())];
+        let clusters = crate::graphemes::Clusters::default();
+
+        let fs = super::frames(stdout.into_iter(), (5, 1), clusters.clone()).collect::<Vec<_>>();
+        let super::Frame { lines, cursor, .. } = &fs[0];
+
+        // the flag takes two cells, like in a terminal
+        assert_eq!(lines[0][0].0, 'a');
+        assert_eq!(lines[0][2].0, crate::graphemes::SPACER);
+        assert_eq!(lines[0][3].0, 'b');
+        assert_eq!(*cursor, Some((4, 0)));
+
+        assert_eq!(
+            clusters.resolve(lines[0][1].0).as_deref(),
+            Some("\u{1f1fa}\u{1f1f8}")
+        );
+
+        // woman technologist: woman, ZWJ, laptop
+        let stdout = [(0.0, "a\u{1f469}\u{200d}\u{1f4bb}b".to_owned())];
+        let fs = super::frames(stdout.into_iter(), (5, 1), clusters.clone()).collect::<Vec<_>>();
+        let super::Frame { lines, cursor, .. } = &fs[0];
+
+        assert_eq!(lines[0][2].0, crate::graphemes::SPACER);
+        assert_eq!(lines[0][3].0, 'b');
+        assert_eq!(*cursor, Some((4, 0)));
+
+        assert_eq!(
+            clusters.resolve(lines[0][1].0).as_deref(),
+            Some("\u{1f469}\u{200d}\u{1f4bb}")
+        );
+    }
+
+    #[test]
+    fn frames_wider_than_terminal() {
+        let stdout = [
+            (0.0, "0123456789".to_owned()),
+            (1.0, "\x1b[1;12Hx\x1b[15Gy".to_owned()),
+        ];
+
+        assert_eq!(super::addressed_cols(stdout.clone().into_iter()), Some(15));
+
+        let fs = super::frames(stdout.into_iter(), (4, 3), Default::default()).collect::<Vec<_>>();
+
+        for super::Frame { lines, cursor, .. } in &fs {
+            assert!(lines.iter().all(|line| line.len() == 4));
+            assert!(cursor.is_none_or(|(col, row)| col <= 4 && row < 3));
+        }
+
+        assert_eq!(
+            super::addressed_cols([(0.0, "\x1b[?25l\x1b[5;3H".to_owned())].into_iter()),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn frames_with_scroll_region() {
+        let stdout = [
+            (0.0, "HEADER\x1b[5;1HFOOTER\x1b[2;4r\x1b[4;1H".to_owned()),
+            (1.0, "a\r\nb\r\nc\r\nd".to_owned()),
+        ];
+
+        let fs = super::frames(stdout.into_iter(), (8, 5), Default::default()).collect::<Vec<_>>();
+        let super::Frame { lines, cursor, .. } = &fs[fs.len() - 1];
+
+        let text = lines
+            .iter()
+            .map(|line| line.iter().map(|(ch, _)| ch).collect::<String>())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            text,
+            vec!["HEADER  ", "b       ", "c       ", "d       ", "FOOTER  "]
+        );
+
+        assert_eq!(*cursor, Some((1, 3)));
+    }
+
+    #[test]
+    fn frames_with_sixel_images() {
+        let stdout = [
+            (
+                0.0,
+                "img:\x1bPq\"1;1;4;4#0;2;0;0;0#1;2;100;100;0".to_owned(),
+            ),
+            (0.5, "#1~~@@vv@@~~$-".to_owned()),
+            (1.0, "\x1b\\ok\x1b_Gf=100;iVBORw0KGgo=\x1b\\!".to_owned()),
+        ];
+
+        let fs = super::frames(stdout.into_iter(), (8, 2), Default::default()).collect::<Vec<_>>();
+        let super::Frame { lines, .. } = &fs[fs.len() - 1];
+        let text = lines[0].iter().map(|(ch, _)| ch).collect::<String>();
+
+        assert_eq!(text, "img:ok! ");
+    }
+
+    #[test]
+    fn bidi() {
+        let stdout = [(0.0, "ls שלום.txt\r\nא 12 ב\r\nא(ב)ג".to_owned())];
+
+        let text = |frame: &super::Frame| {
+            frame
+                .lines
+                .iter()
+                .map(|line| line.iter().map(|(ch, _)| ch).collect::<String>())
+                .collect::<Vec<_>>()
+        };
+
+        // logical order, as the terminal emulator stores it
+        let fs = super::frames(stdout.clone().into_iter(), (12, 3), Default::default())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            text(&fs[0]),
+            vec!["ls שלום.txt ", "א 12 ב      ", "א(ב)ג       "]
+        );
+
+        let fs = super::bidi(
+            super::frames(stdout.into_iter(), (12, 3), Default::default()),
+            Default::default(),
+        )
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            text(&fs[0]),
+            vec!["ls םולש.txt ", "ב 12 א      ", "ג(ב)א       "]
+        );
+
+        // on the logically first "א" in "א(ב)ג"
+        let stdout = [(0.0, "א(ב)ג\x1b[5D".to_owned())];
+        let fs = super::bidi(
+            super::frames(stdout.into_iter(), (12, 1), Default::default()),
+            Default::default(),
+        )
+        .collect::<Vec<_>>();
+
+        assert_eq!(fs[0].cursor, Some((4, 0)));
+        assert_eq!(fs[0].lines[0][4].0, 'א');
+
+        // past the end of the line, unaffected
+        let stdout = [(0.0, "ls שלום".to_owned())];
+        let fs = super::bidi(
+            super::frames(stdout.into_iter(), (12, 1), Default::default()),
+            Default::default(),
+        )
+        .collect::<Vec<_>>();
+
+        assert_eq!(fs[0].cursor, Some((7, 0)));
+    }
+
+    #[test]
+    fn frames_with_markers() {
+        let stdout = [
+            (0.0, "foo".to_owned()),
+            (1.0, "\x1b]agg-marker;Step 1\x07".to_owned()),
+            (2.0, "bar".to_owned()),
+        ];
+
+        let fs = super::frames(stdout.into_iter(), (8, 2), Default::default()).collect::<Vec<_>>();
+
+        assert_eq!(fs.len(), 3);
+        assert!(fs[0].markers.is_empty());
+        assert_eq!(fs[1].markers, vec!["Step 1"]);
+        assert_eq!(fs[1].lines[0][0].0, 'f');
+        assert!(fs[2].markers.is_empty());
+
+        // several markers in one event
+        let stdout = [(
+            1.0,
+            "\x1b]agg-marker;A\x07x\x1b]agg-marker;B\x07".to_owned(),
+        )];
+        let fs = super::frames(stdout.into_iter(), (8, 2), Default::default()).collect::<Vec<_>>();
+
+        assert_eq!(fs[0].markers, vec!["A", "B"]);
+    }
+
+    #[test]
+    fn expire_markers() {
+        let stdout = [
+            (1.0, "\x1b]agg-marker;A\x07".to_owned()),
+            (2.0, "foo".to_owned()),
+            (5.0, "\x1b]agg-marker;B\x07".to_owned()),
+            (6.0, "bar".to_owned()),
+        ];
+
+        let fs = super::frames(stdout.into_iter(), (8, 2), Default::default());
+        let fs = super::expire_markers(fs, 2.0).collect::<Vec<_>>();
+        let times = fs.iter().map(|frame| frame.time).collect::<Vec<_>>();
+
+        // "foo" repeated at 3s, but not before "bar" which comes in time,
+        // and "bar" at 7s at the end
+        assert_eq!(times, vec![1.0, 2.0, 3.0, 5.0, 6.0, 7.0]);
+        assert_eq!(fs[2].lines, fs[1].lines);
+        assert!(fs[2].markers.is_empty());
+        assert_eq!(fs[5].lines, fs[4].lines);
+    }
+
+    #[test]
+    fn frames_with_cr_progress_bar() {
+        let mut stdout = Vec::new();
+
+        for i in 0..=100 {
+            let bar = format!("[{:<20}] {i:>3}%", "#".repeat(i / 5));
+            let (head, tail) = bar.split_at(1 + i / 10);
+
+            // redraws split across events, as written by unbuffered programs
+            stdout.push((i as f64 * 0.01, format!("\r{head}")));
+            stdout.push((i as f64 * 0.01 + 0.005, tail.to_owned()));
+        }
+
+        stdout.push((1.5, "\r\n$ ".to_owned()));
+
+        let stdout = crate::events::batch(stdout.into_iter(), 30);
+        let fs = super::frames(stdout, (30, 3), Default::default()).collect::<Vec<_>>();
+
+        assert!(fs.len() < 100);
+
+        let super::Frame { lines, cursor, .. } = &fs[fs.len() - 1];
+
+        let text = lines
+            .iter()
+            .map(|line| line.iter().map(|(ch, _)| ch).collect::<String>())
+            .collect::<Vec<_>>();
+
+        assert_eq!(text[0], "[####################] 100%   ");
+        assert_eq!(text[1], "$                             ");
+        assert_eq!(*cursor, Some((2, 1)));
+    }
+
+    #[test]
+    fn frames_with_completion_menu() {
+        let stdout = [
+            (0.0, "$ git ch".to_owned()),
+            // menu drawn below the prompt, cursor saved and restored around it
+            (
+                0.5,
+                "\x1b[s\x1b[2;3H\x1b[44m checkout    \x1b[3;3H cherry-pick \x1b[0m\x1b[u"
+                    .to_owned(),
+            ),
+            (
+                1.0,
+                "\x1b[s\x1b[2;3H\x1b[30;47m checkout    \x1b[0m\x1b[u".to_owned(),
+            ),
+            (1.5, "\x1b[s\x1b[2;1H\x1b[J\x1b[ueckout ".to_owned()),
+        ];
+
+        let fs = super::frames(stdout.into_iter(), (20, 4), Default::default()).collect::<Vec<_>>();
+
+        assert_eq!(fs.len(), 4);
+
+        let grid = |frame: &super::Frame| {
+            frame
+                .lines
+                .iter()
+                .map(|line| {
+                    let text = line.iter().map(|(ch, _)| ch).collect::<String>();
+
+                    let background = line
+                        .iter()
+                        .map(|(_, pen)| match pen.background() {
+                            None => '.',
+                            Some(avt::Color::Indexed(n)) => char::from_digit(n as u32, 16).unwrap(),
+                            Some(avt::Color::RGB(_)) => '?',
+                        })
+                        .collect::<String>();
+
+                    (text, background)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let blank = || {
+            (
+                "                    ".to_owned(),
+                "....................".to_owned(),
+            )
+        };
+        let row = |text: &str, background: &str| (text.to_owned(), background.to_owned());
+
+        assert_eq!(
+            grid(&fs[1]),
+            vec![
+                row("$ git ch            ", "...................."),
+                row("   checkout         ", "..4444444444444....."),
+                row("   cherry-pick      ", "..4444444444444....."),
+                blank(),
+            ]
+        );
+
+        assert_eq!(fs[1].cursor, Some((8, 0)));
+
+        assert_eq!(
+            grid(&fs[2]),
+            vec![
+                row("$ git ch            ", "...................."),
+                row("   checkout         ", "..7777777777777....."),
+                row("   cherry-pick      ", "..4444444444444....."),
+                blank(),
+            ]
+        );
+
+        assert!(matches!(
+            fs[2].lines[1][3].1.foreground(),
+            Some(avt::Color::Indexed(0))
+        ));
+        assert_eq!(fs[2].cursor, Some((8, 0)));
+
+        assert_eq!(
+            grid(&fs[3]),
+            vec![
+                row("$ git checkout      ", "...................."),
+                blank(),
+                blank(),
+                blank(),
+            ]
+        );
+
+        assert_eq!(fs[3].cursor, Some((15, 0)));
+    }
+
+    #[test]
+    fn sgr_underline() {
+        use crate::UnderlineStyle::*;
+
+        assert_eq!(
+            super::sgr_underline("4:3"),
+            ("4".to_owned(), Some(Some(Curly)))
+        );
+        assert_eq!(
+            super::sgr_underline("1;4:2;31"),
+            ("1;4;31".to_owned(), Some(Some(Double)))
+        );
+        assert_eq!(super::sgr_underline("4:0"), ("24".to_owned(), Some(None)));
+        assert_eq!(super::sgr_underline("4"), ("4".to_owned(), Some(None)));
+        assert_eq!(super::sgr_underline(""), ("".to_owned(), Some(None)));
+        assert_eq!(super::sgr_underline("1;31"), ("1;31".to_owned(), None));
+        assert_eq!(super::sgr_underline("38;5;4"), ("38;5;4".to_owned(), None));
+        assert_eq!(
+            super::sgr_underline("48;2;0;4;0;4:4"),
+            ("48;2;0;4;0;4".to_owned(), Some(Some(Dotted)))
+        );
+    }
+
+    #[test]
+    fn frames_with_underline_styles() {
+        use crate::UnderlineStyle::*;
+
+        // styles mixed on one line, with sequences split across events
+        let stdout = [
+            (0.0, "\x1b[4:3ma\x1b[4".to_owned()),
+            (1.0, ":2mb\x1b[1mc\x1b".to_owned()),
+            (2.0, "[4md\x1b[0me\x1b[4:4mf\x1b[24mg".to_owned()),
+        ];
+
+        let clusters = crate::graphemes::Clusters::default();
+        let frame = super::frames(stdout.into_iter(), (8, 1), clusters.clone())
+            .last()
+            .unwrap();
+
+        let cells = frame.lines[0]
+            .iter()
+            .map(|(ch, pen)| {
+                let text = clusters.resolve(*ch).unwrap_or_else(|| ch.to_string());
+
+                (text, pen.is_underline(), clusters.underline_style(*ch))
+            })
+            .collect::<Vec<_>>();
+
+        let expected = [
+            ("a", true, Some(Curly)),
+            ("b", true, Some(Double)),
+            ("c", true, Some(Double)),
+            ("d", true, None),
+            ("e", false, None),
+            ("f", true, Some(Dotted)),
+            ("g", false, None),
+            (" ", false, None),
+        ]
+        .map(|(text, underline, style)| (text.to_owned(), underline, style));
+
+        assert_eq!(cells, expected);
+    }
+
+    #[test]
+    fn max_idle_frames() {
+        let frame = |time, text: &str| super::Frame {
+            time,
+            lines: vec![text.chars().map(|ch| (ch, avt::Pen::default())).collect()],
+            cursor: None,
+            cursor_shape: None,
+            markers: Vec::new(),
+            title: None,
+        };
+
+        let frames = vec![
+            frame(0.0, "a"),
+            frame(1.0, "b"),
+            frame(2.0, "b"),
+            frame(3.0, "b"),
+            frame(4.0, "b"),
+            frame(5.0, "c"),
+            frame(6.0, "c"),
+        ];
+
+        let times = super::max_idle_frames(frames.into_iter(), 2)
+            .map(|f| f.time)
+            .collect::<Vec<_>>();
+
+        assert_eq!(times, vec![0.0, 1.0, 2.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn seek() {
+        use crate::Seek;
+
+        let stdout = || {
+            [
+                (0.0, "a".to_owned()),
+                (1.0, "\x1b[31mb".to_owned()),
+                (2.5, "\x1b[2Dc".to_owned()),
+            ]
+            .into_iter()
+        };
+
+        let seek = |target| {
+            super::seek(super::frames(stdout(), (4, 1), Default::default()), target).unwrap()
+        };
+        let text =
+            |frame: super::Frame| frame.lines[0].iter().map(|(ch, _)| ch).collect::<String>();
+
+        assert_eq!(text(seek(Seek::Frame(0))), "a   ");
+        assert_eq!(text(seek(Seek::Frame(1))), "ab  ");
+        assert_eq!(text(seek(Seek::Frame(2))), "cb  ");
+        assert!(super::seek(
+            super::frames(stdout(), (4, 1), Default::default()),
+            Seek::Frame(3)
+        )
+        .is_none());
+
+        assert_eq!(text(seek(Seek::Time(0.5))), "a   ");
+        assert_eq!(text(seek(Seek::Time(1.0))), "ab  ");
+        assert_eq!(text(seek(Seek::Time(2.0))), "ab  ");
+        assert_eq!(text(seek(Seek::Time(10.0))), "cb  ");
+
+        let frame = seek(Seek::Time(2.0));
+        assert!(frame.lines[0][1].1.foreground().is_some());
+        assert_eq!(frame.cursor, Some((2, 0)));
+    }
+
+    #[test]
+    fn reverse() {
+        let stdout = [
+            (0.0, "a".to_owned()),
+            (1.0, "b".to_owned()),
+            (2.5, "c".to_owned()),
+        ];
+
+        let fs =
+            super::reverse(super::frames(stdout.into_iter(), (8, 2), Default::default()).collect());
+        let times = fs.iter().map(|f| f.time).collect::<Vec<_>>();
+
+        assert_eq!(times, vec![0.0, 1.5, 2.5]);
+        assert_eq!(fs[0].lines[0][2].0, 'c');
+        assert_eq!(fs[2].lines[0][1].0, ' ');
+    }
+
+    #[test]
+    fn select() {
+        let stdout = [
+            (0.0, "a".to_owned()),
+            (1.0, "b".to_owned()),
+            (2.5, "c".to_owned()),
+            (4.0, "d".to_owned()),
+        ];
+
+        let fs = super::select(
+            super::frames(stdout.into_iter(), (8, 2), Default::default()),
+            (1, 3),
+        )
+        .collect::<Vec<_>>();
+
+        assert_eq!(fs.len(), 2);
+        assert_eq!(fs[0].time, 0.0);
+        assert_eq!(fs[1].time, 1.5);
+        assert_eq!(fs[1].lines[0][0].0, 'a');
+        assert_eq!(fs[1].lines[0][2].0, 'c');
+    }
 }